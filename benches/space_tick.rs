@@ -0,0 +1,24 @@
+// cargo bench --features bench --bench space_tick [-- <entity count>]
+// 需要 GPU，会开一个不可见的窗口
+
+use hoo_engine::benchmark_space_tick;
+
+fn main() {
+    // cargo bench 会传入 --bench
+    let entity_count = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .and_then(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(100_000);
+
+    let timings = benchmark_space_tick(entity_count, 60);
+    println!(
+        "setup of {} entities: {:?}",
+        timings.entity_count, timings.setup
+    );
+    println!(
+        "tick: {:?} per frame (average of {})",
+        timings.tick, timings.frames
+    );
+    println!("render: {:?} per frame", timings.render);
+}
@@ -0,0 +1,161 @@
+// #[js(...)] 属性，可以用在 struct、字段、js_impl 里的函数上，
+// 以及 #[js_impl(...)] 本身
+
+#[derive(Default)]
+pub(crate) struct FJsAttributes {
+    pub skip: bool,
+    pub readonly: bool,
+    // tuple struct 转成 { 0: .., 1: .. } 而不是数组
+    pub as_object: bool,
+    pub rename: Option<String>,
+    pub rename_all: Option<String>,
+    // Some(None) 表示属性名由函数名推出
+    pub getter: Option<Option<String>>,
+    pub setter: Option<Option<String>>,
+    // 只能用在参数上
+    pub default: Option<syn::Expr>,
+    // 泛型函数的实例：instantiate(lerp_f32 = <f32>, lerp_f64 = <f64>)
+    pub instantiate: Vec<(syn::Ident, Vec<syn::Type>)>,
+}
+
+impl FJsAttributes {
+    pub fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut attributes = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| attributes.parse_meta(meta))?;
+        }
+        Ok(attributes)
+    }
+
+    pub fn parse_meta(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("skip") {
+            self.skip = true;
+        } else if meta.path.is_ident("readonly") {
+            self.readonly = true;
+        } else if meta.path.is_ident("as_object") {
+            self.as_object = true;
+        } else if meta.path.is_ident("rename") {
+            self.rename = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+        } else if meta.path.is_ident("rename_all") {
+            let rule = meta.value()?.parse::<syn::LitStr>()?.value();
+            if !RENAME_RULES.contains(&rule.as_str()) {
+                return Err(meta.error(format!(
+                    "unknown rename_all rule, expected one of: {}",
+                    RENAME_RULES.join(", ")
+                )));
+            }
+            self.rename_all = Some(rule);
+        } else if meta.path.is_ident("default") {
+            self.default = Some(meta.value()?.parse::<syn::Expr>()?);
+        } else if meta.path.is_ident("getter") {
+            self.getter = Some(Self::parse_optional_name(&meta)?);
+        } else if meta.path.is_ident("setter") {
+            self.setter = Some(Self::parse_optional_name(&meta)?);
+        } else if meta.path.is_ident("instantiate") {
+            meta.parse_nested_meta(|instance| {
+                let name = instance
+                    .path
+                    .get_ident()
+                    .cloned()
+                    .ok_or_else(|| instance.error("expected an instance name"))?;
+                let arguments = instance
+                    .value()?
+                    .parse::<syn::AngleBracketedGenericArguments>()?;
+                let types = arguments
+                    .args
+                    .iter()
+                    .map(|argument| match argument {
+                        syn::GenericArgument::Type(ty) => Ok(ty.clone()),
+                        _ => Err(syn::Error::new_spanned(
+                            argument,
+                            "only type arguments are supported in instantiate",
+                        )),
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
+                self.instantiate.push((name, types));
+                Ok(())
+            })?;
+        } else {
+            return Err(meta.error("unsupported js attribute"));
+        }
+        Ok(())
+    }
+
+    fn parse_optional_name(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<String>> {
+        if meta.input.peek(syn::Token![=]) {
+            Ok(Some(meta.value()?.parse::<syn::LitStr>()?.value()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // 参数上只能写 default
+    pub fn is_param_attributes(&self) -> bool {
+        !self.skip
+            && !self.readonly
+            && !self.as_object
+            && self.rename.is_none()
+            && self.rename_all.is_none()
+            && self.getter.is_none()
+            && self.setter.is_none()
+            && self.instantiate.is_empty()
+    }
+
+    // rename 优先，其次是外层的 rename_all
+    pub fn js_name(&self, rust_name: &str, rename_all: Option<&String>) -> String {
+        match (&self.rename, rename_all) {
+            (Some(rename), _) => rename.clone(),
+            (None, Some(rule)) => apply_rename_rule(rust_name, rule),
+            (None, None) => rust_name.to_string(),
+        }
+    }
+}
+
+pub(crate) fn strip_js_attributes(attrs: &mut Vec<syn::Attribute>) {
+    attrs.retain(|attr| !attr.path().is_ident("js"));
+}
+
+pub(crate) fn strip_js_param_attributes(signature: &mut syn::Signature) {
+    for input in signature.inputs.iter_mut() {
+        if let syn::FnArg::Typed(pat_type) = input {
+            strip_js_attributes(&mut pat_type.attrs);
+        }
+    }
+}
+
+const RENAME_RULES: [&str; 5] = [
+    "camelCase",
+    "PascalCase",
+    "snake_case",
+    "SCREAMING_SNAKE_CASE",
+    "kebab-case",
+];
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn apply_rename_rule(rust_name: &str, rule: &str) -> String {
+    let words: Vec<String> = rust_name
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    match rule {
+        "camelCase" => words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| if i == 0 { word.clone() } else { capitalize(word) })
+            .collect(),
+        "PascalCase" => words.iter().map(|word| capitalize(word)).collect(),
+        "snake_case" => words.join("_"),
+        "SCREAMING_SNAKE_CASE" => words.join("_").to_uppercase(),
+        "kebab-case" => words.join("-"),
+        _ => rust_name.to_string(),
+    }
+}
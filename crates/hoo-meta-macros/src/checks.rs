@@ -0,0 +1,147 @@
+// 编译期检查：绑定到 js 的签名里不支持的东西直接报错，并指到具体位置
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+
+// 在类型里找第一个生命周期（'a / 'static），返回它的位置
+fn find_lifetime(tokens: TokenStream) -> Option<proc_macro2::Span> {
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                if let Some(TokenTree::Ident(ident)) = iter.peek() {
+                    return Some(punct.span().join(ident.span()).unwrap_or(ident.span()));
+                }
+            }
+            TokenTree::Group(group) => {
+                if let Some(span) = find_lifetime(group.stream()) {
+                    return Some(span);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn find_impl_trait(ty: &syn::Type) -> Option<&syn::TypeImplTrait> {
+    match ty {
+        syn::Type::ImplTrait(impl_trait) => Some(impl_trait),
+        syn::Type::Reference(reference) => find_impl_trait(&reference.elem),
+        syn::Type::Paren(paren) => find_impl_trait(&paren.elem),
+        syn::Type::Group(group) => find_impl_trait(&group.elem),
+        syn::Type::Slice(slice) => find_impl_trait(&slice.elem),
+        syn::Type::Array(array) => find_impl_trait(&array.elem),
+        syn::Type::Tuple(tuple) => tuple.elems.iter().find_map(find_impl_trait),
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .iter()
+            .filter_map(|segment| match &segment.arguments {
+                syn::PathArguments::AngleBracketed(arguments) => Some(arguments),
+                _ => None,
+            })
+            .flat_map(|arguments| arguments.args.iter())
+            .find_map(|argument| match argument {
+                syn::GenericArgument::Type(ty) => find_impl_trait(ty),
+                _ => None,
+            }),
+        _ => None,
+    }
+}
+
+pub(crate) fn check_type(ty: &syn::Type) -> syn::Result<()> {
+    if let Some(impl_trait) = find_impl_trait(ty) {
+        return Err(syn::Error::new_spanned(
+            impl_trait,
+            "`impl Trait` is not supported in js bindings, use a concrete type",
+        ));
+    }
+    if let Some(span) = find_lifetime(ty.to_token_stream()) {
+        return Err(syn::Error::new(
+            span,
+            "lifetimes are not supported in js bindings",
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_generics(generics: &syn::Generics, what: &str) -> syn::Result<()> {
+    if let Some(lifetime) = generics.lifetimes().next() {
+        return Err(syn::Error::new_spanned(
+            lifetime,
+            "lifetimes are not supported in js bindings",
+        ));
+    }
+    if let Some(param) = generics.params.first() {
+        return Err(syn::Error::new_spanned(
+            param,
+            format!("generic parameters are not supported on {}", what),
+        ));
+    }
+    if let Some(where_clause) = &generics.where_clause {
+        return Err(syn::Error::new_spanned(
+            where_clause,
+            format!("where clauses are not supported on {}", what),
+        ));
+    }
+    Ok(())
+}
+
+// 绑定为 js 函数或方法的签名
+pub(crate) fn check_signature(signature: &syn::Signature) -> syn::Result<()> {
+    check_generics(&signature.generics, "js functions")?;
+
+    if let Some(asyncness) = &signature.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "async functions are not supported in js bindings",
+        ));
+    }
+    if let Some(variadic) = &signature.variadic {
+        return Err(syn::Error::new_spanned(
+            variadic,
+            "variadic functions are not supported in js bindings",
+        ));
+    }
+
+    let mut has_receiver = false;
+    for (i, arg) in signature.inputs.iter().enumerate() {
+        match arg {
+            syn::FnArg::Receiver(receiver) => {
+                if has_receiver {
+                    return Err(syn::Error::new_spanned(
+                        receiver,
+                        "js methods can only have one receiver",
+                    ));
+                }
+                if i != 0 {
+                    return Err(syn::Error::new_spanned(
+                        receiver,
+                        "the receiver must be the first argument",
+                    ));
+                }
+                has_receiver = true;
+
+                if receiver.reference.is_none() || receiver.colon_token.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        receiver,
+                        "js methods must take `&self` or `&mut self`",
+                    ));
+                }
+                if let Some((_, Some(lifetime))) = &receiver.reference {
+                    return Err(syn::Error::new_spanned(
+                        lifetime,
+                        "lifetimes are not supported in js bindings",
+                    ));
+                }
+            }
+            syn::FnArg::Typed(pat_type) => check_type(&pat_type.ty)?,
+        }
+    }
+
+    if let syn::ReturnType::Type(_, ty) = &signature.output {
+        check_type(ty)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// 泛型函数的实例化：把签名里的类型参数换成 instantiate 里给的具体类型
+
+use syn::visit_mut::VisitMut;
+
+struct FTypeSubstitution<'a> {
+    params: Vec<&'a syn::Ident>,
+    types: &'a [syn::Type],
+}
+
+impl VisitMut for FTypeSubstitution<'_> {
+    fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+        if let syn::Type::Path(type_path) = ty {
+            if type_path.qself.is_none() {
+                if let Some(ident) = type_path.path.get_ident() {
+                    if let Some(i) = self.params.iter().position(|param| *param == ident) {
+                        *ty = self.types[i].clone();
+                        return;
+                    }
+                }
+            }
+        }
+        syn::visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+// 返回去掉泛型参数后的签名，调用的地方用 turbofish 指定类型，约束由编译器检查
+pub(crate) fn instantiate_signature(
+    signature: &syn::Signature,
+    name: &syn::Ident,
+    types: &[syn::Type],
+) -> syn::Result<syn::Signature> {
+    let generics = &signature.generics;
+    if let Some(lifetime) = generics.lifetimes().next() {
+        return Err(syn::Error::new_spanned(
+            lifetime,
+            "lifetimes are not supported in js bindings",
+        ));
+    }
+    if let Some(param) = generics.const_params().next() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "const generic parameters can not be instantiated",
+        ));
+    }
+
+    let params: Vec<&syn::Ident> = generics.type_params().map(|param| &param.ident).collect();
+    if params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            name,
+            "instantiate requires a generic function",
+        ));
+    }
+    if params.len() != types.len() {
+        return Err(syn::Error::new_spanned(
+            name,
+            format!(
+                "expected {} type argument{}, got {}",
+                params.len(),
+                if params.len() == 1 { "" } else { "s" },
+                types.len()
+            ),
+        ));
+    }
+
+    let mut instance = signature.clone();
+    instance.generics = syn::Generics::default();
+    let mut substitution = FTypeSubstitution { params, types };
+    for input in instance.inputs.iter_mut() {
+        if let syn::FnArg::Typed(pat_type) = input {
+            substitution.visit_type_mut(&mut pat_type.ty);
+        }
+    }
+    if let syn::ReturnType::Type(_, ty) = &mut instance.output {
+        substitution.visit_type_mut(ty);
+    }
+    Ok(instance)
+}
@@ -1,9 +1,19 @@
 use quote::{quote, TokenStreamExt};
 use std::vec;
 
+mod attributes;
+mod checks;
+mod instantiate;
+mod overloads;
+
+use attributes::*;
+use checks::*;
+use instantiate::*;
+use overloads::*;
+
 // 生命周期坚决不支持，可以报 warning，加 attr 避免 warning
-// template 至少要有限支持，用于数学库函数等
-// reference 要有限支持，比如 Rc 等。函数参数是 mut 引用类型咋弄要想想
+// 泛型函数通过 instantiate(name = <T>, ...) 生成具体的实例，同名的实例按 js 参数的类型选择
+// 引用参数 &T / &mut T 在调用期间从 RcObject<T> 借用，借用冲突时抛 js 异常；&str、&[T] 先转成 String、Vec<T>
 
 // 函数
 macro_rules! js_function_rs_return_format_string {
@@ -18,282 +28,795 @@ macro_rules! js_function_format_string {
     };
 }
 
+macro_rules! js_method_callback_format_string {
+    () => {
+        "__hoo_meta_js_method_callback_{}"
+    };
+}
+
 macro_rules! js_function_name_format_string {
     () => {
         "__hoo_meta_js_function_name_{}"
     };
 }
 
+// 计算属性：同名的 getter 和 setter 合并成一个 accessor
+struct FComputedProperty {
+    js_name: String,
+    getter: Option<(syn::Ident, bool)>,
+    setter: Option<(syn::Ident, syn::Type)>,
+}
+
+fn is_mut_receiver(signature: &syn::Signature) -> bool {
+    match signature.inputs.first() {
+        Some(syn::FnArg::Receiver(receiver)) => receiver.mutability.is_some(),
+        _ => false,
+    }
+}
+
 #[proc_macro_attribute]
 pub fn js_impl(
-    _attr: proc_macro::TokenStream,
+    attr: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let item2: proc_macro2::TokenStream = item.into();
-    let syn_item = syn::parse2::<syn::Item>(item2.clone()).unwrap();
+    let mut impl_attributes = FJsAttributes::default();
+    let attr_parser = syn::meta::parser(|meta| impl_attributes.parse_meta(meta));
+    syn::parse_macro_input!(attr with attr_parser);
+
+    let syn_item = syn::parse_macro_input!(item as syn::Item);
+    let syn_impl = match syn_item {
+        syn::Item::Impl(syn_impl) => syn_impl,
+        _ => {
+            return syn::Error::new_spanned(
+                syn_item,
+                "js_impl attribute can only be applied to impl blocks",
+            )
+            .into_compile_error()
+            .into()
+        }
+    };
 
+    match expand_js_impl(impl_attributes, syn_impl.clone()) {
+        Ok(generated) => generated.into(),
+        Err(err) => {
+            // 出错时仍然输出原来的 impl，避免后面再冒出一堆找不到方法的错误
+            let mut syn_impl = syn_impl;
+            for item in syn_impl.items.iter_mut() {
+                if let syn::ImplItem::Fn(impl_fn) = item {
+                    strip_js_attributes(&mut impl_fn.attrs);
+                    strip_js_param_attributes(&mut impl_fn.sig);
+                }
+            }
+            let mut out = err.into_compile_error();
+            out.append_all(quote!(#syn_impl));
+            out.into()
+        }
+    }
+}
+
+fn expand_js_impl(
+    impl_attributes: FJsAttributes,
+    mut syn_impl: syn::ItemImpl,
+) -> syn::Result<proc_macro2::TokenStream> {
     let mut funcs = Vec::new();
 
-    if let syn::Item::Impl(syn_impl) = syn_item {
-        let impl_type = syn_impl.self_ty;
-
-        let mut method_names: Vec<syn::Ident> = Vec::new();
-
-        for item in syn_impl.items {
-            match item {
-                syn::ImplItem::Fn(impl_fn) => {
-                    if let syn::Visibility::Public(_) = impl_fn.vis {
-                        let signature = impl_fn.sig.clone();
-                        let name = signature.ident;
-                        let mut is_method: bool = false;
-
-                        // 这里应该要做更多检查，比如是不是只有一个有 self 等
-                        for (index, input) in signature.inputs.iter().enumerate() {
-                            match input {
-                                syn::FnArg::Receiver(_) => {
-                                    if index == 0 {
-                                        is_method = true;
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
+    if let Some((_, trait_path, _)) = &syn_impl.trait_ {
+        return Err(syn::Error::new_spanned(
+            trait_path,
+            "js_impl can not be applied to trait impls",
+        ));
+    }
+    check_generics(&syn_impl.generics, "js_impl blocks")?;
+
+    {
+        let impl_type = syn_impl.self_ty.clone();
+        let rename_all = impl_attributes.rename_all.as_ref();
+        let type_name = match &*impl_type {
+            syn::Type::Path(type_path) => type_path.path.segments.last().unwrap().ident.to_string(),
+            _ => quote!(#impl_type).to_string(),
+        };
+
+        let mut methods: Vec<FJsBinding> = Vec::new();
+        let mut static_methods: Vec<FJsBinding> = Vec::new();
+        let mut properties: Vec<FComputedProperty> = Vec::new();
+
+        for item in syn_impl.items.iter_mut() {
+            let impl_fn = match item {
+                syn::ImplItem::Fn(impl_fn) => impl_fn,
+                _ => continue,
+            };
+
+            let attributes = FJsAttributes::from_attrs(&impl_fn.attrs)?;
+            let bound_fn = impl_fn.clone();
+            strip_js_attributes(&mut impl_fn.attrs);
+            strip_js_param_attributes(&mut impl_fn.sig);
+
+            if !matches!(impl_fn.vis, syn::Visibility::Public(_)) || attributes.skip {
+                continue;
+            }
 
-                        if is_method {
-                            method_names.push(name.clone());
+            let signature = impl_fn.sig.clone();
+            let name = signature.ident.clone();
+            let rust_name = name.to_string();
+
+            let is_method = matches!(signature.inputs.first(), Some(syn::FnArg::Receiver(_)));
+
+            if attributes.getter.is_some() || attributes.setter.is_some() {
+                if !attributes.instantiate.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        &signature,
+                        "js getter/setter can not be instantiated",
+                    ));
+                }
+                check_signature(&signature)?;
+                if !is_method {
+                    return Err(syn::Error::new_spanned(
+                        &signature,
+                        "js getter/setter must take `&self` or `&mut self`",
+                    ));
+                }
+                if attributes.getter.is_some() && signature.inputs.len() != 1 {
+                    return Err(syn::Error::new_spanned(
+                        &signature.inputs,
+                        "js getter must not take arguments besides self",
+                    ));
+                }
+
+                if let Some(getter) = &attributes.getter {
+                    let js_name = getter
+                        .clone()
+                        .unwrap_or(attributes.js_name(&rust_name, rename_all));
+                    let property = get_or_insert_property(&mut properties, js_name);
+                    property.getter = Some((name.clone(), is_mut_receiver(&signature)));
+                }
+
+                if let Some(setter) = &attributes.setter {
+                    let default_name = rust_name.strip_prefix("set_").unwrap_or(&rust_name);
+                    let js_name = setter
+                        .clone()
+                        .unwrap_or(attributes.js_name(default_name, rename_all));
+                    let value_type = match signature.inputs.iter().nth(1) {
+                        Some(syn::FnArg::Typed(pat_type)) if signature.inputs.len() == 2 => {
+                            (*pat_type.ty).clone()
+                        }
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                &signature.inputs,
+                                "js setter must take exactly one argument besides self",
+                            ))
                         }
+                    };
+                    let property = get_or_insert_property(&mut properties, js_name);
+                    property.setter = Some((name.clone(), value_type));
+                }
+                continue;
+            }
+
+            if attributes.rename.is_some() && !attributes.instantiate.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &signature.ident,
+                    "instantiated functions are named by instantiate(...), remove `rename`",
+                ));
+            }
+            let is_constructor = rust_name == "new" && !is_method;
+            if is_constructor && !attributes.instantiate.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &signature.ident,
+                    "the constructor `new` can not be instantiated",
+                ));
+            }
 
-                        let generated = generate_js_function(impl_fn.clone(), true);
-                        funcs.push(generated);
+            let bindings = generate_js_bindings(
+                &bound_fn.sig,
+                &attributes.instantiate,
+                true,
+                |rust_name| attributes.js_name(rust_name, rename_all),
+                |js_name| {
+                    if is_constructor {
+                        format!("new {}", type_name)
+                    } else {
+                        format!("{}.{}", type_name, js_name)
                     }
-                }
-                _ => {}
+                },
+                &mut funcs,
+            )?;
+            if is_method {
+                methods.extend(bindings);
+            } else if !is_constructor {
+                static_methods.extend(bindings);
             }
         }
 
-        let method_bindings = method_names.iter().map(|method_ident| {
-            let underlying_func_ident = syn::Ident::new(
-                &format!(
-                    js_function_rs_return_format_string!(),
-                    method_ident.to_string()
-                ),
+        for property in properties.iter() {
+            if let (None, Some((setter_ident, _))) = (&property.getter, &property.setter) {
+                return Err(syn::Error::new_spanned(
+                    setter_ident,
+                    format!("js property `{}` has a setter but no getter", property.js_name),
+                ));
+            }
+        }
+
+        // 方法的回调都生成为具名的关联函数，这样才能放进 snapshot 的 external references 里
+        let method_callback_ident = |method_ident: &syn::Ident| {
+            syn::Ident::new(
+                &format!(js_method_callback_format_string!(), method_ident),
+                method_ident.span(),
+            )
+        };
+        let getter_callback_ident = |method_ident: &syn::Ident| {
+            syn::Ident::new(
+                &format!("__hoo_meta_js_getter_callback_{}", method_ident),
+                method_ident.span(),
+            )
+        };
+        let setter_callback_ident = |method_ident: &syn::Ident| {
+            syn::Ident::new(
+                &format!("__hoo_meta_js_setter_callback_{}", method_ident),
                 method_ident.span(),
+            )
+        };
+        let static_callback_ident = |method_ident: &syn::Ident| {
+            syn::Ident::new(
+                &format!(js_function_format_string!(), method_ident),
+                method_ident.span(),
+            )
+        };
+
+        let binding_callback = |binding: &FJsBinding, callback_ident: syn::Ident| {
+            generate_js_callback(
+                &callback_ident,
+                quote!(#impl_type::),
+                &binding.instances,
+                &binding.display_name,
+            )
+        };
+        let method_callbacks = methods
+            .iter()
+            .map(|binding| binding_callback(binding, method_callback_ident(&binding.ident)));
+        let static_callbacks = static_methods
+            .iter()
+            .map(|binding| binding_callback(binding, static_callback_ident(&binding.ident)));
+
+        let property_callbacks = properties.iter().map(|property| {
+            let (getter_ident, getter_is_mut) = property.getter.as_ref().unwrap();
+            let getter_callback = getter_callback_ident(getter_ident);
+            let message = format!("{}.{}: object is already borrowed", type_name, property.js_name);
+            let getter_receiver = if *getter_is_mut {
+                quote!(&mut *rsobj.try_borrow_mut().map_err(|_| #message.to_string())?)
+            } else {
+                quote!(&*rsobj.try_borrow().map_err(|_| #message.to_string())?)
+            };
+
+            let mut generated = quote!(
+                fn #getter_callback<'s>(
+                    scope: &mut v8::HandleScope<'s>,
+                    _name: v8::Local<'s, v8::Name>,
+                    args: v8::PropertyCallbackArguments<'s>,
+                    mut retval: v8::ReturnValue,
+                ) {
+                    let this: v8::Local<v8::Value> = args.this().into();
+                    let result = <hoo_object::RcObject<Self> as hoo_meta::TryFromJsValue>::try_from(scope, &this)
+                        .map_err(|err| err.error_message())
+                        .and_then(|rsobj| {
+                            let value = #impl_type::#getter_ident(#getter_receiver);
+                            hoo_meta::GetJsValue::get_js_value(&value, scope).map_err(|err| err.error_message())
+                        });
+
+                    match result {
+                        Ok(result) => retval.set(result),
+                        Err(err) => {
+                            let exception_msg = v8::String::new(scope, &err).unwrap().into();
+                            let exception = v8::Exception::error(scope, exception_msg);
+                            scope.throw_exception(exception);
+                        }
+                    }
+                }
             );
 
-            let method_name_str = method_ident.to_string();
-
-            quote!(
-                {
-                    fn function<'a, 's, 'b> (
-                        scope: &'a mut v8::HandleScope<'s>,
-                        args: v8::FunctionCallbackArguments<'s>,
-                        mut retval: v8::ReturnValue<'b>,
+            if let Some((setter_ident, value_type)) = &property.setter {
+                let setter_callback = setter_callback_ident(setter_ident);
+                generated.append_all(quote!(
+                    fn #setter_callback<'s>(
+                        scope: &mut v8::HandleScope<'s>,
+                        _name: v8::Local<'s, v8::Name>,
+                        value: v8::Local<'s, v8::Value>,
+                        args: v8::PropertyCallbackArguments<'s>,
+                        _retval: v8::ReturnValue,
                     ) {
-                        let result =  #impl_type::#underlying_func_ident(scope, args);
-
-                        match result {
-                            Ok(result) => {
-                                let result = hoo_meta::GetJsValue::get_js_value(&result, scope);
-                                match result {
-                                    Err(e) => {
-                                        let exception_msg = v8::String::new(scope, &e.error_message()).unwrap().into();
-                                        let exception = v8::Exception::error(scope, exception_msg);
-                                        scope.throw_exception(exception);
-                                    },
-                                    Ok(result) => {
-                                        retval.set(result);
-                                    }
-                                }
-                            },
-                            Err(err) => {
-                                let exception_msg = v8::String::new(scope, &err.error_message()).unwrap().into();
-                                let exception = v8::Exception::error(scope, exception_msg);
-                                scope.throw_exception(exception);
-                            }
+                        let this: v8::Local<v8::Value> = args.this().into();
+                        let result = <hoo_object::RcObject<Self> as hoo_meta::TryFromJsValue>::try_from(scope, &this)
+                            .and_then(|rsobj| {
+                                let value = <#value_type as hoo_meta::TryFromJsValue>::try_from(scope, &value)?;
+                                let mut rsobj = rsobj.try_borrow_mut().map_err(|_| hoo_meta::TryFromJsValueError::new(#message))?;
+                                #impl_type::#setter_ident(&mut *rsobj, value);
+                                Ok(())
+                            });
+
+                        if let Err(err) = result {
+                            let exception_msg = v8::String::new(scope, &err.error_message()).unwrap().into();
+                            let exception = v8::Exception::error(scope, exception_msg);
+                            scope.throw_exception(exception);
                         }
                     }
+                ));
+            }
 
-                    let function_template = v8::FunctionTemplate::new(scope, function);
+            generated
+        });
+
+        let method_bindings = methods.iter().map(|binding| {
+            let callback_ident = method_callback_ident(&binding.ident);
+            let js_name = &binding.js_name;
+
+            quote!(
+                {
+                    let function_template = v8::FunctionTemplate::new(scope, #impl_type::#callback_ident);
                     let function = function_template.get_function(scope).unwrap();
-                    let function_name = v8::String::new(scope, #method_name_str).unwrap();
+                    let function_name = v8::String::new(scope, #js_name).unwrap();
                     object.set(scope, function_name.into(), function.into());
                 }
             )
         });
 
+        let property_bindings = properties.iter().map(|property| {
+            let js_name = &property.js_name;
+            let getter_callback = getter_callback_ident(&property.getter.as_ref().unwrap().0);
+
+            match &property.setter {
+                Some((setter_ident, _)) => {
+                    let setter_callback = setter_callback_ident(setter_ident);
+                    quote!(
+                        {
+                            let key = v8::String::new(scope, #js_name).unwrap();
+                            object.set_accessor_with_setter(scope, key.into(), #impl_type::#getter_callback, #impl_type::#setter_callback);
+                        }
+                    )
+                }
+                None => quote!(
+                    {
+                        let key = v8::String::new(scope, #js_name).unwrap();
+                        object.set_accessor(scope, key.into(), #impl_type::#getter_callback);
+                    }
+                ),
+            }
+        });
+
+        let static_method_bindings = static_methods.iter().map(|binding| {
+            let callback_ident = static_callback_ident(&binding.ident);
+            let js_name = &binding.js_name;
+
+            quote!(
+                {
+                    let key = v8::String::new(scope, #js_name).unwrap();
+                    let value = v8::FunctionTemplate::new(scope, #impl_type::#callback_ident);
+                    function_template.set(key.into(), value.into());
+                }
+            )
+        });
+
+        let mut references = vec![];
+        for binding in methods.iter() {
+            let callback_ident = method_callback_ident(&binding.ident);
+            references.push(quote!(v8::ExternalReference {
+                function: v8::MapFnTo::map_fn_to(#impl_type::#callback_ident),
+            }));
+        }
+        for binding in static_methods.iter() {
+            let callback_ident = static_callback_ident(&binding.ident);
+            references.push(quote!(v8::ExternalReference {
+                function: v8::MapFnTo::map_fn_to(#impl_type::#callback_ident),
+            }));
+        }
+        for property in properties.iter() {
+            let getter_callback = getter_callback_ident(&property.getter.as_ref().unwrap().0);
+            references.push(quote!(v8::ExternalReference {
+                getter: v8::MapFnTo::map_fn_to(#impl_type::#getter_callback),
+            }));
+            if let Some((setter_ident, _)) = &property.setter {
+                let setter_callback = setter_callback_ident(setter_ident);
+                references.push(quote!(v8::ExternalReference {
+                    setter: v8::MapFnTo::map_fn_to(#impl_type::#setter_callback),
+                }));
+            }
+        }
+
         let mut generated = quote!(
             impl #impl_type {
                 #(#funcs)*
 
+                #(#method_callbacks)*
+                #(#static_callbacks)*
+
+                #(#property_callbacks)*
+
                 fn __hoo_meta_set_trait_methods(
                     scope: &mut v8::HandleScope,
                     object: v8::Local<v8::Object>,
                 ) {
                     #(#method_bindings)*
+                    #(#property_bindings)*
+                }
+
+                fn __hoo_meta_set_static_methods(
+                    scope: &mut v8::HandleScope<()>,
+                    function_template: v8::Local<v8::FunctionTemplate>,
+                ) {
+                    #(#static_method_bindings)*
+                }
+
+                fn __hoo_meta_impl_external_references() -> Vec<v8::ExternalReference<'static>> {
+                    vec![#(#references),*]
                 }
             }
 
         );
 
-        generated.append_all(item2);
-        generated.into()
-    } else {
-        panic!("js_impl attribute can only be applied to impl blocks");
+        // #[js(...)] 不是真正的属性，输出前要去掉
+        generated.append_all(quote!(#syn_impl));
+        Ok(generated)
     }
 }
 
-#[proc_macro_derive(JsStruct)]
+fn get_or_insert_property(
+    properties: &mut Vec<FComputedProperty>,
+    js_name: String,
+) -> &mut FComputedProperty {
+    let index = match properties.iter().position(|property| property.js_name == js_name) {
+        Some(index) => index,
+        None => {
+            properties.push(FComputedProperty {
+                js_name,
+                getter: None,
+                setter: None,
+            });
+            properties.len() - 1
+        }
+    };
+    &mut properties[index]
+}
+
+#[proc_macro_derive(JsStruct, attributes(js))]
 pub fn js_struct_fn(
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let item2: proc_macro2::TokenStream = item.clone().into();
-    let syn_item = syn::parse2::<syn::Item>(item2.clone()).unwrap();
-    if let syn::Item::Struct(syn_struct) = syn_item {
+    let syn_item = syn::parse_macro_input!(item as syn::Item);
+    let generated = get_js_struct(&syn_item, "JsStruct").and_then(|syn_struct| {
         let mut out = quote!();
-        out.append_all(get_item_struct_converter(&syn_struct));
-        out.append_all(get_getters_setters_ctor(&syn_struct));
-        out.into()
-    } else {
-        panic!("js_struct attribute can only be applied to structs");
-    }
+        out.append_all(get_item_struct_converter(syn_struct)?);
+        out.append_all(get_getters_setters_ctor(syn_struct)?);
+        Ok(out)
+    });
+    generated.unwrap_or_else(syn::Error::into_compile_error).into()
 }
 
-#[proc_macro_derive(JsStructNoConstructor)]
+#[proc_macro_derive(JsStructNoConstructor, attributes(js))]
 pub fn js_struct_no_constructor_fn(
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let item2: proc_macro2::TokenStream = item.clone().into();
-    let syn_item = syn::parse2::<syn::Item>(item2.clone()).unwrap();
-    if let syn::Item::Struct(syn_struct) = syn_item {
-        let mut out = quote!();
-        out.append_all(get_item_struct_converter(&syn_struct));
-        out.into()
-    } else {
-        panic!("js_struct attribute can only be applied to structs");
+    let syn_item = syn::parse_macro_input!(item as syn::Item);
+    let generated = get_js_struct(&syn_item, "JsStructNoConstructor")
+        .and_then(get_item_struct_converter);
+    generated.unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+// derive 只支持没有泛型的 struct
+fn get_js_struct<'a>(syn_item: &'a syn::Item, derive_name: &str) -> syn::Result<&'a syn::ItemStruct> {
+    let syn_struct = match syn_item {
+        syn::Item::Struct(syn_struct) => syn_struct,
+        syn::Item::Enum(syn_enum) => {
+            return Err(syn::Error::new_spanned(
+                syn_enum.enum_token,
+                format!("{} can only be derived for structs", derive_name),
+            ))
+        }
+        syn::Item::Union(syn_union) => {
+            return Err(syn::Error::new_spanned(
+                syn_union.union_token,
+                format!("{} can only be derived for structs", derive_name),
+            ))
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                syn_item,
+                format!("{} can only be derived for structs", derive_name),
+            ))
+        }
+    };
+
+    check_generics(&syn_struct.generics, "js structs")?;
+    for field in syn_struct.fields.iter() {
+        check_type(&field.ty)?;
     }
+    Ok(syn_struct)
 }
 
-fn get_getters_setters_ctor(st: &syn::ItemStruct) -> proc_macro2::TokenStream {
-    let ident = &st.ident;
-    let struct_name = ident.to_string();
+// struct 在 js 里的样子
+enum EStructLayout {
+    // { key: value }：普通 struct、unit struct，以及带 #[js(as_object)] 的 tuple struct
+    Object,
+    // [a, b]：tuple struct
+    Array,
+    // 直接就是唯一字段的值：newtype，比如 struct EntityId(u32)
+    Transparent,
+}
 
-    let mut fields: Vec<(syn::Ident, syn::Type)> = vec![];
+struct FStructField {
+    member: syn::Member,
+    ty: syn::Type,
+    js_name: String,
+    is_public: bool,
+    attributes: FJsAttributes,
+}
+
+fn member_to_string(member: &syn::Member) -> String {
+    match member {
+        syn::Member::Named(ident) => ident.to_string(),
+        syn::Member::Unnamed(index) => index.index.to_string(),
+    }
+}
 
-    for field in &st.fields {
-        if let Some(field_ident) = &field.ident {
-            if let syn::Visibility::Public(_) = field.vis {
-                let field_type = field.ty.clone();
-                fields.push((field_ident.clone(), field_type));
+fn get_struct_layout(st: &syn::ItemStruct) -> syn::Result<EStructLayout> {
+    let struct_attributes = FJsAttributes::from_attrs(&st.attrs)?;
+
+    match &st.fields {
+        syn::Fields::Named(_) | syn::Fields::Unit => {
+            if struct_attributes.as_object {
+                return Err(syn::Error::new_spanned(
+                    &st.ident,
+                    "#[js(as_object)] only applies to tuple structs",
+                ));
             }
-            // else skip private fields
-        } else {
-            panic!("only supports structs with named fields");
+            Ok(EStructLayout::Object)
+        }
+        syn::Fields::Unnamed(_) if struct_attributes.as_object => Ok(EStructLayout::Object),
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            let field = fields.unnamed.first().unwrap();
+            if FJsAttributes::from_attrs(&field.attrs)?.skip {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "the field of a newtype can not be skipped",
+                ));
+            }
+            Ok(EStructLayout::Transparent)
         }
+        syn::Fields::Unnamed(_) => Ok(EStructLayout::Array),
     }
+}
+
+fn get_struct_fields(st: &syn::ItemStruct) -> syn::Result<Vec<FStructField>> {
+    let struct_attributes = FJsAttributes::from_attrs(&st.attrs)?;
+    let mut fields = vec![];
+
+    for (i, field) in st.fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(syn::Index::from(i)),
+        };
+        let attributes = FJsAttributes::from_attrs(&field.attrs)?;
+        let js_name = attributes.js_name(
+            &member_to_string(&member),
+            struct_attributes.rename_all.as_ref(),
+        );
+        fields.push(FStructField {
+            member,
+            ty: field.ty.clone(),
+            js_name,
+            is_public: matches!(field.vis, syn::Visibility::Public(_)),
+            attributes,
+        });
+    }
+
+    Ok(fields)
+}
+
+// js 这一侧看到的字段：(rust 字段, 类型, js 名字, 是否只读)
+fn get_bound_fields(st: &syn::ItemStruct) -> syn::Result<Vec<(syn::Member, syn::Type, String, bool)>> {
+    // skip private fields
+    let fields = get_struct_fields(st)?
+        .into_iter()
+        .filter(|field| field.is_public && !field.attributes.skip)
+        .map(|field| (field.member, field.ty, field.js_name, field.attributes.readonly))
+        .collect();
+
+    Ok(fields)
+}
+
+fn get_getters_setters_ctor(st: &syn::ItemStruct) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &st.ident;
+    let struct_name = FJsAttributes::from_attrs(&st.attrs)?.js_name(&ident.to_string(), None);
+
+    let fields = get_bound_fields(st)?;
 
     let converter_func_ident = syn::Ident::new(
         &format!(js_function_rs_return_format_string!(), "new"),
         proc_macro2::Span::call_site(),
     );
 
-    let getters_setters = fields.iter().map(|(key_ident, ty)| {
-        let key_str = key_ident.to_string();
+    let accessor_idents = |key_ident: &syn::Member| {
+        (
+            syn::Ident::new(
+                &format!("__hoo_meta_getter_{}", member_to_string(key_ident)),
+                proc_macro2::Span::call_site(),
+            ),
+            syn::Ident::new(
+                &format!("__hoo_meta_setter_{}", member_to_string(key_ident)),
+                proc_macro2::Span::call_site(),
+            ),
+        )
+    };
 
-        quote!(
-            {
-                // getters && setters
-                fn getter<'s>(
-                    scope: &mut v8::HandleScope<'s>,
-                    name: v8::Local<'s, v8::Name>,
-                    args: v8::PropertyCallbackArguments<'s>,
-                    mut retval: v8::ReturnValue,
-                ) {
-                    let this = args.this();
-                    // 能访问到这个 getter/setter, 说明一定是有效的
-                    let rsobj_index = hoo_meta::get_external_internal_value_from_js_object(scope, &this, 0).unwrap();
-                    let rsobj = hoo_meta::get_registered_rust_object(hoo_object::ObjectId::from_ptr(rsobj_index as *const std::os::raw::c_void)).unwrap();
+    // getters && setters
+    let accessors = fields.iter().map(|(key_ident, ty, key_str, readonly)| {
+        let (getter_ident, setter_ident) = accessor_idents(key_ident);
+        let borrow_message = format!("{}.{}: object is already borrowed", struct_name, key_str);
 
-                    let rsobj = rsobj.try_downcast::<#ident>().unwrap();
-                    let jsobj = hoo_meta::GetJsValue::get_js_value(&rsobj.borrow().#key_ident, scope);
+        let mut generated = quote!(
+            fn #getter_ident<'s>(
+                scope: &mut v8::HandleScope<'s>,
+                _name: v8::Local<'s, v8::Name>,
+                args: v8::PropertyCallbackArguments<'s>,
+                mut retval: v8::ReturnValue,
+            ) {
+                let this = args.this();
+                // 能访问到这个 getter/setter, 说明一定是有效的
+                let rsobj_index = hoo_meta::get_external_internal_value_from_js_object(scope, &this, 0).unwrap();
+                let rsobj = hoo_meta::get_registered_rust_object(hoo_object::ObjectId::from_ptr(rsobj_index as *const std::os::raw::c_void)).unwrap();
+
+                let rsobj = rsobj.try_downcast::<#ident>().unwrap();
+                let rsobj = match rsobj.try_borrow() {
+                    Ok(rsobj) => rsobj,
+                    Err(_) => {
+                        let exception_msg = v8::String::new(scope, #borrow_message).unwrap().into();
+                        let exception = v8::Exception::error(scope, exception_msg);
+                        scope.throw_exception(exception);
+                        return;
+                    }
+                };
+                let jsobj = hoo_meta::GetJsValue::get_js_value(&rsobj.#key_ident, scope);
 
-                    match jsobj {
-                        Ok(jsobj) => {
-                            retval.set(jsobj);
-                        }
-                        Err(err) => {
-                            println!("Failed to convert to js value: {}", &err.error_message());
-                        }
+                match jsobj {
+                    Ok(jsobj) => {
+                        retval.set(jsobj);
+                    }
+                    Err(err) => {
+                        println!("Failed to convert to js value: {}", &err.error_message());
                     }
                 }
+            }
 
-                fn setter<'s>(
-                    scope: &mut v8::HandleScope<'s>,
-                    name: v8::Local<'s, v8::Name>,
-                    value: v8::Local<'s, v8::Value>,
-                    args: v8::PropertyCallbackArguments<'s>,
-                    mut retval: v8::ReturnValue,
-                ) {
-                    let val: Result<#ty, _> = hoo_meta::TryFromJsValue::try_from(scope, &value);
-                    match val {
-                        Ok(val) => {
-                            let this = args.this();
-                            let rsobj_index = hoo_meta::get_external_internal_value_from_js_object(scope, &this, 0).unwrap();
-                            let rsobj = hoo_meta::get_registered_rust_object(hoo_object::ObjectId::from_ptr(rsobj_index as *const std::os::raw::c_void)).unwrap();
-
-                            let rsobj = rsobj.try_downcast::<#ident>().unwrap();
-                            rsobj.borrow_mut().#key_ident = val;
-                        }
-                        Err(err) => {
-                            println!("Failed to convert to rust value: {}", &err.error_message());
-                        }
+        );
+
+        if !readonly {
+            generated.append_all(quote!(
+            fn #setter_ident<'s>(
+                scope: &mut v8::HandleScope<'s>,
+                _name: v8::Local<'s, v8::Name>,
+                value: v8::Local<'s, v8::Value>,
+                args: v8::PropertyCallbackArguments<'s>,
+                _retval: v8::ReturnValue,
+            ) {
+                let val: Result<#ty, _> = hoo_meta::TryFromJsValue::try_from(scope, &value);
+                match val {
+                    Ok(val) => {
+                        let this = args.this();
+                        let rsobj_index = hoo_meta::get_external_internal_value_from_js_object(scope, &this, 0).unwrap();
+                        let rsobj = hoo_meta::get_registered_rust_object(hoo_object::ObjectId::from_ptr(rsobj_index as *const std::os::raw::c_void)).unwrap();
+
+                        let rsobj = rsobj.try_downcast::<#ident>().unwrap();
+                        match rsobj.try_borrow_mut() {
+                            Ok(mut rsobj) => rsobj.#key_ident = val,
+                            Err(_) => {
+                                let exception_msg = v8::String::new(scope, #borrow_message).unwrap().into();
+                                let exception = v8::Exception::error(scope, exception_msg);
+                                scope.throw_exception(exception);
+                            }
+                        };
+                    }
+                    Err(err) => {
+                        println!("Failed to convert to rust value: {}", &err.error_message());
                     }
                 }
-
-                let key = v8::String::new(scope, #key_str).unwrap();
-                this.set_accessor_with_setter(scope, key.into(), getter, setter);
             }
-        )
+            ));
+        }
+
+        generated
     });
 
-    let getters_setters_clone = getters_setters.clone();
+    let accessor_bindings = fields.iter().map(|(key_ident, _, key_str, readonly)| {
+        let (getter_ident, setter_ident) = accessor_idents(key_ident);
+
+        if *readonly {
+            quote!(
+                {
+                    let key = v8::String::new(scope, #key_str).unwrap();
+                    this.set_accessor(scope, key.into(), #ident::#getter_ident);
+                }
+            )
+        } else {
+            quote!(
+                {
+                    let key = v8::String::new(scope, #key_str).unwrap();
+                    this.set_accessor_with_setter(scope, key.into(), #ident::#getter_ident, #ident::#setter_ident);
+                }
+            )
+        }
+    });
+
+    let accessor_bindings_clone = accessor_bindings.clone();
+
+    let accessor_references = fields.iter().map(|(key_ident, _, _, readonly)| {
+        let (getter_ident, setter_ident) = accessor_idents(key_ident);
+
+        let mut generated = quote!(
+            v8::ExternalReference {
+                getter: v8::MapFnTo::map_fn_to(#ident::#getter_ident),
+            },
+        );
+        if !readonly {
+            generated.append_all(quote!(
+                v8::ExternalReference {
+                    setter: v8::MapFnTo::map_fn_to(#ident::#setter_ident),
+                },
+            ));
+        }
+        generated
+    });
 
     let generated = quote!(
         impl #ident {
-            pub fn __hoo_meta_register_struct<'s, 'a>(
-                module_builder: &mut impl hoo_meta::ModuleLikeBuilder<'s, 'a>
+            #(#accessors)*
+
+            fn __hoo_meta_constructor_callback<'a, 's, 'b>(
+                scope: &'a mut v8::HandleScope<'s>,
+                args: v8::FunctionCallbackArguments<'s>,
+                mut retval: v8::ReturnValue<'b>,
             ) {
-                fn new<'a, 's, 'b>(
-                    scope: &'a mut v8::HandleScope<'s>,
-                    args: v8::FunctionCallbackArguments<'s>,
-                    mut retval: v8::ReturnValue<'b>,
-                ) {
-                    let this = args.this();
-                    if this.is_null_or_undefined() {
-                        return;
-                    }
+                let this = args.this();
+                if this.is_null_or_undefined() {
+                    return;
+                }
 
-                    // TODO: 怎么挪到外面去？
-                    let instance_template = v8::ObjectTemplate::new(scope);
-                    instance_template.set_internal_field_count(1);
+                // TODO: 怎么挪到外面去？
+                let instance_template = v8::ObjectTemplate::new(scope);
+                instance_template.set_internal_field_count(1);
 
-                    // 覆盖 this，增加 internal field
-                    let this = instance_template.new_instance(scope).unwrap();
-                    retval.set(this.into());
+                // 覆盖 this，增加 internal field
+                let this = instance_template.new_instance(scope).unwrap();
+                retval.set(this.into());
 
-                    // 构造 RcObject
-                    let rs_stu: #ident = #ident::#converter_func_ident(scope, args).unwrap();
-                    let rs_obj = hoo_object::RcObject::new(rs_stu);
+                // 构造 RcObject
+                let rs_stu: #ident = #ident::#converter_func_ident(scope, &args).unwrap();
+                let rs_obj = hoo_object::RcObject::new(rs_stu);
 
-                    hoo_meta::register_object_enabling_bigc(scope, rs_obj.into_any(), this);
+                hoo_meta::register_object_enabling_bigc(scope, rs_obj.into_any(), this);
 
-                    #(#getters_setters)*
+                #(#accessor_bindings)*
 
-                    #ident::__hoo_meta_set_trait_methods(scope, this);
-                };
+                #ident::__hoo_meta_set_trait_methods(scope, this);
+            }
+
+            // 这个类型用到的所有 native 回调，创建 snapshot 时要用
+            pub fn __hoo_meta_external_references() -> Vec<v8::ExternalReference<'static>> {
+                let mut references = vec![
+                    v8::ExternalReference {
+                        function: v8::MapFnTo::map_fn_to(#ident::__hoo_meta_constructor_callback),
+                    },
+                    #(#accessor_references)*
+                ];
+                references.append(&mut #ident::__hoo_meta_impl_external_references());
+                references
+            }
+
+            pub fn __hoo_meta_register_struct<'s, 'a>(
+                module_builder: &mut impl hoo_meta::ModuleLikeBuilder<'s, 'a>
+            ) {
+                module_builder.add_external_references(#ident::__hoo_meta_external_references());
 
                 // todo: cache?
                 let scope = module_builder.get_global_scope();
-                let function_template = v8::FunctionTemplate::new(scope, new);
+                let function_template = v8::FunctionTemplate::new(scope, #ident::__hoo_meta_constructor_callback);
+                #ident::__hoo_meta_set_static_methods(scope, function_template);
                 let entryname = v8::String::new(scope, #struct_name).unwrap();
                 module_builder.get_template().set(entryname.into(), function_template.into());
             }
@@ -307,7 +830,7 @@ fn get_getters_setters_ctor(st: &syn::ItemStruct) -> proc_macro2::TokenStream {
                 instance_template.set_internal_field_count(1);
 
                 let this = instance_template.new_instance(scope).unwrap();
-                #(#getters_setters_clone)*
+                #(#accessor_bindings_clone)*
 
                 #ident::__hoo_meta_set_trait_methods(scope, this);
 
@@ -316,68 +839,144 @@ fn get_getters_setters_ctor(st: &syn::ItemStruct) -> proc_macro2::TokenStream {
         }
     );
 
-    generated
+    Ok(generated)
 }
 
 // TODO：访问权限控制有一些问题：如果不是所有字段都是 pub 的，那么不应当允许从 Js 到 Rust 的转换
 // 类型转换
-fn get_item_struct_converter(st: &syn::ItemStruct) -> proc_macro2::TokenStream {
+fn get_item_struct_converter(st: &syn::ItemStruct) -> syn::Result<proc_macro2::TokenStream> {
     let ident = &st.ident;
+    let layout = get_struct_layout(st)?;
+    let fields = get_struct_fields(st)?;
 
-    let mut fields: Vec<(syn::Ident, syn::Type)> = vec![];
-
-    let has_private_field = st.fields.iter().find(|x| {
-        if let syn::Visibility::Public(_) = x.vis {
-            false
-        } else {
-            true
-        }
-    }).is_some();
-
-    if has_private_field {
-        return quote!();
+    if fields.iter().any(|field| !field.is_public) {
+        return Ok(quote!());
     }
-    
-    for field in &st.fields {
-        if let Some(field_ident) = &field.ident {
-            let field_type = field.ty.clone();
-            fields.push((field_ident.clone(), field_type));
-        } else {
-            panic!("only supports structs with named fields");
+
+    let local_ident = |i: usize| {
+        syn::Ident::new(
+            &format!("__hoo_meta_field_{}", i),
+            proc_macro2::Span::call_site(),
+        )
+    };
+    let members = fields.iter().map(|field| &field.member);
+    let locals = (0..fields.len()).map(local_ident);
+
+    // 数组里只放没有跳过的字段，下标依次排下去
+    let mut array_indices = vec![];
+    let mut array_len = 0u32;
+    for field in fields.iter() {
+        array_indices.push(array_len);
+        if !field.attributes.skip {
+            array_len += 1;
         }
     }
 
-    let binding_stmts = fields
-        .iter()
-        .map(|(field_ident, field_type)| {
-            let field_name = field_ident.to_string();
-            quote!(
+    let binding_stmts = fields.iter().enumerate().map(|(i, field)| {
+        let local = local_ident(i);
+        let field_type = &field.ty;
+        let field_name = &field.js_name;
+        let array_index = array_indices[i];
+
+        if field.attributes.skip {
+            // 跳过的字段不从 js 读取，用默认值
+            return quote!(
+                let #local = <#field_type as Default>::default();
+            );
+        }
+        match layout {
+            EStructLayout::Object => quote!(
                 let key = v8::String::new(scope, #field_name).unwrap();
                 let js_value = js_object.get(scope, key.into()).ok_or(hoo_meta::TryFromJsValueError::new(&format!("field does not exist: {}", #field_name)))?;
-                let #field_ident = <#field_type as hoo_meta::TryFromJsValue>::try_from(scope, &js_value)?;
-            )
-        });
-
-    let set_js_object_stmts = fields.iter().map(|(field_ident, _)| {
-        let field_name = field_ident.to_string();
-        quote!(
-            let key = v8::String::new(scope, #field_name).unwrap();
-            let value = hoo_meta::GetJsValue::get_js_value(&self.#field_ident, scope)?;
-            js_object.set(scope, key.into(), value);
-        )
+                let #local = <#field_type as hoo_meta::TryFromJsValue>::try_from(scope, &js_value)?;
+            ),
+            EStructLayout::Array => quote!(
+                let js_value = js_array.get_index(scope, #array_index).ok_or(hoo_meta::TryFromJsValueError::new(&format!("no element at {}", #array_index)))?;
+                let #local = <#field_type as hoo_meta::TryFromJsValue>::try_from(scope, &js_value)?;
+            ),
+            EStructLayout::Transparent => quote!(
+                let #local = <#field_type as hoo_meta::TryFromJsValue>::try_from(scope, val)?;
+            ),
+        }
     });
 
-    let set_js_object_stmts_clone = set_js_object_stmts.clone();
+    let source_stmt = match layout {
+        EStructLayout::Object if array_len == 0 => quote!(
+            val.to_object(scope).ok_or(hoo_meta::TryFromJsValueError::new("not an object"))?;
+        ),
+        EStructLayout::Object => quote!(
+            // TODO: 如果 JsValue 是 RcObject，处理方式还能再简单点
+            let js_object = val.to_object(scope).ok_or(hoo_meta::TryFromJsValueError::new("not an object"))?;
+        ),
+        EStructLayout::Array => quote!(
+            let js_array = v8::Local::<v8::Array>::try_from(*val)
+                .or(Err(hoo_meta::TryFromJsValueError::new("not an array")))?;
+            if js_array.length() < #array_len {
+                return Err(hoo_meta::TryFromJsValueError::new(&format!("expected an array of {} elements", #array_len)));
+            }
+        ),
+        EStructLayout::Transparent => quote!(),
+    };
+
+    let bound_fields = fields.iter().filter(|field| !field.attributes.skip);
+
+    let get_js_value_body = match layout {
+        EStructLayout::Object => {
+            let stmts = bound_fields.map(|field| {
+                let member = &field.member;
+                let field_name = &field.js_name;
+                quote!(
+                    let key = v8::String::new(scope, #field_name).unwrap();
+                    let value = hoo_meta::GetJsValue::get_js_value(&self.#member, scope)?;
+                    js_object.set(scope, key.into(), value);
+                )
+            });
+            quote!(
+                let js_object = v8::Object::new(scope);
+                #(#stmts)*
+                Ok(js_object.into())
+            )
+        }
+        EStructLayout::Array => {
+            let bound_members = bound_fields.map(|field| &field.member);
+            quote!(
+                let elements = [
+                    #(hoo_meta::GetJsValue::get_js_value(&self.#bound_members, scope)?),*
+                ];
+                Ok(v8::Array::new_with_elements(scope, &elements).into())
+            )
+        }
+        EStructLayout::Transparent => quote!(
+            hoo_meta::GetJsValue::get_js_value(&self.0, scope)
+        ),
+    };
 
-    let field_idents = fields.iter().map(|(field_ident, _)| field_ident);
+    let fill_js_object_stmts = fields
+        .iter()
+        .zip(array_indices.iter())
+        .filter(|(field, _)| !field.attributes.skip)
+        .map(|(field, array_index)| {
+            let member = &field.member;
+            let field_name = &field.js_name;
+            match layout {
+                EStructLayout::Array => quote!(
+                    let value = hoo_meta::GetJsValue::get_js_value(&self.#member, scope)?;
+                    js_object.set_index(scope, #array_index, value);
+                ),
+                _ => quote!(
+                    let key = v8::String::new(scope, #field_name).unwrap();
+                    let value = hoo_meta::GetJsValue::get_js_value(&self.#member, scope)?;
+                    js_object.set(scope, key.into(), value);
+                ),
+            }
+        });
 
-    let generated = quote!(
+    let mut generated = quote!(
         impl hoo_meta::TryFromJsValue for #ident {
             fn try_from<'a>(scope: &mut v8::HandleScope<'a>, val: &v8::Local<'a, v8::Value>) -> Result<Self, hoo_meta::TryFromJsValueError> {
-                // TODO: 如果 JsValue 是 RcObject，处理方式还能再简单点
-                let js_object = val.to_object(scope).ok_or(hoo_meta::TryFromJsValueError::new("not an object"))?;
+                #source_stmt
                 #(#binding_stmts)*
-                let out = #ident { #(#field_idents),* };
+                let out = #ident { #(#members: #locals),* };
                 Ok(out)
                 // TODO: 这里或许需要调用一下做有效性检查的 trait
             }
@@ -385,83 +984,127 @@ fn get_item_struct_converter(st: &syn::ItemStruct) -> proc_macro2::TokenStream {
 
         impl hoo_meta::GetJsValue for #ident {
             fn get_js_value<'a>(&self, scope: &mut v8::HandleScope<'a>) -> Result<v8::Local<'a, v8::Value>, hoo_meta::JsException> {
-                let js_object = v8::Object::new(scope);
-                #(#set_js_object_stmts)*
-                Ok(js_object.into())
+                #get_js_value_body
             }
         }
+    );
 
-        impl hoo_meta::FillJsObject for #ident {
-            fn fill_js_object<'a, 'b>(
-                &self,
-                js_object: v8::Local<'b, v8::Object>,
-                scope: &mut v8::HandleScope<'a>,
-            ) -> Result<(), hoo_meta::JsException>
-                where 'a: 'b
-            {
-                #(#set_js_object_stmts_clone)*
-                Ok(())
+    // newtype 直接就是里面的值，没法往已有的对象上填
+    if !matches!(layout, EStructLayout::Transparent) {
+        generated.append_all(quote!(
+            impl hoo_meta::FillJsObject for #ident {
+                #[allow(unused_variables)]
+                fn fill_js_object<'a, 'b>(
+                    &self,
+                    js_object: v8::Local<'b, v8::Object>,
+                    scope: &mut v8::HandleScope<'a>,
+                ) -> Result<(), hoo_meta::JsException>
+                    where 'a: 'b
+                {
+                    #(#fill_js_object_stmts)*
+                    Ok(())
+                }
             }
-        }
-    );
+        ));
+    }
 
-    generated
+    Ok(generated)
 }
 
 #[proc_macro]
 pub fn get_js_function(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let iter = item.into_iter();
-    assert!(iter.clone().count() == 1);
-
-    let maybe_ident = iter.last().unwrap();
-
-    match maybe_ident {
-        proc_macro::TokenTree::Ident(ident) => {
-            let new_ident = syn::Ident::new(
-                &format!(js_function_format_string!(), ident),
-                proc_macro2::Span::call_site(),
-            );
-            quote!(#new_ident).into()
-        }
-        _ => unimplemented!("get_js_function only supports identifiers"),
-    }
+    let ident = syn::parse_macro_input!(item as syn::Ident);
+    let new_ident = syn::Ident::new(
+        &format!(js_function_format_string!(), ident),
+        ident.span(),
+    );
+    quote!(#new_ident).into()
 }
 
 
 #[proc_macro]
 pub fn get_js_function_name_string(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let iter = item.into_iter();
-    assert!(iter.clone().count() == 1);
-
-    let maybe_ident = iter.last().unwrap();
-
-    match maybe_ident {
-        proc_macro::TokenTree::Ident(ident) => {
-            let new_ident = syn::Ident::new(
-                &format!(js_function_name_format_string!(), ident),
-                proc_macro2::Span::call_site(),
-            );
-            quote!(#new_ident).into()
-        }
-        _ => unimplemented!("get_js_function only supports identifiers"),
-    }
+    let ident = syn::parse_macro_input!(item as syn::Ident);
+    let new_ident = syn::Ident::new(
+        &format!(js_function_name_format_string!(), ident),
+        ident.span(),
+    );
+    quote!(#new_ident).into()
 }
 
 
 #[proc_macro_attribute]
 pub fn js_function(
-    _attr: proc_macro::TokenStream,
+    attr: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let item2: proc_macro2::TokenStream = item.clone().into();
-    let syn_item = syn::parse2::<syn::Item>(item2.clone()).unwrap();
-    if let syn::Item::Fn(syn_fn) = syn_item {
-        let mut generated = generate_js_function(syn_fn, false);
-        generated.append_all(item2);
-        generated.into()
-    } else {
-        panic!("js_method only supports functions");
+    let mut function_attributes = FJsAttributes::default();
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("instantiate") {
+            function_attributes.parse_meta(meta)
+        } else {
+            Err(meta.error("js_function only supports `instantiate(...)`"))
+        }
+    });
+    syn::parse_macro_input!(attr with attr_parser);
+
+    let syn_item = syn::parse_macro_input!(item as syn::Item);
+    let generated = match &syn_item {
+        syn::Item::Fn(syn_fn) => expand_js_function(&function_attributes, syn_fn),
+        _ => Err(syn::Error::new_spanned(
+            &syn_item,
+            "js_function attribute can only be applied to functions",
+        )),
+    };
+
+    // 参数上的 #[js(...)] 不是真正的属性，输出前要去掉
+    let mut syn_item = syn_item;
+    if let syn::Item::Fn(syn_fn) = &mut syn_item {
+        strip_js_param_attributes(&mut syn_fn.sig);
     }
+
+    let mut out = generated.unwrap_or_else(syn::Error::into_compile_error);
+    out.append_all(quote!(#syn_item));
+    out.into()
+}
+
+fn expand_js_function(
+    function_attributes: &FJsAttributes,
+    syn_fn: &syn::ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut funcs = vec![];
+    let bindings = generate_js_bindings(
+        &syn_fn.sig,
+        &function_attributes.instantiate,
+        false,
+        |rust_name| rust_name.to_string(),
+        |js_name| js_name.to_string(),
+        &mut funcs,
+    )?;
+
+    for binding in bindings.iter() {
+        let callback_ident = syn::Ident::new(
+            &format!(js_function_format_string!(), binding.ident),
+            proc_macro2::Span::call_site(),
+        );
+        let function_name_ident = syn::Ident::new(
+            &format!(js_function_name_format_string!(), binding.ident),
+            proc_macro2::Span::call_site(),
+        );
+        let function_name_str = &binding.js_name;
+
+        funcs.push(quote!(
+            const #function_name_ident: &str = #function_name_str;
+        ));
+        funcs.push(generate_js_callback(
+            &callback_ident,
+            quote!(),
+            &binding.instances,
+            &binding.display_name,
+        ));
+    }
+
+    Ok(quote!(#(#funcs)*))
 }
 
 // #[proc_macro_attribute]
@@ -480,27 +1123,17 @@ pub fn js_function(
 //     }
 // }
 
-trait FunctionType {
-    fn get_signature(&self) -> &syn::Signature;
-}
-
-impl FunctionType for syn::ItemFn {
-    fn get_signature(&self) -> &syn::Signature {
-        &self.sig
-    }
-}
-
-impl FunctionType for syn::ImplItemFn {
-    fn get_signature(&self) -> &syn::Signature {
-        &self.sig
-    }
-}
-
-fn generate_js_function(syn_fn: impl FunctionType, in_impl: bool) -> proc_macro2::TokenStream {
-    let signature = syn_fn.get_signature().clone();
-    let ident = &signature.ident;
-    let ret_ty = signature.output;
-    let ret_ty = match ret_ty {
+// 一个 rust 函数（或者泛型函数的一个实例）对应的参数转换函数：
+// 把 js 参数转换后调用 rust 函数，返回 rust 的返回值。
+// signature 里不能再有泛型参数，call_path 是实际调用的函数，比如 Self::lerp::<f32>。
+// display_name 用在报错信息里，比如 add、Pair.sumAll
+fn generate_js_function(
+    signature: &syn::Signature,
+    call_path: proc_macro2::TokenStream,
+    rs_return_ident: &syn::Ident,
+    display_name: &str,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let ret_ty = match &signature.output {
         syn::ReturnType::Default => {
             quote!(())
         }
@@ -509,33 +1142,16 @@ fn generate_js_function(syn_fn: impl FunctionType, in_impl: bool) -> proc_macro2
         }
     };
 
-    let new_ident_1 = syn::Ident::new(
-        &format!(js_function_rs_return_format_string!(), ident),
-        proc_macro2::Span::call_site(),
-    );
-
-    let new_ident_2 = syn::Ident::new(
-        &format!(js_function_format_string!(), ident),
-        proc_macro2::Span::call_site(),
-    );
-
-    let function_name_ident = syn::Ident::new(
-        &format!(js_function_name_format_string!(), ident),
-        proc_macro2::Span::call_site(),
-    );
-
-    let function_name_str = ident.to_string();
-
-    // check: self is not exisits
-    let arg_count = signature.inputs.len() as i32;
-
     let mut arguments_getter: Vec<proc_macro2::TokenStream> = vec![];
 
     let mut pats: Vec<proc_macro2::TokenStream> = vec![];
-    let mut is_method = false;
+
+    // 不算 self 的参数个数，以及至少要传几个
+    let mut param_count = 0;
+    let mut min_arg_count = 0;
+    let mut has_rest = false;
 
     for (i, arg) in signature.inputs.iter().enumerate() {
-        let i = i as i32;
         let pat = syn::Ident::new(&format!("arg{}", i), proc_macro2::Span::call_site());
         match arg {
             syn::FnArg::Receiver(rec) => {
@@ -543,10 +1159,10 @@ fn generate_js_function(syn_fn: impl FunctionType, in_impl: bool) -> proc_macro2
                 // &mut self 不支持非 RcObject
 
                 if rec.reference.is_none() {
-                    panic!("self must be a reference");
+                    return Err(syn::Error::new_spanned(rec, "self must be a reference"));
                 }
 
-                // let is_mut = rec.mutability.is_some();
+                let is_mut = rec.mutability.is_some();
 
                 // let ty = rec.ty.clone();
 
@@ -558,22 +1174,88 @@ fn generate_js_function(syn_fn: impl FunctionType, in_impl: bool) -> proc_macro2
                 // pats.push(quote!(& #pat));
 
                 // 否则
-                let generated = quote!(
+                let message = format!("{}: object is already borrowed", display_name);
+                arguments_getter.push(quote!(
                     let #pat = <hoo_object::RcObject<Self> as hoo_meta::TryFromJsValue>::try_from(scope, &args.this().into())?;
-                );
-                arguments_getter.push(generated);
-                pats.push(quote!(#pat.borrow().deref()));
-
-                if i == 0 {
-                    is_method = true;
+                ));
+                arguments_getter.push(get_borrow_stmt(&pat, is_mut, &message));
+                if is_mut {
+                    pats.push(quote!(&mut *#pat));
+                } else {
+                    pats.push(quote!(&*#pat));
                 }
             }
             syn::FnArg::Typed(pat_type) => {
                 let ty = &pat_type.ty;
+                let attributes = FJsAttributes::from_attrs(&pat_type.attrs)?;
+                let js_attr = pat_type.attrs.iter().find(|attr| attr.path().is_ident("js"));
+                if !attributes.is_param_attributes() {
+                    return Err(syn::Error::new_spanned(
+                        js_attr,
+                        "only #[js(default = ...)] is supported on parameters",
+                    ));
+                }
 
-                let generated = quote!(
-                    let #pat = <#ty as hoo_meta::TryFromJsValue>::try_from(scope, &args.get(#i - this_offset))?;
-                );
+                if has_rest {
+                    return Err(syn::Error::new_spanned(
+                        &signature.inputs[i - 1],
+                        "JsRest must be the last parameter",
+                    ));
+                }
+
+                let js_index = param_count;
+                param_count += 1;
+
+                if let syn::Type::Reference(reference) = &**ty {
+                    if attributes.default.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            js_attr,
+                            "reference parameters can not have a default value",
+                        ));
+                    }
+                    min_arg_count = param_count;
+
+                    let (getter, pass) = get_reference_argument(
+                        reference,
+                        &pat,
+                        js_index,
+                        &get_argument_name(pat_type, js_index),
+                        display_name,
+                    );
+                    arguments_getter.push(getter);
+                    pats.push(pass);
+                    continue;
+                }
+
+                let generated = if is_type_named(ty, "JsRest") {
+                    if attributes.default.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            js_attr,
+                            "JsRest parameters can not have a default value",
+                        ));
+                    }
+                    has_rest = true;
+                    quote!(
+                        let #pat = <#ty>::from_args(scope, args, #js_index)?;
+                    )
+                } else if let Some(default) = &attributes.default {
+                    // 和 js 一样，显式传 undefined 也用默认值
+                    quote!(
+                        let #pat = if #js_index < args.length() && !args.get(#js_index).is_undefined() {
+                            <#ty as hoo_meta::TryFromJsValue>::try_from(scope, &args.get(#js_index))?
+                        } else {
+                            #default
+                        };
+                    )
+                } else {
+                    // 缺少的参数是 undefined，Option 会得到 None
+                    if !is_type_named(ty, "Option") {
+                        min_arg_count = param_count;
+                    }
+                    quote!(
+                        let #pat = <#ty as hoo_meta::TryFromJsValue>::try_from(scope, &args.get(#js_index))?;
+                    )
+                };
 
                 arguments_getter.push(generated);
                 pats.push(quote!(#pat));
@@ -581,82 +1263,351 @@ fn generate_js_function(syn_fn: impl FunctionType, in_impl: bool) -> proc_macro2
         }
     }
 
-    if !is_method {
-        let qualifier = if in_impl { quote!(Self::) } else { quote!() };
+    let arity_check = get_arity_check(display_name, min_arg_count, param_count, has_rest);
 
-        let generated = quote!(
-            fn #new_ident_1<'a, 's, 'b> (
-                scope: &'a mut v8::HandleScope<'s>,
-                args: v8::FunctionCallbackArguments<'s>,
-            ) -> Result<#ret_ty, hoo_meta::TryFromJsValueError> {
-                let this_offset = 0;
+    let generated = quote!(
+        fn #rs_return_ident<'a, 's, 'b> (
+            scope: &'a mut v8::HandleScope<'s>,
+            args: &v8::FunctionCallbackArguments<'s>,
+        ) -> Result<#ret_ty, hoo_meta::TryFromJsValueError> {
+            #arity_check
 
-                let arg_count = args.length();
-                if arg_count != #arg_count {
-                    return Err(hoo_meta::TryFromJsValueError::new("incorrect number of arguments"));
-                }
+            #(#arguments_getter)*
 
-                #(#arguments_getter)*
+            let result = #call_path(#(#pats),*);
+            Ok(result)
+        }
+    );
+    Ok(generated)
+}
 
-                let result = #qualifier #ident(#(#pats),*);
-                Ok(result)
-            }
+// js 调用的回调。泛型函数的多个实例共用一个 js 名字时，先按 js 参数的类型选出实例，
+// 只转换这个实例的参数。qualifier 是参数转换函数的前缀，比如 Self::
+fn generate_js_callback(
+    callback_ident: &syn::Ident,
+    qualifier: proc_macro2::TokenStream,
+    instances: &[FJsInstance],
+    display_name: &str,
+) -> proc_macro2::TokenStream {
+    let throw_message = quote!(
+        let exception_msg = v8::String::new(scope, &message).unwrap().into();
+        let exception = v8::Exception::error(scope, exception_msg);
+        scope.throw_exception(exception);
+    );
 
-            
-            const #function_name_ident: &str = #function_name_str;
+    let call = |rs_return_ident: &syn::Ident| {
+        quote!(
+            match #qualifier #rs_return_ident(scope, &args) {
+                Ok(result) => {
+                    let result = hoo_meta::GetJsValue::get_js_value(&result, scope);
+                    match result {
+                        Err(e) => {
+                            let message = e.error_message();
+                            #throw_message
+                        },
+                        Ok(result) => {
+                            retval.set(result);
+                        }
+                    }
+                },
+                Err(err) => {
+                    let message = err.error_message();
+                    #throw_message
+                }
+            }
+        )
+    };
 
-            fn #new_ident_2<'a, 's, 'b> (
+    if let [instance] = instances {
+        let call = call(&instance.ident);
+        return quote!(
+            fn #callback_ident<'a, 's, 'b> (
                 scope: &'a mut v8::HandleScope<'s>,
                 args: v8::FunctionCallbackArguments<'s>,
                 mut retval: v8::ReturnValue<'b>,
             ) {
-                let result = #qualifier #new_ident_1(scope, args);
-
-                match result {
-                    Ok(result) => {
-                        let result = hoo_meta::GetJsValue::get_js_value(&result, scope);
-                        match result {
-                            Err(e) => {
-                                let exception_msg = v8::String::new(scope, &e.error_message()).unwrap().into();
-                                let exception = v8::Exception::error(scope, exception_msg);
-                                scope.throw_exception(exception);
-                            },
-                            Ok(result) => {
-                                retval.set(result);
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        let exception_msg = v8::String::new(scope, &err.error_message()).unwrap().into();
-                        let exception = v8::Exception::error(scope, exception_msg);
-                        scope.throw_exception(exception);
-                    }
-                }
+                #call
             }
         );
+    }
 
-        generated
-    } else {
-        let generated = quote!(
-            fn #new_ident_1<'a, 's, 'b> (
-                scope: &'a mut v8::HandleScope<'s>,
-                args: v8::FunctionCallbackArguments<'s>,
-            ) -> Result<#ret_ty, hoo_meta::TryFromJsValueError> {
-                let this_offset = 1;
+    // 宏展开时已经检查过实例之间不会冲突，最多只有一个实例符合
+    let branches = instances.iter().map(|instance| {
+        let check = instance.params.get_check();
+        let call = call(&instance.ident);
+        quote!(
+            if #check {
+                #call
+                return;
+            }
+        )
+    });
+    let expected = instances
+        .iter()
+        .map(|instance| format!("{}{}", instance.label, instance.params.get_description()))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let message_format = format!(
+        "{}: no instantiation accepts these arguments ({{}}), expected {}",
+        display_name, expected
+    );
 
-                // println
+    quote!(
+        fn #callback_ident<'a, 's, 'b> (
+            scope: &'a mut v8::HandleScope<'s>,
+            args: v8::FunctionCallbackArguments<'s>,
+            mut retval: v8::ReturnValue<'b>,
+        ) {
+            #(#branches)*
+
+            let received: Vec<String> = (0..args.length())
+                .map(|i| {
+                    let value = args.get(i);
+                    if value.is_array() {
+                        "array".to_string()
+                    } else {
+                        value.type_of(scope).to_rust_string_lossy(scope)
+                    }
+                })
+                .collect();
+            let message = format!(#message_format, received.join(", "));
+            #throw_message
+        }
+    )
+}
 
-                let arg_count = args.length();
-                if arg_count != #arg_count - this_offset {
-                    return Err(hoo_meta::TryFromJsValueError::new("incorrect number of arguments"));
-                }
+// 泛型函数的一个实例
+struct FJsInstance {
+    // 参数转换函数
+    ident: syn::Ident,
+    // 报错时显示的类型，比如 <f32>
+    label: String,
+    // 共用一个 js 名字时用来选择实例
+    params: FJsParams,
+}
+
+// 绑定到 js 上的一个名字，泛型函数的多个实例可以共用一个名字
+struct FJsBinding {
+    // 生成的回调用它命名
+    ident: syn::Ident,
+    js_name: String,
+    display_name: String,
+    instances: Vec<FJsInstance>,
+}
+
+// 为一个函数生成所有实例的参数转换函数（放进 funcs），返回要绑定的名字。
+// js_name_of 把 rust 名字（函数名或者 instantiate 里写的名字）转成 js 名字，
+// display_name_of 再把 js 名字转成报错信息里用的名字
+fn generate_js_bindings(
+    signature: &syn::Signature,
+    instantiate: &[(syn::Ident, Vec<syn::Type>)],
+    in_impl: bool,
+    js_name_of: impl Fn(&str) -> String,
+    display_name_of: impl Fn(&str) -> String,
+    funcs: &mut Vec<proc_macro2::TokenStream>,
+) -> syn::Result<Vec<FJsBinding>> {
+    let ident = &signature.ident;
+    let qualifier = if in_impl { quote!(Self::) } else { quote!() };
+
+    if instantiate.is_empty() {
+        if let Some(param) = signature.generics.type_params().next() {
+            return Err(syn::Error::new_spanned(
+                param,
+                "generic js functions must be instantiated, e.g. `instantiate(name = <f32>)`",
+            ));
+        }
+        check_signature(signature)?;
+
+        let js_name = js_name_of(&ident.to_string());
+        let display_name = display_name_of(&js_name);
+        let rs_return_ident = syn::Ident::new(
+            &format!(js_function_rs_return_format_string!(), ident),
+            proc_macro2::Span::call_site(),
+        );
+        funcs.push(generate_js_function(
+            signature,
+            quote!(#qualifier #ident),
+            &rs_return_ident,
+            &display_name,
+        )?);
+
+        return Ok(vec![FJsBinding {
+            ident: ident.clone(),
+            js_name,
+            display_name,
+            instances: vec![FJsInstance {
+                ident: rs_return_ident,
+                label: String::new(),
+                params: FJsParams::from_signature(signature)?,
+            }],
+        }]);
+    }
 
-                #(#arguments_getter)*
+    let mut bindings: Vec<FJsBinding> = vec![];
+    for (i, (name, types)) in instantiate.iter().enumerate() {
+        let instance_signature = instantiate_signature(signature, name, types)?;
+        check_signature(&instance_signature)?;
 
-                let result = Self::#ident(#(#pats),*);
-                Ok(result)
+        let js_name = js_name_of(&name.to_string());
+        let display_name = display_name_of(&js_name);
+        let rs_return_ident = syn::Ident::new(
+            &format!(js_function_rs_return_format_string!(), format!("{}__{}", ident, i)),
+            proc_macro2::Span::call_site(),
+        );
+        funcs.push(generate_js_function(
+            &instance_signature,
+            quote!(#qualifier #ident::<#(#types),*>),
+            &rs_return_ident,
+            &display_name,
+        )?);
+
+        let instance = FJsInstance {
+            ident: rs_return_ident,
+            label: format!("<{}>", quote!(#(#types),*)).replace(' ', ""),
+            params: FJsParams::from_signature(&instance_signature)?,
+        };
+        match bindings
+            .iter_mut()
+            .find(|binding| binding.js_name == js_name)
+        {
+            Some(binding) => {
+                // 按 js 类型分不出来的实例，调用时没法选择
+                if let Some(other) = binding
+                    .instances
+                    .iter()
+                    .find(|other| other.params.overlaps(&instance.params))
+                {
+                    return Err(syn::Error::new_spanned(
+                        name,
+                        format!(
+                            "instantiations {}{} and {}{} of `{}` can not be told apart by js argument types, give them different names",
+                            other.label,
+                            other.params.get_description(),
+                            instance.label,
+                            instance.params.get_description(),
+                            js_name
+                        ),
+                    ));
+                }
+                binding.instances.push(instance);
             }
+            None => bindings.push(FJsBinding {
+                ident: name.clone(),
+                js_name,
+                display_name,
+                instances: vec![instance],
+            }),
+        }
+    }
+    Ok(bindings)
+}
+
+fn get_argument_name(pat_type: &syn::PatType, js_index: i32) -> String {
+    match &*pat_type.pat {
+        syn::Pat::Ident(pat_ident) => format!("`{}`", pat_ident.ident),
+        _ => format!("{}", js_index + 1),
+    }
+}
+
+// 借用失败时报错而不是 panic，比如 &mut self 的方法里又回调到 js，js 又访问了同一个对象
+fn get_borrow_stmt(pat: &syn::Ident, is_mut: bool, message: &str) -> proc_macro2::TokenStream {
+    if is_mut {
+        quote!(
+            let mut #pat = #pat.try_borrow_mut().map_err(|_| hoo_meta::TryFromJsValueError::new(#message))?;
+        )
+    } else {
+        quote!(
+            let #pat = #pat.try_borrow().map_err(|_| hoo_meta::TryFromJsValueError::new(#message))?;
+        )
+    }
+}
+
+// &T / &mut T 参数：取到 RcObject<T> 后在调用期间借用它。
+// &str 和 &[T] 没法放进 RcObject，转成 String 和 Vec<T> 再借出来
+fn get_reference_argument(
+    reference: &syn::TypeReference,
+    pat: &syn::Ident,
+    js_index: i32,
+    argument_name: &str,
+    display_name: &str,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let elem = &reference.elem;
+    let is_mut = reference.mutability.is_some();
+
+    let owned_type = match &**elem {
+        syn::Type::Path(type_path) if type_path.path.is_ident("str") => Some(quote!(String)),
+        syn::Type::Slice(slice) => {
+            let elem = &slice.elem;
+            Some(quote!(Vec<#elem>))
+        }
+        _ => None,
+    };
+
+    if let Some(owned_type) = owned_type {
+        let mutability = if is_mut { quote!(mut) } else { quote!() };
+        let getter = quote!(
+            let #mutability #pat = <#owned_type as hoo_meta::TryFromJsValue>::try_from(scope, &args.get(#js_index))?;
         );
-        generated
+        let pass = if is_mut { quote!(&mut #pat[..]) } else { quote!(&#pat[..]) };
+        return (getter, pass);
+    }
+
+    let message = format!(
+        "{}: argument {} is already borrowed",
+        display_name, argument_name
+    );
+    let mut getter = quote!(
+        let #pat = <hoo_object::RcObject<#elem> as hoo_meta::TryFromJsValue>::try_from(scope, &args.get(#js_index))?;
+    );
+    getter.append_all(get_borrow_stmt(pat, is_mut, &message));
+    let pass = if is_mut { quote!(&mut *#pat) } else { quote!(&*#pat) };
+    (getter, pass)
+}
+
+fn is_type_named(ty: &syn::Type, name: &str) -> bool {
+    match ty {
+        syn::Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == name),
+        _ => false,
     }
 }
+
+fn get_arity_check(
+    display_name: &str,
+    min_arg_count: i32,
+    param_count: i32,
+    has_rest: bool,
+) -> proc_macro2::TokenStream {
+    let plural = |count: i32| if count == 1 { "argument" } else { "arguments" };
+
+    let (condition, expected) = if has_rest {
+        if min_arg_count == 0 {
+            return quote!();
+        }
+        (
+            quote!(arg_count < #min_arg_count),
+            format!("at least {} {}", min_arg_count, plural(min_arg_count)),
+        )
+    } else if min_arg_count == param_count {
+        (
+            quote!(arg_count != #param_count),
+            format!("{} {}", param_count, plural(param_count)),
+        )
+    } else {
+        (
+            quote!(arg_count < #min_arg_count || arg_count > #param_count),
+            format!("{} to {} arguments", min_arg_count, param_count),
+        )
+    };
+
+    let message = format!("{}: expected {}, got {{}}", display_name, expected);
+    quote!(
+        let arg_count = args.length();
+        if #condition {
+            return Err(hoo_meta::TryFromJsValueError::new(&format!(#message, arg_count)));
+        }
+    )
+}
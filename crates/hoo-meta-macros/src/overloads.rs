@@ -0,0 +1,254 @@
+// 泛型函数的多个实例共用一个 js 名字时，按 js 参数的类型选择实例。
+// 宏展开时算出每个实例的每个参数能接受的 js 类型，两个实例可能接受同一组参数时直接报错，
+// 调用时先检查类型选出唯一的实例，参数只转换一次
+
+use quote::quote;
+
+use crate::attributes::FJsAttributes;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EJsArgKind {
+    // 看不出来的类型（用户定义的类型、类型别名），和任何类型都可能冲突
+    Any,
+    Boolean,
+    Number,
+    String,
+    Array,
+    // 不是数组的 object，比如 RcObject、glm 的向量
+    Object,
+}
+
+impl EJsArgKind {
+    fn from_type(ty: &syn::Type) -> Self {
+        match ty {
+            syn::Type::Reference(reference) => match &*reference.elem {
+                syn::Type::Path(type_path) if type_path.path.is_ident("str") => Self::String,
+                syn::Type::Slice(_) => Self::Array,
+                // 从 RcObject<T> 借用
+                _ => Self::Object,
+            },
+            syn::Type::Slice(_) | syn::Type::Array(_) => Self::Array,
+            syn::Type::Paren(paren) => Self::from_type(&paren.elem),
+            syn::Type::Group(group) => Self::from_type(&group.elem),
+            syn::Type::Path(type_path) if type_path.qself.is_none() => {
+                let name = match type_path.path.segments.last() {
+                    Some(segment) => segment.ident.to_string(),
+                    None => return Self::Any,
+                };
+                match name.as_str() {
+                    "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64"
+                    | "usize" | "f32" | "f64" => Self::Number,
+                    "bool" => Self::Boolean,
+                    "String" | "char" => Self::String,
+                    "Vec" | "VecDeque" => Self::Array,
+                    "RcObject" | "Vec2" | "Vec3" | "Vec4" | "Quat" | "Mat4" => Self::Object,
+                    _ => Self::Any,
+                }
+            }
+            _ => Self::Any,
+        }
+    }
+
+    fn overlaps(self, other: Self) -> bool {
+        self == Self::Any || other == Self::Any || self == other
+    }
+
+    fn get_name(self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::Boolean => "boolean",
+            Self::Number => "number",
+            Self::String => "string",
+            Self::Array => "array",
+            Self::Object => "object",
+        }
+    }
+
+    fn get_check(self, value: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            Self::Any => quote!(true),
+            Self::Boolean => quote!(#value.is_boolean()),
+            Self::Number => quote!(#value.is_number()),
+            Self::String => quote!(#value.is_string()),
+            Self::Array => quote!(#value.is_array()),
+            Self::Object => quote!((#value.is_object() && !#value.is_array())),
+        }
+    }
+}
+
+// 参数不传（undefined）或者传 null 时是否接受
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ENullable {
+    No,
+    // #[js(default = ...)]
+    Undefined,
+    // Option<T>
+    NullOrUndefined,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FJsParam {
+    kind: EJsArgKind,
+    nullable: ENullable,
+}
+
+impl FJsParam {
+    fn overlaps(&self, other: &FJsParam) -> bool {
+        self.kind.overlaps(other.kind)
+            || (self.nullable != ENullable::No && other.nullable != ENullable::No)
+    }
+
+    fn get_name(&self) -> String {
+        match self.nullable {
+            ENullable::No => self.kind.get_name().to_string(),
+            _ => format!("{}?", self.kind.get_name()),
+        }
+    }
+}
+
+// 一个实例能接受的 js 参数，和 generate_js_function 里的参数个数检查一致
+pub(crate) struct FJsParams {
+    params: Vec<FJsParam>,
+    min_arg_count: usize,
+    // JsRest<T> 剩下的参数的类型
+    rest: Option<EJsArgKind>,
+}
+
+impl FJsParams {
+    pub(crate) fn from_signature(signature: &syn::Signature) -> syn::Result<Self> {
+        let mut params = vec![];
+        let mut min_arg_count = 0;
+        let mut rest = None;
+        for arg in signature.inputs.iter() {
+            let pat_type = match arg {
+                syn::FnArg::Typed(pat_type) => pat_type,
+                syn::FnArg::Receiver(_) => continue,
+            };
+            let ty = &*pat_type.ty;
+            if let Some(elem) = get_generic_argument(ty, "JsRest") {
+                rest = Some(EJsArgKind::from_type(elem));
+                continue;
+            }
+            let attributes = FJsAttributes::from_attrs(&pat_type.attrs)?;
+            let param = if attributes.default.is_some() {
+                FJsParam {
+                    kind: EJsArgKind::from_type(ty),
+                    nullable: ENullable::Undefined,
+                }
+            } else if let Some(inner) = get_generic_argument(ty, "Option") {
+                FJsParam {
+                    kind: EJsArgKind::from_type(inner),
+                    nullable: ENullable::NullOrUndefined,
+                }
+            } else {
+                min_arg_count = params.len() + 1;
+                FJsParam {
+                    kind: EJsArgKind::from_type(ty),
+                    nullable: ENullable::No,
+                }
+            };
+            params.push(param);
+        }
+        Ok(Self {
+            params,
+            min_arg_count,
+            rest,
+        })
+    }
+
+    fn get_param(&self, index: usize) -> Option<FJsParam> {
+        match self.params.get(index) {
+            Some(param) => Some(*param),
+            None => self.rest.map(|kind| FJsParam {
+                kind,
+                nullable: ENullable::No,
+            }),
+        }
+    }
+
+    // 是否存在一组参数两个实例都接受。参数个数取两边都允许的最小值，
+    // 更多的参数只会多出限制
+    pub(crate) fn overlaps(&self, other: &FJsParams) -> bool {
+        let count = self.min_arg_count.max(other.min_arg_count);
+        let accepts_count =
+            |params: &FJsParams| params.rest.is_some() || count <= params.params.len();
+        if !accepts_count(self) || !accepts_count(other) {
+            return false;
+        }
+        (0..count).all(|i| match (self.get_param(i), other.get_param(i)) {
+            (Some(a), Some(b)) => a.overlaps(&b),
+            _ => false,
+        })
+    }
+
+    // 报错信息里用，比如 (number, string?, ...array)
+    pub(crate) fn get_description(&self) -> String {
+        let mut names: Vec<String> = self.params.iter().map(FJsParam::get_name).collect();
+        if let Some(rest) = self.rest {
+            names.push(format!("...{}", rest.get_name()));
+        }
+        format!("({})", names.join(", "))
+    }
+
+    // 生成检查 args 是否符合这个实例的表达式
+    pub(crate) fn get_check(&self) -> proc_macro2::TokenStream {
+        let min_arg_count = self.min_arg_count as i32;
+        let count_check = match self.rest {
+            Some(_) => quote!(arg_count >= #min_arg_count),
+            None => {
+                let max_arg_count = self.params.len() as i32;
+                quote!(arg_count >= #min_arg_count && arg_count <= #max_arg_count)
+            }
+        };
+
+        let value = quote!(value);
+        let param_checks = self.params.iter().enumerate().map(|(i, param)| {
+            let i = i as i32;
+            let kind_check = param.kind.get_check(&value);
+            let nullable_check = match param.nullable {
+                ENullable::No => quote!(false),
+                ENullable::Undefined => quote!(value.is_undefined()),
+                ENullable::NullOrUndefined => quote!(value.is_null_or_undefined()),
+            };
+            quote!(&& {
+                let value = args.get(#i);
+                #nullable_check || #kind_check
+            })
+        });
+        let rest_check = self.rest.map(|kind| {
+            let start = self.params.len() as i32;
+            let kind_check = kind.get_check(&value);
+            quote!(&& (#start..arg_count).all(|i| {
+                let value = args.get(i);
+                #kind_check
+            }))
+        });
+
+        quote!(
+            {
+                let arg_count = args.length();
+                #count_check #(#param_checks)* #rest_check
+            }
+        )
+    }
+}
+
+// Option<T> 里的 T
+fn get_generic_argument<'a>(ty: &'a syn::Type, name: &str) -> Option<&'a syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(type_path) => type_path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != name {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => {
+            arguments.args.iter().find_map(|argument| match argument {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+        }
+        _ => None,
+    }
+}
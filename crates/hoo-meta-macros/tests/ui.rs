@@ -0,0 +1,8 @@
+// 宏的报错信息测试，期望输出在 tests/ui/*.stderr
+// 修改报错信息后用 TRYBUILD=overwrite cargo test 重新生成
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
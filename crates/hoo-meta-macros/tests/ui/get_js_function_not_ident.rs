@@ -0,0 +1,5 @@
+use hoo_meta_macros::get_js_function;
+
+fn main() {
+    let _ = get_js_function!("succ");
+}
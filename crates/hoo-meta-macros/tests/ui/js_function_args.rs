@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function(name = "add")]
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+async fn load(path: String) -> String {
+    path
+}
+
+fn main() {}
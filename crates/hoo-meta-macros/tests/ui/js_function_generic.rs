@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+fn identity<T>(value: T) -> T {
+    value
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+fn sum(values: Vec<impl Into<i32>>) -> i32 {
+    values.into_iter().map(Into::into).sum()
+}
+
+fn main() {}
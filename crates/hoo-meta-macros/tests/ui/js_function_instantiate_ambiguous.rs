@@ -0,0 +1,9 @@
+use hoo_meta_macros::js_function;
+
+// f32 和 f64 在 js 里都是 number，调用时分不出来
+#[js_function(instantiate(scale = <f32>, scale = <f64>))]
+fn scale<T: std::ops::Mul<Output = T> + Copy>(value: T, factor: T) -> T {
+    value * factor
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function(instantiate(pair_f32 = <f32, f32>))]
+fn pair<T: Copy>(value: T) -> (T, T) {
+    (value, value)
+}
+
+fn main() {}
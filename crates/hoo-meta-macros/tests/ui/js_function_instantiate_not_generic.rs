@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function(instantiate(add_f32 = <f32>))]
+fn add(a: f64, b: f64) -> f64 {
+    a + b
+}
+
+fn main() {}
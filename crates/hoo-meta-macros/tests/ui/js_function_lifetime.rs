@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+fn first_word(text: &'static str) -> i32 {
+    text.len() as i32
+}
+
+fn main() {}
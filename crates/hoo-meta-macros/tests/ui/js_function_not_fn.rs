@@ -0,0 +1,6 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+struct NotAFunction;
+
+fn main() {}
@@ -0,0 +1,8 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+fn add(a: i32, #[js(rename = "other")] b: i32) -> i32 {
+    a + b
+}
+
+fn main() {}
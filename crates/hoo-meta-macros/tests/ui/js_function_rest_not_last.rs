@@ -0,0 +1,10 @@
+use hoo_meta_macros::js_function;
+
+struct JsRest<T>(Vec<T>);
+
+#[js_function]
+fn sum(values: JsRest<i32>, scale: i32) -> i32 {
+    values.0.iter().sum::<i32>() * scale
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use hoo_meta_macros::js_function;
+
+#[js_function]
+fn length(value: Vec<i32>) -> usize
+where
+    Vec<i32>: Clone,
+{
+    value.len()
+}
+
+fn main() {}
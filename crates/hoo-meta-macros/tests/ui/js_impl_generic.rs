@@ -0,0 +1,14 @@
+use hoo_meta_macros::js_impl;
+
+struct Wrapper<T> {
+    value: T,
+}
+
+#[js_impl]
+impl<T> Wrapper<T> {
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(getter)]
+    pub fn value(&self, offset: i32) -> i32 {
+        self.value + offset
+    }
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(getter, instantiate(value_i64 = <i64>))]
+    pub fn value<T: From<i32>>(&self) -> T {
+        T::from(self.value)
+    }
+}
+
+fn main() {}
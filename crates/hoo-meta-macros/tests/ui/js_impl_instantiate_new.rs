@@ -0,0 +1,15 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(instantiate(new_i32 = <i32>))]
+    pub fn new<T: Into<i32>>(value: T) -> Self {
+        Self { value: value.into() }
+    }
+}
+
+fn main() {}
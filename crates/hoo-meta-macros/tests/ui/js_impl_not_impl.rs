@@ -0,0 +1,6 @@
+use hoo_meta_macros::js_impl;
+
+#[js_impl]
+fn not_an_impl() {}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    pub fn get<'a>(&'a self) -> i32 {
+        self.value
+    }
+}
+
+fn main() {}
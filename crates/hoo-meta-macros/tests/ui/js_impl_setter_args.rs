@@ -0,0 +1,20 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(getter)]
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    #[js(setter)]
+    pub fn set_value(&mut self, value: i32, offset: i32) {
+        self.value = value + offset;
+    }
+}
+
+fn main() {}
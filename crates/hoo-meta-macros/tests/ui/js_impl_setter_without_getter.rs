@@ -0,0 +1,15 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(setter)]
+    pub fn set_value(&mut self, value: i32) {
+        self.value = value;
+    }
+}
+
+fn main() {}
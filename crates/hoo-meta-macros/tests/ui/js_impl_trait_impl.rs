@@ -0,0 +1,14 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Clone for Counter {
+    fn clone(&self) -> Self {
+        Counter { value: self.value }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use hoo_meta_macros::js_impl;
+
+struct Counter {
+    value: i32,
+}
+
+#[js_impl]
+impl Counter {
+    #[js(hidden)]
+    pub fn get(&self) -> i32 {
+        self.value
+    }
+}
+
+fn main() {}
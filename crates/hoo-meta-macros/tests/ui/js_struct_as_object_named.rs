@@ -0,0 +1,10 @@
+use hoo_meta_macros::JsStructNoConstructor;
+
+#[derive(JsStructNoConstructor)]
+#[js(as_object)]
+struct Pair {
+    pub x: i32,
+    pub y: i32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use hoo_meta_macros::JsStruct;
+
+#[derive(JsStruct)]
+enum Direction {
+    Left,
+    Right,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use hoo_meta_macros::JsStruct;
+
+#[derive(JsStruct)]
+struct Label {
+    pub text: &'static str,
+}
+
+fn main() {}
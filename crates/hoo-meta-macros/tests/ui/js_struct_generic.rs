@@ -0,0 +1,8 @@
+use hoo_meta_macros::JsStruct;
+
+#[derive(JsStruct)]
+struct Wrapper<T> {
+    pub value: T,
+}
+
+fn main() {}
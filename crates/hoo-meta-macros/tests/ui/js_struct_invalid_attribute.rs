@@ -0,0 +1,10 @@
+use hoo_meta_macros::JsStruct;
+
+#[derive(JsStruct)]
+struct Pair {
+    #[js(rename = 1)]
+    pub x: i32,
+    pub y: i32,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use hoo_meta_macros::JsStructNoConstructor;
+
+#[derive(JsStructNoConstructor)]
+struct EntityId(#[js(skip)] pub u32);
+
+fn main() {}
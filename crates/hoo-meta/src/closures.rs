@@ -0,0 +1,122 @@
+// 让 ModuleLikeBuilder::add_closure 可以绑定捕获了状态的闭包。
+// 闭包擦除类型后先由 HooMetaContextBuilder 保管，context 创建好之后放进 context 的 slot，
+// js 函数通过 v8::External 拿到它的地址。slot 在 context 被 GC（最晚是 isolate 销毁）时释放；
+// js 函数引用着创建它的 context，所以函数被别的 context 拿走时，context 和闭包也不会被释放。
+// snapshot 里不能有闭包。
+
+use crate::traits::*;
+use crate::types::*;
+
+pub struct FJsClosure(
+    Box<
+        dyn for<'s> Fn(
+            &mut v8::HandleScope<'s>,
+            &v8::FunctionCallbackArguments<'s>,
+        ) -> Result<v8::Local<'s, v8::Value>, JsException>,
+    >,
+);
+
+fn erase_closure<F>(closure: F) -> FJsClosure
+where
+    F: for<'s> Fn(
+            &mut v8::HandleScope<'s>,
+            &v8::FunctionCallbackArguments<'s>,
+        ) -> Result<v8::Local<'s, v8::Value>, JsException>
+        + 'static,
+{
+    FJsClosure(Box::new(closure))
+}
+
+// 一个 context 的所有闭包。Box 固定了地址，移动 Vec 不影响 js 函数里记下的地址
+struct FContextClosures(Vec<Box<FJsClosure>>);
+
+// 创建 context 之后调用，闭包从此和 context 一起释放
+pub(crate) fn attach_closures(
+    isolate: &mut v8::Isolate,
+    context: v8::Local<v8::Context>,
+    closures: Vec<Box<FJsClosure>>,
+) {
+    if !closures.is_empty() {
+        context.set_slot(isolate, FContextClosures(closures));
+    }
+}
+
+// Args 只用来区分不同参数个数的实现。name 用在错误信息里
+pub trait IntoJsClosure<Args>: 'static {
+    fn into_js_closure(self, name: &str) -> FJsClosure;
+}
+
+macro_rules! count_one {
+    ($arg: ident) => {
+        1
+    };
+}
+
+macro_rules! impl_into_js_closure {
+    ($($arg: ident),*) => {
+        impl<F, R, $($arg),*> IntoJsClosure<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: GetJsValue,
+            $($arg: TryFromJsValue,)*
+        {
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn into_js_closure(self, name: &str) -> FJsClosure {
+                let name = name.to_string();
+                erase_closure(move |scope, args| {
+                    let arg_count: i32 = 0 $(+ count_one!($arg))*;
+                    if args.length() != arg_count {
+                        let plural = if arg_count == 1 { "argument" } else { "arguments" };
+                        return Err(JsException::new(&format!(
+                            "{}: expected {} {}, got {}",
+                            name,
+                            arg_count,
+                            plural,
+                            args.length()
+                        )));
+                    }
+
+                    let mut index = 0;
+                    $(
+                        let $arg = <$arg as TryFromJsValue>::try_from(scope, &args.get(index))
+                            .map_err(|err| JsException::new(&err.error_message()))?;
+                        index += 1;
+                    )*
+
+                    let result = self($($arg),*);
+                    result.get_js_value(scope)
+                })
+            }
+        }
+    };
+}
+
+impl_into_js_closure!();
+impl_into_js_closure!(A1);
+impl_into_js_closure!(A1, A2);
+impl_into_js_closure!(A1, A2, A3);
+impl_into_js_closure!(A1, A2, A3, A4);
+impl_into_js_closure!(A1, A2, A3, A4, A5);
+impl_into_js_closure!(A1, A2, A3, A4, A5, A6);
+impl_into_js_closure!(A1, A2, A3, A4, A5, A6, A7);
+impl_into_js_closure!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+// 所有闭包共用这一个回调，闭包本身放在 function 的 data 里
+pub(crate) fn closure_callback<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    args: v8::FunctionCallbackArguments<'s>,
+    mut retval: v8::ReturnValue,
+) {
+    let data = v8::Local::<v8::External>::try_from(args.data()).unwrap();
+    // 闭包由创建函数的 context 持有，能调用到这里说明 context 还活着
+    let closure = unsafe { &*(data.value() as *const FJsClosure) };
+
+    match (closure.0)(scope, &args) {
+        Ok(result) => retval.set(result),
+        Err(err) => {
+            let exception_msg = v8::String::new(scope, &err.error_message()).unwrap();
+            let exception = v8::Exception::error(scope, exception_msg);
+            scope.throw_exception(exception);
+        }
+    }
+}
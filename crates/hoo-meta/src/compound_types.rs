@@ -12,3 +12,100 @@ impl<OT: GetJsValue, ET: ToString> GetJsValue for Result<OT, ET> {
         }
     }
 }
+
+// Option<T>: None <=> null / undefined
+
+impl<T: GetJsValue> GetJsValue for Option<T> {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        match self {
+            Some(i) => GetJsValue::get_js_value(i, scope),
+            None => Ok(v8::null(scope).into()),
+        }
+    }
+}
+
+impl<T: TryFromJsValue> TryFromJsValue for Option<T> {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        if val.is_null_or_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(T::try_from(scope, val)?))
+        }
+    }
+}
+
+// Vec<T> <=> Array
+
+impl<T: GetJsValue> GetJsValue for Vec<T> {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        let mut elements = Vec::with_capacity(self.len());
+        for i in self.iter() {
+            elements.push(GetJsValue::get_js_value(i, scope)?);
+        }
+        Ok(v8::Array::new_with_elements(scope, &elements).into())
+    }
+}
+
+impl<T: TryFromJsValue> TryFromJsValue for Vec<T> {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let array = v8::Local::<v8::Array>::try_from(*val)
+            .or(Err(TryFromJsValueError::new("not an array")))?;
+
+        let mut out = Vec::with_capacity(array.length() as usize);
+        for i in 0..array.length() {
+            let element = array
+                .get_index(scope, i)
+                .ok_or(TryFromJsValueError::new(&format!("no element at {}", i)))?;
+            out.push(T::try_from(scope, &element)?);
+        }
+        Ok(out)
+    }
+}
+
+// JsRest<T>: 函数最后一个参数，接收剩下的所有参数，相当于 js 的 ...rest
+
+pub struct JsRest<T>(pub Vec<T>);
+
+impl<T: TryFromJsValue> JsRest<T> {
+    // 给 #[js_function] 生成的代码用，从第 start 个参数开始收集
+    pub fn from_args<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        args: &v8::FunctionCallbackArguments<'s>,
+        start: i32,
+    ) -> Result<Self, TryFromJsValueError> {
+        let mut out = Vec::with_capacity((args.length() - start).max(0) as usize);
+        for i in start..args.length() {
+            out.push(T::try_from(scope, &args.get(i))?);
+        }
+        Ok(JsRest(out))
+    }
+}
+
+impl<T> std::ops::Deref for JsRest<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> IntoIterator for JsRest<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
@@ -0,0 +1,189 @@
+use crate::traits::*;
+use crate::types::*;
+
+use nalgebra_glm as glm;
+
+// 向量和四元数转换为 {x, y, z(, w)} 形式的普通 js object
+// 矩阵转换为 column-major 的数组，和 glm 的内存布局一致
+
+fn get_f32_field<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    object: &v8::Local<'a, v8::Object>,
+    name: &str,
+) -> Result<f32, TryFromJsValueError> {
+    let key = v8::String::new(scope, name).unwrap();
+    let value = object
+        .get(scope, key.into())
+        .ok_or(TryFromJsValueError::new(&format!("field does not exist: {}", name)))?;
+    if !value.is_number() {
+        return Err(TryFromJsValueError::new(&format!(
+            "field is not a number: {}",
+            name
+        )));
+    }
+    <f32 as TryFromJsValue>::try_from(scope, &value)
+}
+
+fn new_object_with_f32_fields<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    fields: &[(&str, f32)],
+) -> v8::Local<'a, v8::Value> {
+    let object = v8::Object::new(scope);
+    for (name, value) in fields {
+        let key = v8::String::new(scope, name).unwrap();
+        let value = v8::Number::new(scope, *value as f64);
+        object.set(scope, key.into(), value.into());
+    }
+    object.into()
+}
+
+fn to_object<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    val: &v8::Local<'a, v8::Value>,
+) -> Result<v8::Local<'a, v8::Object>, TryFromJsValueError> {
+    if !val.is_object() {
+        return Err(TryFromJsValueError::new("not an object"));
+    }
+    val.to_object(scope)
+        .ok_or(TryFromJsValueError::new("not an object"))
+}
+
+// Vec2
+
+impl TryFromJsValue for glm::Vec2 {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = to_object(scope, val)?;
+        Ok(glm::vec2(
+            get_f32_field(scope, &object, "x")?,
+            get_f32_field(scope, &object, "y")?,
+        ))
+    }
+}
+
+impl GetJsValue for glm::Vec2 {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        Ok(new_object_with_f32_fields(
+            scope,
+            &[("x", self.x), ("y", self.y)],
+        ))
+    }
+}
+
+// Vec3
+
+impl TryFromJsValue for glm::Vec3 {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = to_object(scope, val)?;
+        Ok(glm::vec3(
+            get_f32_field(scope, &object, "x")?,
+            get_f32_field(scope, &object, "y")?,
+            get_f32_field(scope, &object, "z")?,
+        ))
+    }
+}
+
+impl GetJsValue for glm::Vec3 {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        Ok(new_object_with_f32_fields(
+            scope,
+            &[("x", self.x), ("y", self.y), ("z", self.z)],
+        ))
+    }
+}
+
+// Vec4
+
+impl TryFromJsValue for glm::Vec4 {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = to_object(scope, val)?;
+        Ok(glm::vec4(
+            get_f32_field(scope, &object, "x")?,
+            get_f32_field(scope, &object, "y")?,
+            get_f32_field(scope, &object, "z")?,
+            get_f32_field(scope, &object, "w")?,
+        ))
+    }
+}
+
+impl GetJsValue for glm::Vec4 {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        Ok(new_object_with_f32_fields(
+            scope,
+            &[("x", self.x), ("y", self.y), ("z", self.z), ("w", self.w)],
+        ))
+    }
+}
+
+// Quat
+
+impl TryFromJsValue for glm::Quat {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = to_object(scope, val)?;
+        Ok(glm::quat(
+            get_f32_field(scope, &object, "x")?,
+            get_f32_field(scope, &object, "y")?,
+            get_f32_field(scope, &object, "z")?,
+            get_f32_field(scope, &object, "w")?,
+        ))
+    }
+}
+
+impl GetJsValue for glm::Quat {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        Ok(new_object_with_f32_fields(
+            scope,
+            &[("x", self.i), ("y", self.j), ("z", self.k), ("w", self.w)],
+        ))
+    }
+}
+
+// Mat4
+
+impl TryFromJsValue for glm::Mat4 {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let elements = <Vec<f32> as TryFromJsValue>::try_from(scope, val)?;
+        if elements.len() != 16 {
+            return Err(TryFromJsValueError::new(&format!(
+                "a mat4 needs 16 elements, got {}",
+                elements.len()
+            )));
+        }
+        Ok(glm::make_mat4(&elements))
+    }
+}
+
+impl GetJsValue for glm::Mat4 {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        self.as_slice().to_vec().get_js_value(scope)
+    }
+}
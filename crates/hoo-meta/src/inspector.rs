@@ -0,0 +1,612 @@
+// 接入 V8 inspector，通过本地 WebSocket 提供 Chrome DevTools Protocol，
+// Chrome (chrome://inspect) 和 VS Code 都可以直接 attach。
+//
+// 网络部分在后台线程，v8 相关的部分都在持有 isolate 的线程上：
+// 引擎每帧调用 HooInspector::poll 处理消息；脚本停在断点上时，
+// run_message_loop_on_pause 会阻塞当前线程，只处理调试消息，直到继续执行。
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use v8::inspector::*;
+
+const CONTEXT_GROUP_ID: i32 = 1;
+
+// 引擎自己发给 inspector 的消息用这个 id，对应的回复不转发给调试器
+const INTERNAL_CALL_ID: i32 = i32::MAX;
+
+// 服务线程多久检查一次是否要退出
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EPauseOnExceptions {
+    None,
+    Uncaught,
+    All,
+}
+
+impl EPauseOnExceptions {
+    fn protocol_state(&self) -> &'static str {
+        match self {
+            EPauseOnExceptions::None => "none",
+            EPauseOnExceptions::Uncaught => "uncaught",
+            EPauseOnExceptions::All => "all",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FInspectorOptions {
+    // 0 表示随便选一个空闲端口
+    pub port: u16,
+    pub pause_on_exceptions: EPauseOnExceptions,
+    // 调试器点了暂停时，不等下一段脚本执行，直接在 poll 里停住整个帧循环
+    pub pause_blocks_frame_loop: bool,
+}
+
+impl Default for FInspectorOptions {
+    fn default() -> Self {
+        Self {
+            port: 9229,
+            pause_on_exceptions: EPauseOnExceptions::Uncaught,
+            pause_blocks_frame_loop: true,
+        }
+    }
+}
+
+enum EInspectorEvent {
+    Connected(u32, mpsc::Sender<String>),
+    Message(u32, String),
+    Disconnected(u32),
+}
+
+struct FInspectorSession {
+    channel: ChannelBase,
+    session: Option<v8::UniqueRef<V8InspectorSession>>,
+    outgoing: mpsc::Sender<String>,
+}
+
+impl FInspectorSession {
+    fn new(inspector: &mut V8Inspector, outgoing: mpsc::Sender<String>) -> Box<Self> {
+        // v8 持有 channel 的指针，先放进 Box 固定地址再 connect
+        let mut session = Box::new(Self {
+            channel: ChannelBase::new::<Self>(),
+            session: None,
+            outgoing,
+        });
+        let channel = &mut *session as *mut Self;
+        session.session = Some(inspector.connect(
+            CONTEXT_GROUP_ID,
+            unsafe { &mut *channel },
+            StringView::empty(),
+            V8InspectorClientTrustLevel::FullyTrusted,
+        ));
+        session
+    }
+
+    // 可能重入：dispatch 过程中脚本暂停，会在 run_message_loop_on_pause 里继续 dispatch
+    fn dispatch(this: *mut Self, message: &str) {
+        let session = unsafe { (*this).session.as_mut().unwrap() };
+        session.dispatch_protocol_message(StringView::from(message.as_bytes()));
+    }
+
+    fn schedule_pause_on_next_statement(&mut self) {
+        let reason = StringView::from(&b"debugCommand"[..]);
+        let detail = StringView::from(&b"{}"[..]);
+        self.session
+            .as_mut()
+            .unwrap()
+            .schedule_pause_on_next_statement(reason, detail);
+    }
+
+    fn send(&mut self, message: v8::UniquePtr<StringBuffer>) {
+        let message = message.unwrap().string().to_string();
+        // 发送失败说明连接已经断开，Disconnected 事件会随后处理
+        let _ = self.outgoing.send(message);
+    }
+}
+
+impl ChannelImpl for FInspectorSession {
+    fn base(&self) -> &ChannelBase {
+        &self.channel
+    }
+
+    unsafe fn base_ptr(this: *const Self) -> *const ChannelBase
+    where
+        Self: Sized,
+    {
+        std::ptr::addr_of!((*this).channel)
+    }
+
+    fn base_mut(&mut self) -> &mut ChannelBase {
+        &mut self.channel
+    }
+
+    fn send_response(&mut self, call_id: i32, message: v8::UniquePtr<StringBuffer>) {
+        if call_id != INTERNAL_CALL_ID {
+            self.send(message);
+        }
+    }
+
+    fn send_notification(&mut self, message: v8::UniquePtr<StringBuffer>) {
+        self.send(message);
+    }
+
+    fn flush_protocol_notifications(&mut self) {}
+}
+
+struct FInspectorClient {
+    base: V8InspectorClientBase,
+    inspector: *mut V8Inspector,
+    events: mpsc::Receiver<EInspectorEvent>,
+    sessions: HashMap<u32, Box<FInspectorSession>>,
+    // 断开的连接不能在 dispatch 途中销毁，等回到 poll 再统一清理
+    closed_sessions: Vec<u32>,
+    pause_on_exceptions: EPauseOnExceptions,
+    paused: bool,
+    pause_requested: bool,
+    waiting_for_debugger: bool,
+}
+
+impl FInspectorClient {
+    fn handle_event(&mut self, event: EInspectorEvent) {
+        match event {
+            EInspectorEvent::Connected(session_id, outgoing) => {
+                let session = FInspectorSession::new(unsafe { &mut *self.inspector }, outgoing);
+                self.sessions.insert(session_id, session);
+            }
+            EInspectorEvent::Message(session_id, message) => {
+                let session = match self.sessions.get_mut(&session_id) {
+                    Some(session) => &mut **session as *mut FInspectorSession,
+                    None => return,
+                };
+                let method = get_protocol_method(&message);
+
+                if method.as_deref() == Some("Debugger.pause") {
+                    self.pause_requested = true;
+                }
+
+                FInspectorSession::dispatch(session, &message);
+
+                if method.as_deref() == Some("Debugger.enable") {
+                    // 调试器自己之后再发 setPauseOnExceptions 的话，以调试器的为准
+                    let message = format!(
+                        r#"{{"id":{},"method":"Debugger.setPauseOnExceptions","params":{{"state":"{}"}}}}"#,
+                        INTERNAL_CALL_ID,
+                        self.pause_on_exceptions.protocol_state()
+                    );
+                    FInspectorSession::dispatch(session, &message);
+                }
+            }
+            EInspectorEvent::Disconnected(session_id) => {
+                // 调试器断开时如果还停着，就继续执行，否则引擎会一直卡住
+                if self.paused {
+                    if let Some(session) = self.sessions.get_mut(&session_id) {
+                        let session = &mut **session as *mut FInspectorSession;
+                        let message =
+                            format!(r#"{{"id":{},"method":"Debugger.resume"}}"#, INTERNAL_CALL_ID);
+                        FInspectorSession::dispatch(session, &message);
+                    }
+                }
+                self.closed_sessions.push(session_id);
+            }
+        }
+    }
+
+    fn handle_pending_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            self.handle_event(event);
+        }
+    }
+
+    fn remove_closed_sessions(&mut self) {
+        for session_id in std::mem::take(&mut self.closed_sessions) {
+            self.sessions.remove(&session_id);
+        }
+    }
+}
+
+impl V8InspectorClientImpl for FInspectorClient {
+    fn base(&self) -> &V8InspectorClientBase {
+        &self.base
+    }
+
+    unsafe fn base_ptr(this: *const Self) -> *const V8InspectorClientBase
+    where
+        Self: Sized,
+    {
+        std::ptr::addr_of!((*this).base)
+    }
+
+    fn base_mut(&mut self) -> &mut V8InspectorClientBase {
+        &mut self.base
+    }
+
+    fn run_message_loop_on_pause(&mut self, _context_group_id: i32) {
+        self.paused = true;
+        while self.paused {
+            match self.events.recv() {
+                Ok(event) => self.handle_event(event),
+                // 服务线程没了，不可能再收到 resume
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn quit_message_loop_on_pause(&mut self) {
+        self.paused = false;
+    }
+
+    fn run_if_waiting_for_debugger(&mut self, _context_group_id: i32) {
+        self.waiting_for_debugger = false;
+    }
+}
+
+// 服务线程的句柄。停止时通知线程退出并等它结束，监听的端口随之释放
+struct FInspectorServerHandle {
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FInspectorServerHandle {
+    fn stop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for FInspectorServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub struct HooInspector {
+    inspector: v8::UniqueRef<V8Inspector>,
+    // v8 持有 client 的指针，必须固定地址
+    client: Box<FInspectorClient>,
+    server: FInspectorServerHandle,
+    address: SocketAddr,
+    target_id: String,
+    options: FInspectorOptions,
+}
+
+impl HooInspector {
+    pub fn new(
+        scope: &mut v8::HandleScope,
+        context: v8::Local<v8::Context>,
+        options: FInspectorOptions,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", options.port))?;
+        // 不阻塞在 accept 上，才能及时发现要退出
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let target_id = generate_target_id();
+
+        let (event_sender, events) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let server = FInspectorServer {
+            address,
+            target_id: target_id.clone(),
+            events: event_sender,
+            shutdown: shutdown.clone(),
+        };
+        let thread = std::thread::Builder::new()
+            .name("hoo-inspector".into())
+            .spawn(move || server.serve(listener))?;
+        let server = FInspectorServerHandle {
+            shutdown,
+            thread: Some(thread),
+        };
+
+        let mut client = Box::new(FInspectorClient {
+            base: V8InspectorClientBase::new::<FInspectorClient>(),
+            inspector: std::ptr::null_mut(),
+            events,
+            sessions: HashMap::new(),
+            closed_sessions: vec![],
+            pause_on_exceptions: options.pause_on_exceptions,
+            paused: false,
+            pause_requested: false,
+            waiting_for_debugger: false,
+        });
+        let mut inspector = V8Inspector::create(scope, &mut *client);
+        client.inspector = &mut *inspector as *mut V8Inspector;
+
+        let name = StringView::from(&b"hoo-engine"[..]);
+        let aux_data = StringView::from(&br#"{"isDefault": true}"#[..]);
+        inspector.context_created(context, CONTEXT_GROUP_ID, name, aux_data);
+
+        Ok(Self {
+            inspector,
+            client,
+            server,
+            address,
+            target_id,
+            options,
+        })
+    }
+
+    pub fn get_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn get_websocket_url(&self) -> String {
+        format!("ws://{}/{}", self.address, self.target_id)
+    }
+
+    pub fn get_devtools_url(&self) -> String {
+        format!(
+            "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}/{}",
+            self.address, self.target_id
+        )
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.client.paused
+    }
+
+    // 停止服务线程、断开所有调试器并释放端口。drop 时会自动调用，
+    // 不能正常 drop 的时候（比如 isolate 已经销毁，只能 leak）要手动调用
+    pub fn stop_server(&mut self) {
+        self.server.stop();
+    }
+
+    // 阻塞直到调试器 attach 并发出 Runtime.runIfWaitingForDebugger，
+    // 然后在下一条语句上暂停（相当于 node 的 --inspect-brk）
+    pub fn wait_for_debugger(&mut self) {
+        self.client.waiting_for_debugger = true;
+        while self.client.waiting_for_debugger {
+            match self.client.events.recv() {
+                Ok(event) => self.client.handle_event(event),
+                Err(_) => return,
+            }
+        }
+        self.client.remove_closed_sessions();
+
+        for session in self.client.sessions.values_mut() {
+            session.schedule_pause_on_next_statement();
+        }
+    }
+
+    // 每帧调用一次，处理调试器发来的消息，不会阻塞（除非要求了暂停帧循环）
+    pub fn poll(&mut self, scope: &mut v8::HandleScope) {
+        self.client.handle_pending_events();
+        self.client.remove_closed_sessions();
+
+        if self.client.pause_requested && self.options.pause_blocks_frame_loop {
+            self.client.pause_requested = false;
+
+            // v8 只会在执行 js 的时候暂停，执行一段空脚本让它停在这里，
+            // run_message_loop_on_pause 返回之前帧循环都不会继续
+            let scope = &mut v8::TryCatch::new(scope);
+            let source = v8::String::new(scope, "undefined").unwrap();
+            let origin = crate::modules::create_script_origin(scope, "hoo://frame-loop", false);
+            if let Some(script) = v8::Script::compile(scope, source, Some(&origin)) {
+                script.run(scope);
+            }
+        }
+        self.client.pause_requested = false;
+    }
+}
+
+impl Drop for HooInspector {
+    fn drop(&mut self) {
+        // session 必须先于 inspector 销毁
+        self.client.sessions.clear();
+        self.server.stop();
+    }
+}
+
+fn get_protocol_method(message: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(message).ok()?;
+    value.get("method")?.as_str().map(|method| method.to_string())
+}
+
+// 形如 uuid 的随机 id，只用来避免别的网页随便连上来
+fn generate_target_id() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = || RandomState::new().build_hasher().finish();
+    let hex = format!("{:016x}{:016x}", random(), random());
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// 在后台线程上运行：应答 DevTools 的 http 发现请求，并为每个 WebSocket 连接开一个线程
+struct FInspectorServer {
+    address: SocketAddr,
+    target_id: String,
+    events: mpsc::Sender<EInspectorEvent>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl FInspectorServer {
+    fn serve(self, listener: TcpListener) {
+        let mut next_session_id = 0;
+
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                // 没有新连接（WouldBlock）或者连接出错，都等一会儿再检查
+                Err(_) => {
+                    std::thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                    continue;
+                }
+            };
+            // 有的平台上连接会继承监听 socket 的非阻塞设置
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+
+            let path = match peek_request_path(&stream) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            if path == format!("/{}", self.target_id) {
+                next_session_id += 1;
+                let session_id = next_session_id;
+                let events = self.events.clone();
+                let shutdown = self.shutdown.clone();
+                let _ = std::thread::Builder::new()
+                    .name(format!("hoo-inspector-session-{}", session_id))
+                    .spawn(move || run_session(stream, session_id, events, shutdown));
+            } else {
+                self.respond_http(stream, &path);
+            }
+        }
+    }
+
+    fn respond_http(&self, mut stream: TcpStream, path: &str) {
+        // 先把请求读完，这里的请求都不带 body
+        let mut request = vec![];
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => request.extend_from_slice(&buffer[..n]),
+            }
+        }
+
+        let body = match path {
+            "/json" | "/json/list" => Some(self.target_list()),
+            "/json/version" => Some(
+                serde_json::json!({
+                    "Browser": "hoo-engine",
+                    "Protocol-Version": "1.3",
+                    "V8-Version": v8::V8::get_version(),
+                })
+                .to_string(),
+            ),
+            _ => None,
+        };
+
+        let response = match body {
+            Some(body) => format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=UTF-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+            None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into(),
+        };
+        let _ = stream.write_all(response.as_bytes());
+    }
+
+    fn target_list(&self) -> String {
+        let websocket_address = format!("{}/{}", self.address, self.target_id);
+        serde_json::json!([{
+            "description": "hoo-engine",
+            "devtoolsFrontendUrl": format!(
+                "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}",
+                websocket_address
+            ),
+            "id": self.target_id,
+            "title": "hoo-engine",
+            "type": "node",
+            "url": "hoo://engine",
+            "webSocketDebuggerUrl": format!("ws://{}", websocket_address),
+        }])
+        .to_string()
+    }
+}
+
+// 只看不取，WebSocket 握手还要用到完整的请求
+fn peek_request_path(stream: &TcpStream) -> Option<String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+
+    let mut buffer = [0u8; 2048];
+    let mut length = 0;
+    for _ in 0..100 {
+        length = stream.peek(&mut buffer).ok()?;
+        if length == 0 || buffer[..length].windows(2).any(|window| window == b"\r\n") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let request = String::from_utf8_lossy(&buffer[..length]);
+    let request_line = request.lines().next()?;
+    let mut parts = request_line.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+    let path = parts.next()?;
+    Some(path.split('?').next().unwrap_or(path).to_string())
+}
+
+fn run_session(
+    stream: TcpStream,
+    session_id: u32,
+    events: mpsc::Sender<EInspectorEvent>,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut websocket = match tungstenite::accept(stream) {
+        Ok(websocket) => websocket,
+        Err(_) => return,
+    };
+
+    // 读带超时，这样同一个线程里也能及时把 inspector 的消息发出去
+    if websocket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .is_err()
+    {
+        return;
+    }
+
+    let (outgoing_sender, outgoing) = mpsc::channel();
+    if events
+        .send(EInspectorEvent::Connected(session_id, outgoing_sender))
+        .is_err()
+    {
+        return;
+    }
+
+    'session: while !shutdown.load(Ordering::SeqCst) {
+        match websocket.read() {
+            Ok(tungstenite::Message::Text(text)) => {
+                if events
+                    .send(EInspectorEvent::Message(session_id, text))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            Ok(tungstenite::Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if err.kind() == std::io::ErrorKind::WouldBlock
+                    || err.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(_) => break,
+        }
+
+        loop {
+            match outgoing.try_recv() {
+                Ok(message) => {
+                    if websocket.send(tungstenite::Message::Text(message)).is_err() {
+                        break 'session;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => break 'session,
+            }
+        }
+    }
+
+    let _ = events.send(EInspectorEvent::Disconnected(session_id));
+}
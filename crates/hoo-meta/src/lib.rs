@@ -1,13 +1,25 @@
 mod basic_types;
+mod closures;
 mod compound_types;
+#[cfg(feature = "glm")]
+mod glm_types;
+#[cfg(feature = "inspector")]
+mod inspector;
+mod modules;
 mod objects;
+mod snapshot;
 mod tests;
 mod traits;
 mod types;
 
 pub use basic_types::*;
+pub use closures::{FJsClosure, IntoJsClosure};
 pub use compound_types::*;
+pub use modules::script_url_from_path;
+#[cfg(feature = "inspector")]
+pub use inspector::*;
 pub use objects::*;
+pub use snapshot::*;
 pub use traits::*;
 pub use types::*;
 
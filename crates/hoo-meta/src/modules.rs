@@ -0,0 +1,146 @@
+use crate::types::*;
+
+use std::collections::HashMap;
+use std::num::NonZeroI32;
+use std::path::{Path, PathBuf};
+
+// 存在 isolate 的 slot 里，随 isolate 一起销毁
+#[derive(Default)]
+struct FModuleMap {
+    resource_names: HashMap<NonZeroI32, String>,
+    modules: HashMap<PathBuf, v8::Global<v8::Module>>,
+}
+
+// 脚本的 resource name 用 file:// url，调试器才能把脚本对应回本地文件
+pub fn script_url_from_path(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or(path.to_path_buf());
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+// resource name 既可以是普通路径，也可以是 script_url_from_path 生成的 url
+fn path_from_resource_name(resource_name: &str) -> PathBuf {
+    match resource_name.strip_prefix("file://") {
+        // windows 上是 file:///C:/...
+        Some(path) if path.len() > 3 && path.as_bytes()[2] == b':' => PathBuf::from(&path[1..]),
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(resource_name),
+    }
+}
+
+pub(crate) fn create_script_origin<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    resource_name: &str,
+    is_module: bool,
+) -> v8::ScriptOrigin<'s> {
+    let name = v8::String::new(scope, resource_name).unwrap();
+    let source_map_url = v8::undefined(scope);
+    v8::ScriptOrigin::new(
+        scope,
+        name.into(),
+        0,
+        0,
+        false,
+        0,
+        source_map_url.into(),
+        false,
+        false,
+        is_module,
+    )
+}
+
+pub(crate) fn compile_module<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    source: &str,
+    resource_name: &str,
+) -> Option<v8::Local<'s, v8::Module>> {
+    let path = path_from_resource_name(resource_name);
+
+    if let Some(map) = scope.get_slot::<FModuleMap>() {
+        if let Some(module) = map.modules.get(&path) {
+            let module = module.clone();
+            return Some(v8::Local::new(scope, module));
+        }
+    }
+
+    let code = v8::String::new(scope, source).unwrap();
+    let origin = create_script_origin(scope, resource_name, true);
+    let source = v8::script_compiler::Source::new(code, Some(&origin));
+    let module = v8::script_compiler::compile_module(scope, source)?;
+
+    let global = v8::Global::new(scope, module);
+    if scope.get_slot::<FModuleMap>().is_none() {
+        scope.set_slot(FModuleMap::default());
+    }
+    let map = scope.get_slot_mut::<FModuleMap>().unwrap();
+    map.resource_names
+        .insert(module.get_identity_hash(), resource_name.to_string());
+    map.modules.insert(path, global);
+
+    Some(module)
+}
+
+pub(crate) fn resolve_module_callback<'a>(
+    context: v8::Local<'a, v8::Context>,
+    specifier: v8::Local<'a, v8::String>,
+    _import_assertions: v8::Local<'a, v8::FixedArray>,
+    referrer: v8::Local<'a, v8::Module>,
+) -> Option<v8::Local<'a, v8::Module>> {
+    let scope = &mut unsafe { v8::CallbackScope::new(context) };
+    let specifier = specifier.to_rust_string_lossy(scope);
+
+    let referrer_name = scope
+        .get_slot::<FModuleMap>()
+        .and_then(|map| map.resource_names.get(&referrer.get_identity_hash()).cloned())
+        .unwrap_or_default();
+    let path = path_from_resource_name(&referrer_name)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&specifier);
+
+    // 被 import 的模块沿用 referrer 的命名方式
+    let resource_name = if referrer_name.starts_with("file://") {
+        script_url_from_path(&path)
+    } else {
+        path.to_string_lossy().to_string()
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(source) => compile_module(scope, &source, &resource_name),
+        Err(err) => {
+            let msg = format!("cannot load module '{}': {}", specifier, err);
+            let msg = v8::String::new(scope, &msg).unwrap();
+            let exception = v8::Exception::error(scope, msg);
+            scope.throw_exception(exception);
+            None
+        }
+    }
+}
+
+// 跑完 microtask。如果 value 是 Promise，则取出它的结果
+pub(crate) fn settle_promise<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+) -> Result<v8::Local<'s, v8::Value>, JsRuntimeError> {
+    scope.perform_microtask_checkpoint();
+
+    let promise = match v8::Local::<v8::Promise>::try_from(value) {
+        Ok(promise) => promise,
+        Err(_) => return Ok(value),
+    };
+
+    match promise.state() {
+        v8::PromiseState::Fulfilled => Ok(promise.result(scope)),
+        v8::PromiseState::Rejected => {
+            let exception = promise.result(scope);
+            Err(JsRuntimeError::from_exception(scope, exception))
+        }
+        v8::PromiseState::Pending => Err(JsRuntimeError::new(
+            "promise is still pending after running microtasks",
+        )),
+    }
+}
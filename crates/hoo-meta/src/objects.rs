@@ -116,7 +116,8 @@ pub fn get_external_internal_value_from_js_object<'a>(
     Ok(ptr)
 }
 
-impl<T: TryFromJsValue> TryFromJsValue for RcObject<T> {
+// T 本身不需要能从 JsValue 转换：RcObject 只是取回已经注册的 Rust 对象
+impl<T: 'static> TryFromJsValue for RcObject<T> {
     fn try_from<'a>(
         scope: &mut v8::HandleScope<'a>,
         val: &v8::Local<'a, v8::Value>,
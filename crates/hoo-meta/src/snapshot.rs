@@ -0,0 +1,139 @@
+// 把注册好的模块、类和 prelude 脚本预先跑一遍，存成 V8 startup snapshot。
+// 之后新建 isolate 时直接反序列化，不用每次重新构造 template、重新编译脚本。
+//
+// 限制：prelude 脚本只能定义纯 js 的东西，不能创建 rust 对象（比如 new Pair()），
+// 因为这类对象的 internal field 指向当前进程里的 rust 内存，没法序列化。
+// 同理也不能用 add_closure。
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::types::*;
+
+pub struct HooMetaSnapshot {
+    blob: Vec<u8>,
+    external_references: &'static v8::ExternalReferences,
+}
+
+// v8 要求 external references 活得比所有用这个 snapshot 的 isolate 都久，所以只能 leak。
+// 同一张表（同一个 build）只 leak 一次，之后的 create / from_blob 复用，key 是回调地址
+fn intern_external_references(
+    references: &[v8::ExternalReference<'static>],
+) -> &'static v8::ExternalReferences {
+    static TABLES: OnceLock<Mutex<HashMap<Vec<usize>, &'static v8::ExternalReferences>>> =
+        OnceLock::new();
+
+    let key = references
+        .iter()
+        .map(|reference| unsafe { reference.pointer } as usize)
+        .collect::<Vec<_>>();
+    let mut tables = TABLES.get_or_init(Default::default).lock().unwrap();
+    tables
+        .entry(key)
+        .or_insert_with(|| Box::leak(Box::new(v8::ExternalReferences::new(references))))
+}
+
+// snapshot_creator 在构造时就要拿到 external references，
+// 所以先在一个临时 isolate 里跑一遍 build，只收集回调
+fn collect_external_references<F: Fn(&mut HooMetaContextBuilder)>(
+    build: &F,
+) -> Result<&'static v8::ExternalReferences, JsRuntimeError> {
+    let isolate = &mut v8::Isolate::new(Default::default());
+    let global_scope = &mut v8::HandleScope::new(isolate);
+    let context_template = v8::ObjectTemplate::new(global_scope);
+
+    let mut builder = HooMetaContextBuilder {
+        global_scope,
+        context_template,
+        external_references: vec![],
+        closures: vec![],
+    };
+    build(&mut builder);
+
+    if !builder.closures.is_empty() {
+        return Err(JsRuntimeError::new(
+            "closures registered with add_closure cannot be put into a snapshot",
+        ));
+    }
+
+    Ok(intern_external_references(&builder.external_references))
+}
+
+impl HooMetaSnapshot {
+    // prelude_scripts: (resource_name, source)
+    pub fn create<F: Fn(&mut HooMetaContextBuilder)>(
+        build: F,
+        prelude_scripts: &[(&str, &str)],
+    ) -> Result<Self, JsRuntimeError> {
+        let external_references = collect_external_references(&build)?;
+
+        let mut isolate = v8::Isolate::snapshot_creator(Some(external_references));
+        let mut error = None;
+        {
+            let mut global_scope = v8::HandleScope::new(&mut isolate);
+            let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+                build(context_builder)
+            });
+
+            for (resource_name, source) in prelude_scripts {
+                if let Err(err) = hoo_meta_context.run_script(source, resource_name) {
+                    error = Some(err);
+                    break;
+                }
+            }
+
+            let context = hoo_meta_context.get_context();
+            hoo_meta_context.scope_mut().set_default_context(context);
+        }
+
+        // 即使 prelude 出错也要走完 create_blob，否则 snapshot creator 析构时会出问题
+        let blob = isolate.create_blob(v8::FunctionCodeHandling::Keep);
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let blob = blob.ok_or(JsRuntimeError::new("failed to create snapshot blob"))?;
+
+        Ok(Self {
+            blob: blob.to_vec(),
+            external_references,
+        })
+    }
+
+    // 从之前保存的 blob 恢复。build 必须和创建时一致，否则回调对不上
+    pub fn from_blob<F: Fn(&mut HooMetaContextBuilder)>(
+        blob: Vec<u8>,
+        build: F,
+    ) -> Result<Self, JsRuntimeError> {
+        Ok(Self {
+            blob,
+            external_references: collect_external_references(&build)?,
+        })
+    }
+
+    pub fn get_blob(&self) -> &[u8] {
+        &self.blob
+    }
+
+    pub fn create_isolate(&self) -> v8::OwnedIsolate {
+        let params = v8::CreateParams::default()
+            .snapshot_blob(self.blob.clone())
+            .external_references(&**self.external_references);
+        v8::Isolate::new(params)
+    }
+}
+
+// 用 snapshot 里的默认 context，isolate 必须由 HooMetaSnapshot::create_isolate 创建
+pub fn build_context_from_snapshot<'a, 's: 'a>(
+    global_scope: &'a mut v8::HandleScope<'s, ()>,
+) -> HooMetaContext<'s, 'a> {
+    let context: v8::Local<'s, v8::Context> = v8::Context::new(global_scope);
+    let context_scope: v8::ContextScope<'a, v8::HandleScope<'s>> =
+        v8::ContextScope::new(global_scope, context);
+
+    HooMetaContext {
+        context_scope,
+        context,
+    }
+}
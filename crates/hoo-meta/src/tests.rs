@@ -60,6 +60,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tuple_and_unit_struct() {
+        initialize();
+
+        #[derive(JsStructNoConstructor)]
+        struct FEntityId(pub u32);
+
+        #[derive(JsStructNoConstructor)]
+        struct FRange(pub i32, pub i32);
+
+        #[derive(JsStructNoConstructor)]
+        #[js(as_object)]
+        struct FSize(pub f32, pub f32);
+
+        #[derive(JsStruct)]
+        struct FMarker;
+
+        #[js_impl]
+        impl FMarker {
+            pub fn new() -> Self {
+                Self
+            }
+
+            pub fn name(&self) -> String {
+                "marker".into()
+            }
+        }
+
+        #[js_function]
+        fn next_id(id: FEntityId) -> FEntityId {
+            FEntityId(id.0 + 1)
+        }
+
+        #[js_function]
+        fn reverse(range: FRange) -> FRange {
+            FRange(range.1, range.0)
+        }
+
+        #[js_function]
+        fn area(size: FSize) -> f32 {
+            size.0 * size.1
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_function!(context_builder, next_id);
+            module_add_function!(context_builder, reverse);
+            module_add_function!(context_builder, area);
+            module_add_class!(context_builder, FMarker);
+        });
+
+        // newtype 在 js 里就是里面的值
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("next_id(41)"), "42");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("JSON.stringify(reverse([1, 2]))"),
+            "[2,1]"
+        );
+        assert!(hoo_meta_context.evaluate_script("reverse([1])").is_none());
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("area({0: 2, 1: 3})"),
+            "6"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("new FMarker().name()"),
+            "marker"
+        );
+    }
+
     #[test]
     fn reference_type() {
         initialize();
@@ -332,4 +402,772 @@ mod tests {
         assert_eq!(hoo_meta_context.evaluate_script_get_string("0"), "0");
         assert_eq!(unsafe { COUNT }, 2);
     }
+
+    #[test]
+    fn runtime_error() {
+        initialize();
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |_| {});
+
+        let err = hoo_meta_context
+            .run_script(
+                "function thrower() { throw new Error('boom'); }\nthrower();",
+                "thrower.js",
+            )
+            .unwrap_err();
+
+        assert_eq!(err.error_message(), "Error: boom");
+        let stack_trace = err.stack_trace().unwrap();
+        assert!(stack_trace.contains("thrower"));
+        assert!(stack_trace.contains("thrower.js"));
+
+        let err = hoo_meta_context.run_script("throw 1", "literal.js").unwrap_err();
+        assert_eq!(err.error_message(), "1");
+        assert!(err.stack_trace().unwrap().contains("literal.js:1"));
+    }
+
+    #[test]
+    fn module_exports() {
+        initialize();
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |_| {});
+
+        let namespace = hoo_meta_context
+            .run_module(
+                "export function add(a, b) { return a + b; }\nexport async function fail() { throw new Error('async'); }",
+                "module.mjs",
+            )
+            .unwrap();
+
+        let scope = hoo_meta_context.scope_mut();
+        let add = v8::String::new(scope, "add").unwrap();
+        let add = namespace.get(scope, add.into()).unwrap();
+        let add = v8::Local::<v8::Function>::try_from(add).unwrap();
+        let fail = v8::String::new(scope, "fail").unwrap();
+        let fail = namespace.get(scope, fail.into()).unwrap();
+        let fail = v8::Local::<v8::Function>::try_from(fail).unwrap();
+        let one = v8::Integer::new(scope, 1).into();
+        let two = v8::Integer::new(scope, 2).into();
+
+        let sum = hoo_meta_context.call_function(add, &[one, two]).unwrap();
+        assert_eq!(sum.int32_value(hoo_meta_context.scope_mut()), Some(3));
+
+        let err = hoo_meta_context.call_function(fail, &[]).unwrap_err();
+        assert_eq!(err.error_message(), "Error: async");
+    }
+
+    #[test]
+    fn mutable_method() {
+        initialize();
+
+        #[derive(JsStruct)]
+        struct Counter {
+            pub value: i32,
+        }
+
+        #[js_impl]
+        impl Counter {
+            pub fn new(value: i32) -> Self {
+                Self { value }
+            }
+
+            pub fn increase(&mut self, delta: i32) -> i32 {
+                self.value += delta;
+                self.value
+            }
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_class!(context_builder, Counter);
+        });
+
+        assert_eq!(
+            hoo_meta_context
+                .evaluate_script_get_string("let c = new Counter(1); c.increase(2); c.value"),
+            "3"
+        );
+    }
+
+    #[test]
+    fn option_and_vec() {
+        initialize();
+
+        #[js_function]
+        fn sum_or_zero(values: Option<Vec<i32>>) -> i32 {
+            values.map(|v| v.iter().sum()).unwrap_or(0)
+        }
+
+        #[js_function]
+        fn first(values: Vec<i32>) -> Option<i32> {
+            values.first().cloned()
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_function!(context_builder, sum_or_zero);
+            module_add_function!(context_builder, first);
+        });
+
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("sum_or_zero([1, 2, 3])"),
+            "6"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("sum_or_zero(null)"),
+            "0"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("first([])"),
+            "null"
+        );
+        assert!(hoo_meta_context.evaluate_script("sum_or_zero(1)").is_none());
+    }
+
+    #[test]
+    fn reference_parameters() {
+        initialize();
+
+        #[derive(JsStruct)]
+        struct FCounter {
+            pub value: i32,
+        }
+
+        #[js_impl]
+        impl FCounter {
+            pub fn new(value: i32) -> Self {
+                Self { value }
+            }
+
+            pub fn absorb(&mut self, other: &FCounter) -> i32 {
+                self.value += other.value;
+                self.value
+            }
+        }
+
+        #[js_function]
+        fn copy_into(dst: &mut FCounter, src: &FCounter) {
+            dst.value = src.value;
+        }
+
+        #[js_function]
+        fn describe(name: &str, values: &[i32]) -> String {
+            format!("{}: {}", name, values.len())
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_class!(context_builder, FCounter);
+            module_add_function!(context_builder, copy_into);
+            module_add_function!(context_builder, describe);
+        });
+
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string(
+                "let a = new FCounter(1); let b = new FCounter(2); copy_into(a, b); a.value"
+            ),
+            "2"
+        );
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("a.absorb(b)"), "4");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("describe('values', [1, 2, 3])"),
+            "values: 3"
+        );
+
+        // 同一个对象同时被可变和不可变借用，抛 js 异常而不是 panic
+        let err = hoo_meta_context.run_script("copy_into(a, a)", "borrow.js").unwrap_err();
+        assert!(err
+            .error_message()
+            .contains("copy_into: argument `src` is already borrowed"));
+        let err = hoo_meta_context.run_script("a.absorb(a)", "borrow.js").unwrap_err();
+        assert!(err
+            .error_message()
+            .contains("FCounter.absorb: argument `other` is already borrowed"));
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("a.value"), "4");
+    }
+
+    #[test]
+    fn optional_parameters() {
+        initialize();
+
+        #[js_function]
+        fn greet(name: String, suffix: Option<String>, #[js(default = 1)] times: i32) -> String {
+            format!("{}{}", name, suffix.unwrap_or_default()).repeat(times as usize)
+        }
+
+        #[js_function]
+        fn sum_all(first: i32, rest: JsRest<i32>) -> i32 {
+            first + rest.iter().sum::<i32>()
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_function!(context_builder, greet);
+            module_add_function!(context_builder, sum_all);
+        });
+
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("greet('a')"), "a");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("greet('a', '!', 2)"),
+            "a!a!"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("greet('a', undefined, undefined)"),
+            "a"
+        );
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("sum_all(1)"), "1");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("sum_all(1, 2, 3, 4)"),
+            "10"
+        );
+
+        let err = hoo_meta_context.run_script("greet()", "arity.js").unwrap_err();
+        assert!(err
+            .error_message()
+            .contains("greet: expected 1 to 3 arguments, got 0"));
+        let err = hoo_meta_context.run_script("sum_all()", "arity.js").unwrap_err();
+        assert!(err
+            .error_message()
+            .contains("sum_all: expected at least 1 argument, got 0"));
+    }
+
+    #[test]
+    fn generic_instantiation() {
+        initialize();
+
+        #[js_function(instantiate(lerp = <f32>, lerp_f64 = <f64>))]
+        fn lerp<T>(a: T, b: T, t: T) -> T
+        where
+            T: Copy + std::ops::Add<Output = T> + std::ops::Sub<Output = T> + std::ops::Mul<Output = T>,
+        {
+            a + (b - a) * t
+        }
+
+        // 同名的实例按 js 参数的类型选择，数组走第一个，number 走第二个
+        #[js_function(instantiate(total = <Vec<i32>>, total = <f64>))]
+        fn total<T: std::fmt::Debug>(value: T) -> String {
+            format!("{:?}", value)
+        }
+
+        // 排在后面的实例也能选到，true 不会被转换成 number
+        #[js_function(instantiate(show = <f64>, show = <String>, show = <bool>))]
+        fn show<T: std::fmt::Debug>(value: T) -> String {
+            format!("{:?}", value)
+        }
+
+        #[derive(JsStruct)]
+        struct FMath {}
+
+        #[js_impl(rename_all = "camelCase")]
+        impl FMath {
+            pub fn new() -> Self {
+                Self {}
+            }
+
+            #[js(instantiate(max_of = <i32>))]
+            pub fn max_of<T: PartialOrd>(&self, a: T, b: T) -> T {
+                if a > b {
+                    a
+                } else {
+                    b
+                }
+            }
+
+            #[js(instantiate(clamp_f64 = <f64>))]
+            pub fn clamp<T: PartialOrd>(value: T, lo: T, hi: T) -> T {
+                if value < lo {
+                    lo
+                } else if value > hi {
+                    hi
+                } else {
+                    value
+                }
+            }
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_function!(context_builder, lerp);
+            module_add_function!(context_builder, lerp_f64);
+            module_add_function!(context_builder, total);
+            module_add_function!(context_builder, show);
+            module_add_class!(context_builder, FMath);
+        });
+
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("lerp(0, 10, 0.5)"), "5");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("lerp_f64(1, 2, 0.25)"),
+            "1.25"
+        );
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("total([1, 2])"), "[1, 2]");
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("total(1.5)"), "1.5");
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("new FMath().maxOf(3, 7)"),
+            "7"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("FMath.clampF64(1.5, 0, 1)"),
+            "1"
+        );
+
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("show(1)"), "1.0");
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("show('1')"), "\"1\"");
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("show(true)"), "true");
+
+        let err = hoo_meta_context.run_script("total()", "instantiate.js").unwrap_err();
+        assert!(err
+            .error_message()
+            .contains("total: no instantiation accepts these arguments ()"));
+        let err = hoo_meta_context.run_script("total('1')", "instantiate.js").unwrap_err();
+        assert!(err.error_message().contains(
+            "total: no instantiation accepts these arguments (string), expected <Vec<i32>>(array) or <f64>(number)"
+        ));
+    }
+
+    #[test]
+    fn startup_snapshot() {
+        initialize();
+
+        #[derive(JsStruct)]
+        struct Counter {
+            pub value: i32,
+        }
+
+        #[js_impl]
+        impl Counter {
+            pub fn new(value: i32) -> Self {
+                Self { value }
+            }
+
+            pub fn increase(&mut self, delta: i32) -> i32 {
+                self.value += delta;
+                self.value
+            }
+        }
+
+        #[js_function]
+        fn succ(val: i32) -> i32 {
+            return val + 1;
+        }
+
+        let prelude = "function twice(x) { return succ(succ(x)); }";
+        let snapshot = HooMetaSnapshot::create(
+            |context_builder| {
+                module_add_function!(context_builder, succ);
+                module_add_class!(context_builder, Counter);
+            },
+            &[("prelude.js", prelude)],
+        )
+        .unwrap();
+        assert!(!snapshot.get_blob().is_empty());
+
+        let isolate = &mut snapshot.create_isolate();
+        let mut global_scope = v8::HandleScope::new(isolate);
+        let mut hoo_meta_context = build_context_from_snapshot(&mut global_scope);
+
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("twice(1)"), "3");
+        assert_eq!(
+            hoo_meta_context
+                .evaluate_script_get_string("let c = new Counter(1); c.increase(2); c.value"),
+            "3"
+        );
+    }
+
+    #[test]
+    fn closure() {
+        initialize();
+
+        let counter = std::rc::Rc::new(std::cell::Cell::new(0));
+        let captured = counter.clone();
+
+        {
+            let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+            let mut global_scope = v8::HandleScope::new(isolate);
+
+            let mut hoo_meta_context = build_context(&mut global_scope, move |context_builder| {
+                context_builder.add_closure("add", move |delta: i32| {
+                    captured.set(captured.get() + delta);
+                    captured.get()
+                });
+            });
+
+            assert_eq!(
+                hoo_meta_context.evaluate_script_get_string("add(2); add(3)"),
+                "5"
+            );
+            assert!(hoo_meta_context.evaluate_script("add()").is_none());
+            assert_eq!(
+                hoo_meta_context
+                    .evaluate_script_get_string("try { add(1, 2) } catch (e) { e.message }"),
+                "add: expected 1 argument, got 2"
+            );
+            assert_eq!(counter.get(), 5);
+        }
+
+        // isolate 销毁后闭包捕获的状态也被释放
+        assert_eq!(std::rc::Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn closures_are_freed_with_their_context() {
+        initialize();
+
+        // 每个闭包都持有一份 counter，strong count - 1 就是还活着的闭包个数
+        let counter = std::rc::Rc::new(std::cell::Cell::new(0));
+        let live_closures =
+            |counter: &std::rc::Rc<std::cell::Cell<i32>>| std::rc::Rc::strong_count(counter) - 1;
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        for round in 0..3 {
+            let scope = &mut v8::HandleScope::new(&mut global_scope);
+            let captured = counter.clone();
+            let mut hoo_meta_context = build_context(scope, move |context_builder| {
+                context_builder.add_closure("add", move |delta: i32| {
+                    captured.set(captured.get() + delta);
+                    captured.get()
+                });
+            });
+            assert_eq!(
+                hoo_meta_context.evaluate_script_get_string("add(1)"),
+                (round + 1).to_string()
+            );
+            assert_eq!(live_closures(&counter), 1);
+        }
+
+        // context 没有被引用之后，GC 时闭包跟着释放，不用等到 isolate 销毁
+        global_scope.low_memory_notification();
+        assert_eq!(live_closures(&counter), 0);
+        assert_eq!(counter.get(), 3);
+    }
+
+    #[test]
+    fn js_attributes() {
+        initialize();
+
+        #[derive(JsStruct)]
+        #[js(rename = "Vec2", rename_all = "camelCase")]
+        struct FVector2 {
+            pub pos_x: f32,
+            #[js(readonly)]
+            pub pos_y: f32,
+            #[js(skip)]
+            pub cache: Vec<f32>,
+        }
+
+        #[js_impl(rename_all = "camelCase")]
+        impl FVector2 {
+            pub fn new(pos_x: f32, pos_y: f32) -> Self {
+                Self {
+                    pos_x,
+                    pos_y,
+                    cache: vec![],
+                }
+            }
+
+            pub fn zero() -> RcObject<FVector2> {
+                RcObject::new(Self::new(0.0, 0.0))
+            }
+
+            pub fn sum_all(&self) -> f32 {
+                self.pos_x + self.pos_y
+            }
+
+            #[js(getter)]
+            pub fn scale(&self) -> f32 {
+                self.pos_x
+            }
+
+            #[js(setter)]
+            pub fn set_scale(&mut self, value: f32) {
+                self.pos_x = value;
+                self.pos_y = value;
+            }
+
+            #[js(skip)]
+            pub fn clear_cache(&mut self) {
+                self.cache.clear();
+            }
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_class!(context_builder, FVector2);
+        });
+
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("new Vec2(1, 2).sumAll()"),
+            "3"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("Vec2.zero().posX"),
+            "0"
+        );
+        // readonly 字段赋值会被忽略
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("let v = new Vec2(1, 2); v.posY = 5; v.posY"),
+            "2"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("v.scale = 3; [v.scale, v.posY].join()"),
+            "3,3"
+        );
+        assert_eq!(
+            hoo_meta_context.evaluate_script_get_string("[v.cache, v.clearCache, v.pos_x].join()"),
+            ",,"
+        );
+    }
+
+    #[cfg(feature = "inspector")]
+    #[test]
+    fn inspector_protocol() {
+        initialize();
+
+        #[js_function]
+        fn succ(val: i32) -> i32 {
+            return val + 1;
+        }
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+
+        let mut hoo_meta_context = build_context(&mut global_scope, |context_builder| {
+            module_add_function!(context_builder, succ);
+        });
+
+        let options = FInspectorOptions {
+            port: 0,
+            ..Default::default()
+        };
+        let context = hoo_meta_context.get_context();
+        let mut inspector = HooInspector::new(hoo_meta_context.scope_mut(), context, options).unwrap();
+
+        // 在另一个线程上扮演 DevTools
+        let url = inspector.get_websocket_url();
+        let client = std::thread::spawn(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            let request = r#"{"id":1,"method":"Runtime.evaluate","params":{"expression":"succ(41)"}}"#;
+            socket.send(tungstenite::Message::Text(request.into())).unwrap();
+
+            loop {
+                if let tungstenite::Message::Text(text) = socket.read().unwrap() {
+                    let response: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    if response["id"] == 1 {
+                        return response;
+                    }
+                }
+            }
+        });
+
+        while !client.is_finished() {
+            inspector.poll(hoo_meta_context.scope_mut());
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let response = client.join().unwrap();
+        assert_eq!(response["result"]["result"]["value"], 42);
+        assert!(!inspector.is_paused());
+    }
+
+    #[cfg(feature = "inspector")]
+    #[test]
+    fn inspector_releases_port_on_drop() {
+        initialize();
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+        let mut hoo_meta_context = build_context(&mut global_scope, |_| {});
+        let context = hoo_meta_context.get_context();
+
+        let options = FInspectorOptions {
+            port: 0,
+            ..Default::default()
+        };
+        let inspector = HooInspector::new(hoo_meta_context.scope_mut(), context, options).unwrap();
+        let port = inspector.get_address().port();
+        let mut debugger = FTestDebugger::connect(inspector.get_websocket_url());
+        drop(inspector);
+
+        // 同一个端口可以马上再用
+        let options = FInspectorOptions {
+            port,
+            ..Default::default()
+        };
+        let inspector =
+            HooInspector::new(hoo_meta_context.scope_mut(), context, options.clone()).unwrap();
+        assert_eq!(inspector.get_address().port(), port);
+        drop(inspector);
+        assert!(HooInspector::new(hoo_meta_context.scope_mut(), context, options).is_ok());
+
+        // 已经连上的调试器也被断开了
+        assert!(!matches!(
+            debugger.socket.read(),
+            Ok(tungstenite::Message::Text(_))
+        ));
+    }
+
+    // 扮演 DevTools 的一端：发请求，读到满足条件的消息为止
+    #[cfg(feature = "inspector")]
+    struct FTestDebugger {
+        socket: tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>,
+    }
+
+    #[cfg(feature = "inspector")]
+    impl FTestDebugger {
+        fn connect(url: String) -> Self {
+            let (socket, _) = tungstenite::connect(url).unwrap();
+            Self { socket }
+        }
+
+        fn send(&mut self, request: serde_json::Value) {
+            let request = tungstenite::Message::Text(request.to_string());
+            self.socket.send(request).unwrap();
+        }
+
+        fn read_until(&mut self, pred: impl Fn(&serde_json::Value) -> bool) -> serde_json::Value {
+            loop {
+                if let tungstenite::Message::Text(text) = self.socket.read().unwrap() {
+                    let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    if pred(&message) {
+                        return message;
+                    }
+                }
+            }
+        }
+
+        fn call(&mut self, id: i32, method: &str, params: serde_json::Value) -> serde_json::Value {
+            self.send(serde_json::json!({ "id": id, "method": method, "params": params }));
+            self.read_until(|message| message["id"] == id)
+        }
+    }
+
+    // 调试器准备好（ready 收到消息）之前一直 poll。调试器线程 panic 时也会返回，交给 join 报告
+    #[cfg(feature = "inspector")]
+    fn poll_until_ready(
+        inspector: &mut HooInspector,
+        hoo_meta_context: &mut HooMetaContext,
+        ready: &std::sync::mpsc::Receiver<()>,
+    ) {
+        while let Err(std::sync::mpsc::TryRecvError::Empty) = ready.try_recv() {
+            inspector.poll(hoo_meta_context.scope_mut());
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[cfg(feature = "inspector")]
+    #[test]
+    fn inspector_breakpoint() {
+        initialize();
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+        let mut hoo_meta_context = build_context(&mut global_scope, |_| {});
+
+        let options = FInspectorOptions {
+            port: 0,
+            ..Default::default()
+        };
+        let context = hoo_meta_context.get_context();
+        let mut inspector =
+            HooInspector::new(hoo_meta_context.scope_mut(), context, options).unwrap();
+
+        let url = inspector.get_websocket_url();
+        let (ready_sender, ready) = std::sync::mpsc::channel();
+        let client = std::thread::spawn(move || {
+            let mut debugger = FTestDebugger::connect(url);
+            debugger.call(1, "Debugger.enable", serde_json::json!({}));
+            let breakpoint = debugger.call(
+                2,
+                "Debugger.setBreakpointByUrl",
+                serde_json::json!({ "url": "test://breakpoint.js", "lineNumber": 1 }),
+            );
+            ready_sender.send(()).unwrap();
+
+            let paused = debugger.read_until(|message| message["method"] == "Debugger.paused");
+            debugger.call(3, "Debugger.resume", serde_json::json!({}));
+            (breakpoint, paused)
+        });
+
+        poll_until_ready(&mut inspector, &mut hoo_meta_context, &ready);
+
+        // 停在第二行，直到调试器 resume
+        let source = "var hits = 1;\nhits += 1;\n";
+        hoo_meta_context
+            .run_script(source, "test://breakpoint.js")
+            .unwrap();
+        assert!(!inspector.is_paused());
+        assert_eq!(hoo_meta_context.evaluate_script_get_string("hits"), "2");
+
+        let (breakpoint, paused) = client.join().unwrap();
+        let breakpoint_id = breakpoint["result"]["breakpointId"].clone();
+        assert!(breakpoint_id.is_string());
+        assert_eq!(paused["params"]["reason"], "other");
+        assert_eq!(paused["params"]["hitBreakpoints"][0], breakpoint_id);
+        assert_eq!(
+            paused["params"]["callFrames"][0]["location"]["lineNumber"],
+            1
+        );
+    }
+
+    #[cfg(feature = "inspector")]
+    #[test]
+    fn inspector_pause_on_exception() {
+        initialize();
+
+        let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        let mut global_scope = v8::HandleScope::new(isolate);
+        let mut hoo_meta_context = build_context(&mut global_scope, |_| {});
+
+        // 不需要调试器自己发 setPauseOnExceptions，Debugger.enable 时引擎替它设置
+        let options = FInspectorOptions {
+            port: 0,
+            pause_on_exceptions: EPauseOnExceptions::All,
+            ..Default::default()
+        };
+        let context = hoo_meta_context.get_context();
+        let mut inspector =
+            HooInspector::new(hoo_meta_context.scope_mut(), context, options).unwrap();
+
+        let url = inspector.get_websocket_url();
+        let (ready_sender, ready) = std::sync::mpsc::channel();
+        let client = std::thread::spawn(move || {
+            let mut debugger = FTestDebugger::connect(url);
+            debugger.call(1, "Debugger.enable", serde_json::json!({}));
+            ready_sender.send(()).unwrap();
+
+            let paused = debugger.read_until(|message| message["method"] == "Debugger.paused");
+            debugger.call(2, "Debugger.resume", serde_json::json!({}));
+            paused
+        });
+
+        poll_until_ready(&mut inspector, &mut hoo_meta_context, &ready);
+
+        let result = hoo_meta_context.run_script("throw new Error('boom')", "test://exception.js");
+        assert!(result.unwrap_err().to_string().contains("boom"));
+        assert!(!inspector.is_paused());
+
+        let paused = client.join().unwrap();
+        assert_eq!(paused["params"]["reason"], "exception");
+        let description = paused["params"]["data"]["description"].as_str().unwrap();
+        assert!(description.starts_with("Error: boom"));
+    }
 }
@@ -1,3 +1,5 @@
+use crate::closures::*;
+
 #[derive(Debug)]
 pub struct TryFromJsValueError(String);
 
@@ -25,9 +27,89 @@ impl JsException {
     }
 }
 
+// 脚本运行时抛出、没有被脚本自己 catch 的异常
+#[derive(Debug)]
+pub struct JsRuntimeError {
+    message: String,
+    stack_trace: Option<String>,
+}
+
+impl JsRuntimeError {
+    pub fn new(msg: &str) -> Self {
+        Self {
+            message: msg.to_string(),
+            stack_trace: None,
+        }
+    }
+
+    pub fn from_exception<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        exception: v8::Local<'s, v8::Value>,
+    ) -> Self {
+        let message = exception.to_rust_string_lossy(scope);
+
+        // Error 对象的 stack 属性里已经包含了 message
+        let stack_trace = exception.to_object(scope).and_then(|object| {
+            let key = v8::String::new(scope, "stack").unwrap();
+            let stack = object.get(scope, key.into())?;
+            if stack.is_string() {
+                Some(stack.to_rust_string_lossy(scope))
+            } else {
+                None
+            }
+        });
+
+        Self {
+            message,
+            stack_trace,
+        }
+    }
+
+    pub fn from_try_catch(scope: &mut v8::TryCatch<v8::HandleScope>) -> Self {
+        let mut error = match scope.exception() {
+            Some(exception) => Self::from_exception(scope, exception),
+            None => Self::new("execution terminated"),
+        };
+
+        // 非 Error 对象（比如 throw "foo"）没有调用栈，至少给出位置
+        if error.stack_trace.is_none() {
+            if let Some(message) = scope.message() {
+                let resource_name = message
+                    .get_script_resource_name(scope)
+                    .map(|name| name.to_rust_string_lossy(scope))
+                    .unwrap_or_default();
+                let line_number = message.get_line_number(scope).unwrap_or(0);
+                error.stack_trace = Some(format!(
+                    "{}\n    at {}:{}",
+                    error.message, resource_name, line_number
+                ));
+            }
+        }
+
+        error
+    }
+
+    pub fn error_message(&self) -> String {
+        self.message.clone()
+    }
+
+    pub fn stack_trace(&self) -> Option<String> {
+        self.stack_trace.clone()
+    }
+}
+
+impl std::fmt::Display for JsRuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.stack_trace {
+            Some(stack_trace) => write!(f, "{}", stack_trace),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
 pub struct HooMetaContext<'s, 'a> {
-    context_scope: v8::ContextScope<'a, v8::HandleScope<'s>>,
-    _context: v8::Local<'s, v8::Context>,
+    pub(crate) context_scope: v8::ContextScope<'a, v8::HandleScope<'s>>,
+    pub(crate) context: v8::Local<'s, v8::Context>,
 }
 
 pub struct HooMetaModuleBuilder<'s, 'a, 'x> {
@@ -39,20 +121,50 @@ pub struct HooMetaContextBuilder<'s, 'a>
 where
     's: 'a,
 {
-    global_scope: &'a mut v8::HandleScope<'s, ()>,
-    context_template: v8::Local<'s, v8::ObjectTemplate>,
+    pub(crate) global_scope: &'a mut v8::HandleScope<'s, ()>,
+    pub(crate) context_template: v8::Local<'s, v8::ObjectTemplate>,
+    // 所有注册过的 native 回调，按注册顺序排列
+    pub(crate) external_references: Vec<v8::ExternalReference<'static>>,
+    // add_closure 注册的闭包，创建 context 后交给 context 保管。snapshot 用它拒绝闭包
+    pub(crate) closures: Vec<Box<FJsClosure>>,
 }
 
 pub trait ModuleLikeBuilder<'s, 'a> {
     fn get_global_scope<'x>(&'x mut self) -> &'x mut v8::HandleScope<'s, ()>;
     fn get_template(&mut self) -> v8::Local<'s, v8::ObjectTemplate>;
+    fn add_external_references(&mut self, references: Vec<v8::ExternalReference<'static>>);
+    // 交给将要创建的 context 保管，返回的地址在 context 销毁前一直有效
+    fn store_closure(&mut self, closure: FJsClosure) -> *const FJsClosure;
+
+    fn add_function(
+        &mut self,
+        name: &str,
+        callback: impl v8::MapFnTo<v8::FunctionCallback> + Copy,
+    ) {
+        self.add_external_references(vec![v8::ExternalReference {
+            function: callback.map_fn_to(),
+        }]);
 
-    fn add_function(&mut self, name: &str, callback: impl v8::MapFnTo<v8::FunctionCallback>) {
         let key = v8::String::new(self.get_global_scope(), name).unwrap();
         let value = v8::FunctionTemplate::new(self.get_global_scope(), callback);
         self.get_template().set(key.into(), value.into());
     }
 
+    // 和 add_function 一样，但可以捕获状态。参数和返回值的要求与 #[js_function] 相同
+    fn add_closure<Args, F: IntoJsClosure<Args>>(&mut self, name: &str, closure: F) {
+        self.add_external_references(vec![v8::ExternalReference {
+            function: v8::MapFnTo::map_fn_to(crate::closures::closure_callback),
+        }]);
+
+        let closure = self.store_closure(closure.into_js_closure(name));
+        let data = v8::External::new(self.get_global_scope(), closure as *mut std::ffi::c_void);
+        let key = v8::String::new(self.get_global_scope(), name).unwrap();
+        let value = v8::FunctionTemplate::builder(crate::closures::closure_callback)
+            .data(data.into())
+            .build(self.get_global_scope());
+        self.get_template().set(key.into(), value.into());
+    }
+
     fn add_class<F: FnOnce(&mut Self)>(&mut self, generator: F) {
         generator(self);
     }
@@ -66,6 +178,17 @@ impl<'s, 'a> ModuleLikeBuilder<'s, 'a> for HooMetaContextBuilder<'s, 'a> {
     fn get_template(&mut self) -> v8::Local<'s, v8::ObjectTemplate> {
         self.context_template
     }
+
+    fn add_external_references(&mut self, mut references: Vec<v8::ExternalReference<'static>>) {
+        self.external_references.append(&mut references);
+    }
+
+    fn store_closure(&mut self, closure: FJsClosure) -> *const FJsClosure {
+        let closure = Box::new(closure);
+        let ptr = &*closure as *const FJsClosure;
+        self.closures.push(closure);
+        ptr
+    }
 }
 
 impl<'s, 'a, 'x> ModuleLikeBuilder<'s, 'a> for HooMetaModuleBuilder<'s, 'a, 'x> {
@@ -76,6 +199,14 @@ impl<'s, 'a, 'x> ModuleLikeBuilder<'s, 'a> for HooMetaModuleBuilder<'s, 'a, 'x>
     fn get_template(&mut self) -> v8::Local<'s, v8::ObjectTemplate> {
         self.object_template
     }
+
+    fn add_external_references(&mut self, references: Vec<v8::ExternalReference<'static>>) {
+        self.context_builder.add_external_references(references);
+    }
+
+    fn store_closure(&mut self, closure: FJsClosure) -> *const FJsClosure {
+        self.context_builder.store_closure(closure)
+    }
 }
 
 impl<'s, 'a> HooMetaContextBuilder<'s, 'a>
@@ -151,6 +282,8 @@ pub fn build_context<'a, 's: 'a, F: FnOnce(&mut HooMetaContextBuilder)>(
     let mut builder: HooMetaContextBuilder<'s, 'a> = HooMetaContextBuilder {
         global_scope,
         context_template,
+        external_references: vec![],
+        closures: vec![],
     };
 
     build(&mut builder);
@@ -158,6 +291,7 @@ pub fn build_context<'a, 's: 'a, F: FnOnce(&mut HooMetaContextBuilder)>(
     // global_scope<> -> context<>
     let context: v8::Local<'s, v8::Context> =
         v8::Context::new_from_template(&mut builder.global_scope, context_template.clone());
+    crate::closures::attach_closures(builder.global_scope, context, builder.closures);
 
     // global_scope<> and its self -> context_scope<>
     let context_scope: v8::ContextScope<'a, v8::HandleScope<'s>> =
@@ -165,7 +299,7 @@ pub fn build_context<'a, 's: 'a, F: FnOnce(&mut HooMetaContextBuilder)>(
 
     HooMetaContext {
         context_scope,
-        _context: context,
+        context,
     }
 }
 
@@ -174,6 +308,10 @@ impl<'s, 'a> HooMetaContext<'s, 'a> {
         &mut self.context_scope
     }
 
+    pub fn get_context(&self) -> v8::Local<'s, v8::Context> {
+        self.context
+    }
+
     pub fn evaluate_script(&mut self, source: &str) -> Option<v8::Local<'s, v8::Value>> {
         let scope = self.scope_mut();
         let code = v8::String::new(scope, source).unwrap();
@@ -187,4 +325,71 @@ impl<'s, 'a> HooMetaContext<'s, 'a> {
         let result = result.to_string(scope).unwrap().to_rust_string_lossy(scope);
         result
     }
+
+    // 和 evaluate_script 一样，但是会带上脚本名，并且把异常和调用栈交给调用者
+    pub fn run_script(
+        &mut self,
+        source: &str,
+        resource_name: &str,
+    ) -> Result<v8::Local<'s, v8::Value>, JsRuntimeError> {
+        let scope = &mut v8::TryCatch::new(self.scope_mut());
+        let code = v8::String::new(scope, source).unwrap();
+        let origin = crate::modules::create_script_origin(scope, resource_name, false);
+
+        let result = v8::Script::compile(scope, code, Some(&origin))
+            .and_then(|script| script.run(scope));
+
+        result.ok_or_else(|| JsRuntimeError::from_try_catch(scope))
+    }
+
+    // 以 ES module 的方式执行，返回 module namespace（即所有 export）
+    // import 的相对路径以 resource_name 所在目录为基准
+    pub fn run_module(
+        &mut self,
+        source: &str,
+        resource_name: &str,
+    ) -> Result<v8::Local<'s, v8::Object>, JsRuntimeError> {
+        let scope = &mut v8::TryCatch::new(self.scope_mut());
+
+        let module = crate::modules::compile_module(scope, source, resource_name)
+            .ok_or_else(|| JsRuntimeError::from_try_catch(scope))?;
+
+        if module
+            .instantiate_module(scope, crate::modules::resolve_module_callback)
+            .is_none()
+        {
+            return Err(JsRuntimeError::from_try_catch(scope));
+        }
+
+        let result = module
+            .evaluate(scope)
+            .ok_or_else(|| JsRuntimeError::from_try_catch(scope))?;
+        crate::modules::settle_promise(scope, result)?;
+
+        if module.get_status() == v8::ModuleStatus::Errored {
+            let exception = module.get_exception();
+            return Err(JsRuntimeError::from_exception(scope, exception));
+        }
+
+        let namespace = module.get_module_namespace();
+        namespace
+            .to_object(scope)
+            .ok_or_else(|| JsRuntimeError::new("module namespace is not an object"))
+    }
+
+    // 调用一个 js 函数。返回 Promise 的话，会跑完 microtask 再取结果
+    pub fn call_function(
+        &mut self,
+        function: v8::Local<'s, v8::Function>,
+        args: &[v8::Local<'s, v8::Value>],
+    ) -> Result<v8::Local<'s, v8::Value>, JsRuntimeError> {
+        let scope = &mut v8::TryCatch::new(self.scope_mut());
+        let receiver = v8::undefined(scope).into();
+
+        let result = function
+            .call(scope, receiver, args)
+            .ok_or_else(|| JsRuntimeError::from_try_catch(scope))?;
+
+        crate::modules::settle_promise(scope, result)
+    }
 }
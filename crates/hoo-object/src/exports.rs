@@ -1 +1 @@
-pub use std::ops::Deref;
\ No newline at end of file
+pub use std::ops::{Deref, DerefMut};
\ No newline at end of file
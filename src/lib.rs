@@ -4,12 +4,21 @@ mod global;
 mod graphics;
 mod io;
 mod object;
+mod script;
 mod utils;
 
 use editor::FEditor;
 use global::{configs::Configs, resources::FGlobalResources};
 use hoo_object::RcObject;
-use object::context::HContext;
+use object::{context::HContext, resources::FRenderTargets};
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use object::benchmark::{benchmark_space_tick, FSpaceTickTimings};
+pub use script::{
+    create_engine_snapshot, load_engine_snapshot, measure_startup, run_repl, run_script_file,
+    run_script_tests, with_headless_script_context, with_headless_script_context_from_snapshot,
+    EScriptKind, FScriptInspector, FScriptTestReport, FStartupTimings,
+};
 use utils::RcMut;
 
 use std::{
@@ -36,6 +45,9 @@ pub struct HooEngine {
     window: RcMut<winit::window::Window>,
 
     object_context: RcObject<HContext>,
+
+    // script
+    inspector: RefCell<Option<FScriptInspector>>,
 }
 
 thread_local! {
@@ -75,6 +87,7 @@ impl HooEngine {
             egui_winit_state: RefCell::new(egui_winit_state),
             object_context: RcObject::new(HContext::new()),
             window: window.clone(),
+            inspector: RefCell::new(None),
         };
         rcmut!(out)
     }
@@ -88,10 +101,38 @@ impl HooEngine {
     }
 
     pub fn next_frame(&self) {
-        self.object_context.borrow_mut().tick(0.0);
+        // 调试器的消息在帧开始时处理，要求暂停时会停在这里
+        {
+            let mut inspector = self.inspector.borrow_mut();
+            if let Some(script_inspector) = inspector.as_mut() {
+                if !script_inspector.poll() {
+                    *inspector = None;
+                }
+            }
+        }
+
+        let render_targets = {
+            let renderer = self.renderer.borrow();
+            FRenderTargets {
+                swapchain_size: renderer.get_swapchain_size(),
+                main_viewport_target: renderer.get_main_viewport_target(),
+            }
+        };
+        let pipelines = self
+            .object_context
+            .borrow_mut()
+            .tick_with_renderer(&render_targets);
+        for pipeline in pipelines {
+            self.renderer.borrow().submit_pipeline(pipeline);
+        }
         self.renderer.borrow_mut().next_frame();
     }
 
+    // 之后每帧 poll。脚本 isolate 销毁后自动丢掉
+    pub fn set_inspector(&self, inspector: Option<FScriptInspector>) {
+        *self.inspector.borrow_mut() = inspector;
+    }
+
     pub fn get_renderer(&self) -> Ref<Renderer> {
         self.renderer.borrow()
     }
@@ -0,0 +1,1300 @@
+// archetype 存储：component 集合相同的 entity 放在同一个 archetype 里，
+// 每种 component 一列，同一行是同一个 entity。列里按类型连续存放 component 的值（Vec<T>），
+// 以列为单位借用：system 执行时每列只检查一次借用和类型，之后逐行访问
+// 每个 component 记录加入和最后一次被修改时的 change tick，查询可以只要新加入或修改过的
+
+use std::{
+    any::{Any, TypeId},
+    cell::{Cell, Ref, RefCell, RefMut},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use super::{components::get_component_type_name, entity::FEntityId};
+
+// change tick 由 HSpace 递增，比 system 上次执行时的 tick 大说明在那之后发生
+#[derive(Clone, Debug)]
+pub struct FComponentTicks {
+    added: u32,
+    // 遍历时只有 &self，所以用 Cell
+    changed: Cell<u32>,
+}
+
+impl FComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: Cell::new(tick),
+        }
+    }
+
+    pub fn get_added(&self) -> u32 {
+        self.added
+    }
+
+    pub fn get_changed(&self) -> u32 {
+        self.changed.get()
+    }
+
+    pub fn is_added_since(&self, tick: u32) -> bool {
+        self.added > tick
+    }
+
+    pub fn is_changed_since(&self, tick: u32) -> bool {
+        self.changed.get() > tick
+    }
+
+    pub fn set_changed(&self, tick: u32) {
+        self.changed.set(tick);
+    }
+}
+
+// 一个 component 的值：加入 space 之前、在 archetype 之间移动时和被移除之后装在 Box 里
+// 记下存放它的列怎么创建，第一次出现这种 component 组合时用来建 archetype
+pub struct FComponentValue {
+    value: Box<dyn Any>,
+    new_column: fn() -> Box<dyn TColumnData>,
+}
+
+impl FComponentValue {
+    pub fn new<T: 'static>(value: T) -> Self {
+        Self {
+            value: Box::new(value),
+            new_column: FTypedColumn::<T>::new_boxed,
+        }
+    }
+
+    pub fn get_type_id(&self) -> TypeId {
+        (*self.value).type_id()
+    }
+
+    pub fn as_any(&self) -> &dyn Any {
+        &*self.value
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.value.downcast_ref::<T>()
+    }
+
+    pub fn downcast<T: 'static>(self) -> Result<T, Self> {
+        let new_column = self.new_column;
+        match self.value.downcast::<T>() {
+            Ok(value) => Ok(*value),
+            Err(value) => Err(Self { value, new_column }),
+        }
+    }
+}
+
+// 一列 component 的值，实际是 FTypedColumn<T>。放进来的值由 FArchetypeStorage 事先检查过类型
+pub trait TColumnData {
+    fn len(&self) -> usize;
+    fn get(&self, row: usize) -> &dyn Any;
+    fn get_mut(&mut self, row: usize) -> &mut dyn Any;
+    fn push(&mut self, value: FComponentValue);
+    // 返回原来的值
+    fn replace(&mut self, row: usize, value: FComponentValue) -> FComponentValue;
+    // 用最后一行填补空位
+    fn swap_remove(&mut self, row: usize) -> FComponentValue;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+pub struct FTypedColumn<T> {
+    data: Vec<T>,
+}
+
+impl<T: 'static> FTypedColumn<T> {
+    fn new_boxed() -> Box<dyn TColumnData> {
+        Box::new(Self { data: vec![] })
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    fn unwrap_value(value: FComponentValue) -> T {
+        match value.downcast::<T>() {
+            Ok(value) => value,
+            Err(_) => panic!("component is not a {}", std::any::type_name::<T>()),
+        }
+    }
+}
+
+impl<T: 'static> TColumnData for FTypedColumn<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn get(&self, row: usize) -> &dyn Any {
+        &self.data[row]
+    }
+
+    fn get_mut(&mut self, row: usize) -> &mut dyn Any {
+        &mut self.data[row]
+    }
+
+    fn push(&mut self, value: FComponentValue) {
+        self.data.push(Self::unwrap_value(value));
+    }
+
+    fn replace(&mut self, row: usize, value: FComponentValue) -> FComponentValue {
+        let value = Self::unwrap_value(value);
+        FComponentValue::new(std::mem::replace(&mut self.data[row], value))
+    }
+
+    fn swap_remove(&mut self, row: usize) -> FComponentValue {
+        FComponentValue::new(self.data.swap_remove(row))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// 借用和 RefCell 一样读写互斥，只是粒度是整列
+pub struct FColumn {
+    component_id: u32,
+    data: RefCell<Box<dyn TColumnData>>,
+    ticks: Vec<FComponentTicks>,
+}
+
+impl FColumn {
+    pub fn get_component_id(&self) -> u32 {
+        self.component_id
+    }
+
+    pub fn get_ticks(&self, row: usize) -> &FComponentTicks {
+        &self.ticks[row]
+    }
+
+    fn get_type_name(&self) -> String {
+        get_component_type_name(self.component_id).unwrap_or(self.component_id.to_string())
+    }
+
+    pub fn try_borrow(&self) -> Result<Ref<'_, dyn TColumnData>, String> {
+        let data = self
+            .data
+            .try_borrow()
+            .map_err(|_| format!("{} is already mutably borrowed", self.get_type_name()))?;
+        Ok(Ref::map(data, |data| &**data))
+    }
+
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, dyn TColumnData>, String> {
+        let data = self
+            .data
+            .try_borrow_mut()
+            .map_err(|_| format!("{} is already borrowed", self.get_type_name()))?;
+        Ok(RefMut::map(data, |data| &mut **data))
+    }
+
+    pub fn borrow_slice<T: 'static>(&self) -> Result<Ref<'_, [T]>, String> {
+        Ref::filter_map(self.try_borrow()?, |data| {
+            data.as_any()
+                .downcast_ref::<FTypedColumn<T>>()
+                .map(|column| column.as_slice())
+        })
+        .map_err(|_| self.type_mismatch::<T>())
+    }
+
+    pub fn borrow_slice_mut<T: 'static>(&self) -> Result<RefMut<'_, [T]>, String> {
+        RefMut::filter_map(self.try_borrow_mut()?, |data| {
+            data.as_any_mut()
+                .downcast_mut::<FTypedColumn<T>>()
+                .map(|column| column.as_mut_slice())
+        })
+        .map_err(|_| self.type_mismatch::<T>())
+    }
+
+    fn type_mismatch<T>(&self) -> String {
+        format!(
+            "{} is not a {}",
+            self.get_type_name(),
+            std::any::type_name::<T>()
+        )
+    }
+}
+
+// 查询在一个 archetype 上借用的一列：只读的项共享借用，可写的项独占借用
+pub enum FColumnBorrow<'a> {
+    Read(Ref<'a, dyn TColumnData>),
+    Write(RefMut<'a, dyn TColumnData>),
+}
+
+impl<'a> FColumnBorrow<'a> {
+    pub fn get(&mut self, row: usize) -> FComponentRef<'_> {
+        match self {
+            FColumnBorrow::Read(data) => FComponentRef::Read(data.get(row)),
+            FColumnBorrow::Write(data) => FComponentRef::Write(data.get_mut(row)),
+        }
+    }
+}
+
+// 借用好的列里的一个 component，可写的项才拿得到 &mut
+pub enum FComponentRef<'a> {
+    Read(&'a dyn Any),
+    Write(&'a mut dyn Any),
+}
+
+impl<'a> FComponentRef<'a> {
+    pub fn as_any(&self) -> &dyn Any {
+        match self {
+            FComponentRef::Read(component) => *component,
+            FComponentRef::Write(component) => &**component,
+        }
+    }
+
+    pub fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        match self {
+            FComponentRef::Read(_) => None,
+            FComponentRef::Write(component) => Some(&mut **component),
+        }
+    }
+}
+
+// 一行里的一个 component 和它的 change tick
+type FCell = (FComponentValue, FComponentTicks);
+
+pub struct FArchetype {
+    // 有序，列和它一一对应
+    component_ids: Vec<u32>,
+    columns: Vec<FColumn>,
+    entities: Vec<FEntityId>,
+}
+
+impl FArchetype {
+    // components 是第一行，用来确定每一列的类型
+    fn new(component_ids: Vec<u32>, components: &[FCell]) -> Self {
+        let columns = component_ids
+            .iter()
+            .zip(components.iter())
+            .map(|(component_id, (component, _))| FColumn {
+                component_id: *component_id,
+                data: RefCell::new((component.new_column)()),
+                ticks: vec![],
+            })
+            .collect();
+        Self {
+            component_ids,
+            columns,
+            entities: vec![],
+        }
+    }
+
+    pub fn get_component_ids(&self) -> &[u32] {
+        &self.component_ids
+    }
+
+    pub fn get_entities(&self) -> &[FEntityId] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn get_column_index(&self, component_id: u32) -> Option<usize> {
+        self.component_ids.binary_search(&component_id).ok()
+    }
+
+    pub fn get_column(&self, index: usize) -> &FColumn {
+        &self.columns[index]
+    }
+
+    fn contains_all(&self, component_ids: &[u32]) -> bool {
+        component_ids
+            .iter()
+            .all(|component_id| self.get_column_index(*component_id).is_some())
+    }
+
+    // components 必须和 component_ids 一一对应
+    fn push(&mut self, entity_id: FEntityId, components: Vec<FCell>) -> usize {
+        debug_assert_eq!(components.len(), self.columns.len());
+        for (column, (component, ticks)) in self.columns.iter_mut().zip(components) {
+            column.data.get_mut().push(component);
+            column.ticks.push(ticks);
+        }
+        self.entities.push(entity_id);
+        self.entities.len() - 1
+    }
+
+    // 用最后一行填补空位，返回被移走的 components 和被移动的 entity（如果有）
+    fn swap_remove(&mut self, row: usize) -> (Vec<FCell>, Option<FEntityId>) {
+        let components = self
+            .columns
+            .iter_mut()
+            .map(|column| {
+                (
+                    column.data.get_mut().swap_remove(row),
+                    column.ticks.swap_remove(row),
+                )
+            })
+            .collect();
+        self.entities.swap_remove(row);
+        (components, self.entities.get(row).cloned())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FEntityLocation {
+    pub archetype: usize,
+    pub row: usize,
+}
+
+// 按 change tick 筛选 entity，比较的是 system 上次执行时的 tick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EChangeFilter {
+    Added,
+    Changed,
+    // component 被移除或者 entity 被 despawn。entity 现在不一定还有这个 component
+    Removed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FComponentAccess {
+    pub component_id: u32,
+    pub write: bool,
+    // 可选的 component 不参与匹配，不存在时得到 None
+    pub optional: bool,
+    // 不为 None 时只用来筛选，不会被借用
+    pub filter: Option<EChangeFilter>,
+}
+
+// 一个查询要访问的 component，顺序和 tick 时拿到的 components 一致
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FQueryDesc {
+    pub components: Vec<FComponentAccess>,
+}
+
+impl FQueryDesc {
+    // 只读且都是必需的 component
+    pub fn from_ids(component_ids: &[u32]) -> Self {
+        Self {
+            components: component_ids
+                .iter()
+                .map(|component_id| FComponentAccess {
+                    component_id: *component_id,
+                    write: false,
+                    optional: false,
+                    filter: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get_required_ids(&self) -> Vec<u32> {
+        self.components
+            .iter()
+            .filter(|access| !access.optional)
+            .map(|access| access.component_id)
+            .collect()
+    }
+
+    pub fn get_optional_ids(&self) -> Vec<u32> {
+        self.components
+            .iter()
+            .filter(|access| access.optional)
+            .map(|access| access.component_id)
+            .collect()
+    }
+
+    // 注册 system 时检查，避免 tick 时借用冲突
+    pub fn validate(&self) -> Result<(), String> {
+        if self.get_required_ids().is_empty() {
+            return Err("a query needs at least one required component".into());
+        }
+        // 筛选条件不借用 component，可以和读写同一个 component 的项同时出现
+        let borrowed: Vec<&FComponentAccess> = self
+            .components
+            .iter()
+            .filter(|access| access.filter.is_none())
+            .collect();
+        for (i, access) in borrowed.iter().enumerate() {
+            if borrowed[..i]
+                .iter()
+                .any(|other| other.component_id == access.component_id)
+            {
+                return Err(format!(
+                    "component {} appears more than once in a query",
+                    get_component_type_name(access.component_id)
+                        .unwrap_or(access.component_id.to_string())
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 一个查询在某个 archetype 上的匹配结果：要求的每个 component 对应的列，可选的 component 可能没有
+#[derive(Clone)]
+pub struct FQueryMatch {
+    pub archetype: usize,
+    pub columns: Vec<Option<usize>>,
+}
+
+struct FQueryCache {
+    // 已经检查过的 archetype 数量。archetype 只增不减，新增的部分再检查即可
+    checked_archetypes: usize,
+    matches: Rc<Vec<FQueryMatch>>,
+}
+
+pub struct FArchetypeStorage {
+    archetypes: Vec<FArchetype>,
+    archetype_index: HashMap<Vec<u32>, usize>,
+    locations: HashMap<FEntityId, FEntityLocation>,
+    query_cache: RefCell<HashMap<FQueryDesc, FQueryCache>>,
+    // component id -> 被移除的 entity 和移除时的 tick
+    removed: HashMap<u32, HashMap<FEntityId, u32>>,
+    // 每个 component id 只能存放一种类型，第一次出现时记下
+    component_types: HashMap<u32, TypeId>,
+}
+
+impl FArchetypeStorage {
+    pub fn new() -> Self {
+        let mut storage = Self {
+            archetypes: vec![],
+            archetype_index: HashMap::new(),
+            locations: HashMap::new(),
+            query_cache: RefCell::new(HashMap::new()),
+            removed: HashMap::new(),
+            component_types: HashMap::new(),
+        };
+        // 没有 component 的 entity 放在 0 号 archetype
+        storage.get_or_create_archetype(vec![], &[]);
+        storage
+    }
+
+    pub fn get_archetypes(&self) -> &[FArchetype] {
+        &self.archetypes
+    }
+
+    pub fn get_archetype(&self, index: usize) -> &FArchetype {
+        &self.archetypes[index]
+    }
+
+    pub fn get_location(&self, entity_id: FEntityId) -> Option<FEntityLocation> {
+        self.locations.get(&entity_id).cloned()
+    }
+
+    pub fn contains(&self, entity_id: FEntityId) -> bool {
+        self.locations.contains_key(&entity_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn entity_ids(&self) -> impl Iterator<Item = FEntityId> + '_ {
+        self.locations.keys().cloned()
+    }
+
+    // components 是要放进去的一行，新建 archetype 时用来确定列的类型
+    fn get_or_create_archetype(&mut self, component_ids: Vec<u32>, components: &[FCell]) -> usize {
+        if let Some(index) = self.archetype_index.get(&component_ids) {
+            return *index;
+        }
+        let index = self.archetypes.len();
+        self.archetypes
+            .push(FArchetype::new(component_ids.clone(), components));
+        self.archetype_index.insert(component_ids, index);
+        index
+    }
+
+    fn check_type(&mut self, component_id: u32, component: &FComponentValue) -> Result<(), String> {
+        let type_id = component.get_type_id();
+        if *self.component_types.entry(component_id).or_insert(type_id) != type_id {
+            return Err(format!(
+                "component {} is stored as another type",
+                get_component_type_name(component_id).unwrap_or(component_id.to_string())
+            ));
+        }
+        Ok(())
+    }
+
+    // 同一个 component id 出现多次时，后面的覆盖前面的
+    pub fn spawn(
+        &mut self,
+        entity_id: FEntityId,
+        components: Vec<(u32, FComponentValue)>,
+        tick: u32,
+    ) -> Result<(), String> {
+        debug_assert!(!self.contains(entity_id));
+
+        let mut components = components;
+        components.reverse();
+        components.sort_by_key(|(component_id, _)| *component_id);
+        components.dedup_by_key(|(component_id, _)| *component_id);
+        for (component_id, component) in components.iter() {
+            self.check_type(*component_id, component)?;
+        }
+
+        let (component_ids, components): (Vec<u32>, Vec<FCell>) = components
+            .into_iter()
+            .map(|(component_id, component)| {
+                (component_id, (component, FComponentTicks::new(tick)))
+            })
+            .unzip();
+        let archetype = self.get_or_create_archetype(component_ids, &components);
+        let row = self.archetypes[archetype].push(entity_id, components);
+        self.locations
+            .insert(entity_id, FEntityLocation { archetype, row });
+        Ok(())
+    }
+
+    // 返回 entity 的所有 component
+    pub fn despawn(
+        &mut self,
+        entity_id: FEntityId,
+        tick: u32,
+    ) -> Option<Vec<(u32, FComponentValue)>> {
+        let location = self.locations.remove(&entity_id)?;
+        let (component_ids, components) = self.take_row(location);
+        for component_id in component_ids.iter() {
+            self.record_removed(*component_id, entity_id, tick);
+        }
+        Some(
+            component_ids
+                .into_iter()
+                .zip(components.into_iter().map(|(component, _)| component))
+                .collect(),
+        )
+    }
+
+    fn take_row(&mut self, location: FEntityLocation) -> (Vec<u32>, Vec<FCell>) {
+        let archetype = &mut self.archetypes[location.archetype];
+        let component_ids = archetype.component_ids.clone();
+        let (components, moved_entity) = archetype.swap_remove(location.row);
+        if let Some(moved_entity) = moved_entity {
+            self.locations.insert(moved_entity, location);
+        }
+        (component_ids, components)
+    }
+
+    // 把 entity 挪到 component 集合为 component_ids 的 archetype
+    fn move_entity(
+        &mut self,
+        entity_id: FEntityId,
+        component_ids: Vec<u32>,
+        components: Vec<FCell>,
+    ) {
+        let archetype = self.get_or_create_archetype(component_ids, &components);
+        let row = self.archetypes[archetype].push(entity_id, components);
+        self.locations
+            .insert(entity_id, FEntityLocation { archetype, row });
+    }
+
+    // 已经有同类 component 时替换，entity 不需要移动，返回被替换的 component。替换也算作新加入
+    pub fn insert_component(
+        &mut self,
+        entity_id: FEntityId,
+        component_id: u32,
+        component: FComponentValue,
+        tick: u32,
+    ) -> Result<Option<FComponentValue>, String> {
+        let location = self
+            .get_location(entity_id)
+            .ok_or(format!("entity {} does not exist", entity_id))?;
+        self.check_type(component_id, &component)?;
+
+        let archetype = &mut self.archetypes[location.archetype];
+        if let Some(column) = archetype.get_column_index(component_id) {
+            let column = &mut archetype.columns[column];
+            let replaced = column.data.get_mut().replace(location.row, component);
+            column.ticks[location.row] = FComponentTicks::new(tick);
+            return Ok(Some(replaced));
+        }
+
+        let (mut component_ids, mut components) = self.take_row(location);
+        let index = component_ids.binary_search(&component_id).unwrap_err();
+        component_ids.insert(index, component_id);
+        components.insert(index, (component, FComponentTicks::new(tick)));
+        self.move_entity(entity_id, component_ids, components);
+        Ok(None)
+    }
+
+    pub fn remove_component(
+        &mut self,
+        entity_id: FEntityId,
+        component_id: u32,
+        tick: u32,
+    ) -> Option<FComponentValue> {
+        let location = self.get_location(entity_id)?;
+        let index = self.archetypes[location.archetype].get_column_index(component_id)?;
+
+        let (mut component_ids, mut components) = self.take_row(location);
+        component_ids.remove(index);
+        let (removed, _) = components.remove(index);
+        self.move_entity(entity_id, component_ids, components);
+        self.record_removed(component_id, entity_id, tick);
+        Some(removed)
+    }
+
+    fn record_removed(&mut self, component_id: u32, entity_id: FEntityId, tick: u32) {
+        self.removed
+            .entry(component_id)
+            .or_default()
+            .insert(entity_id, tick);
+    }
+
+    pub fn is_removed_since(&self, component_id: u32, entity_id: FEntityId, tick: u32) -> bool {
+        self.removed
+            .get(&component_id)
+            .and_then(|removed| removed.get(&entity_id))
+            .map_or(false, |removed_tick| *removed_tick > tick)
+    }
+
+    // since 之后被移除了 component 的 entity，包括已经被 despawn 的，按 id 排序
+    pub fn get_removed_since(&self, component_id: u32, since: u32) -> Vec<FEntityId> {
+        let mut entity_ids: Vec<FEntityId> = match self.removed.get(&component_id) {
+            Some(removed) => removed
+                .iter()
+                .filter(|(_, removed_tick)| **removed_tick > since)
+                .map(|(entity_id, _)| *entity_id)
+                .collect(),
+            None => vec![],
+        };
+        entity_ids.sort();
+        entity_ids
+    }
+
+    // 所有 system 都已经在 tick 之后执行过，更早的移除记录不会再被用到
+    pub fn clear_removed_before(&mut self, tick: u32) {
+        for removed in self.removed.values_mut() {
+            removed.retain(|_, removed_tick| *removed_tick > tick);
+        }
+        self.removed.retain(|_, removed| !removed.is_empty());
+    }
+
+    pub fn get_component_ticks(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Option<&FComponentTicks> {
+        let location = self.get_location(entity_id)?;
+        let archetype = &self.archetypes[location.archetype];
+        let column = archetype.get_column_index(component_id)?;
+        Some(archetype.columns[column].get_ticks(location.row))
+    }
+
+    // 通过 RcObject 直接修改 component 时，用它通知 Changed 筛选
+    pub fn mark_changed(&self, entity_id: FEntityId, component_id: u32, tick: u32) -> bool {
+        match self.get_component_ticks(entity_id, component_id) {
+            Some(ticks) => {
+                ticks.set_changed(tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_column_of(&self, entity_id: FEntityId, component_id: u32) -> Option<(&FColumn, usize)> {
+        let location = self.get_location(entity_id)?;
+        let archetype = &self.archetypes[location.archetype];
+        let column = archetype.get_column_index(component_id)?;
+        Some((&archetype.columns[column], location.row))
+    }
+
+    // 借用 component 所在的整列。entity 没有这个 component 时是 Ok(None)，列已经被借用时返回错误
+    pub fn get_component<T: 'static>(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<Option<Ref<'_, T>>, String> {
+        let (column, row) = match self.get_column_of(entity_id, component_id) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let data = column.borrow_slice::<T>()?;
+        Ok(Some(Ref::map(data, |data| &data[row])))
+    }
+
+    pub fn get_component_mut<T: 'static>(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<Option<RefMut<'_, T>>, String> {
+        let (column, row) = match self.get_column_of(entity_id, component_id) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let data = column.borrow_slice_mut::<T>()?;
+        Ok(Some(RefMut::map(data, |data| &mut data[row])))
+    }
+
+    pub fn get_component_ids(&self, entity_id: FEntityId) -> Option<&[u32]> {
+        let location = self.get_location(entity_id)?;
+        Some(self.archetypes[location.archetype].get_component_ids())
+    }
+
+    // 找出包含全部必需 component 的 archetype，以及每个 component 所在的列（顺序和 desc 一致）
+    pub fn query(&self, desc: &FQueryDesc) -> Rc<Vec<FQueryMatch>> {
+        let mut cache = self.query_cache.borrow_mut();
+        let cached = cache.entry(desc.clone()).or_insert_with(|| FQueryCache {
+            checked_archetypes: 0,
+            matches: Rc::new(vec![]),
+        });
+
+        if cached.checked_archetypes < self.archetypes.len() {
+            let required_ids = desc.get_required_ids();
+            let matches = Rc::make_mut(&mut cached.matches);
+            for (index, archetype) in self
+                .archetypes
+                .iter()
+                .enumerate()
+                .skip(cached.checked_archetypes)
+            {
+                if archetype.contains_all(&required_ids) {
+                    let columns = desc
+                        .components
+                        .iter()
+                        .map(|access| archetype.get_column_index(access.component_id))
+                        .collect();
+                    matches.push(FQueryMatch {
+                        archetype: index,
+                        columns,
+                    });
+                }
+            }
+            cached.checked_archetypes = self.archetypes.len();
+        }
+        cached.matches.clone()
+    }
+
+    // 按查询借用 archetype 里用到的列，顺序和查询一致。只用来筛选的项不借用，是 None
+    pub fn borrow_columns(
+        &self,
+        desc: &FQueryDesc,
+        query_match: &FQueryMatch,
+    ) -> Result<Vec<Option<FColumnBorrow<'_>>>, String> {
+        let archetype = &self.archetypes[query_match.archetype];
+        desc.components
+            .iter()
+            .zip(query_match.columns.iter())
+            .map(|(access, column)| {
+                let column = match (access.filter, column) {
+                    (None, Some(column)) => &archetype.columns[*column],
+                    _ => return Ok(None),
+                };
+                let borrow = match access.write {
+                    true => FColumnBorrow::Write(column.try_borrow_mut()?),
+                    false => FColumnBorrow::Read(column.try_borrow()?),
+                };
+                Ok(Some(borrow))
+            })
+            .collect()
+    }
+
+    // 某一行的 change ticks，顺序和查询一致
+    pub fn fetch_row_ticks(
+        &self,
+        query_match: &FQueryMatch,
+        row: usize,
+    ) -> Vec<Option<&FComponentTicks>> {
+        let archetype = &self.archetypes[query_match.archetype];
+        query_match
+            .columns
+            .iter()
+            .map(|column| column.map(|column| archetype.columns[column].get_ticks(row)))
+            .collect()
+    }
+
+    // 这一行是否满足查询里的变化筛选。since 是 system 上次执行时的 tick
+    pub fn filter_row(
+        &self,
+        desc: &FQueryDesc,
+        query_match: &FQueryMatch,
+        row: usize,
+        since: u32,
+    ) -> bool {
+        let archetype = &self.archetypes[query_match.archetype];
+        desc.components
+            .iter()
+            .zip(query_match.columns.iter())
+            .all(|(access, column)| match (access.filter, column) {
+                (None, _) => true,
+                (Some(EChangeFilter::Added), Some(column)) => archetype.columns[*column]
+                    .get_ticks(row)
+                    .is_added_since(since),
+                (Some(EChangeFilter::Changed), Some(column)) => archetype.columns[*column]
+                    .get_ticks(row)
+                    .is_changed_since(since),
+                (Some(EChangeFilter::Removed), _) => {
+                    self.is_removed_since(access.component_id, archetype.entities[row], since)
+                }
+                (Some(_), None) => false,
+            })
+    }
+
+    // 满足查询的 entity，不检查变化筛选
+    pub fn query_entities(&self, desc: &FQueryDesc) -> Vec<FEntityId> {
+        let mut result = vec![];
+        for query_match in self.query(desc).iter() {
+            result.extend_from_slice(&self.archetypes[query_match.archetype].entities);
+        }
+        result
+    }
+
+    // 依次修改所有 entity 的某种 component，每个 archetype 整列借用一次
+    pub fn for_each_mut<T: 'static>(
+        &self,
+        component_id: u32,
+        mut f: impl FnMut(FEntityId, &mut T),
+    ) -> Result<(), String> {
+        let desc = FQueryDesc::from_ids(&[component_id]);
+        for query_match in self.query(&desc).iter() {
+            let archetype = &self.archetypes[query_match.archetype];
+            if let Some(column) = query_match.columns[0] {
+                let mut data = archetype.columns[column].borrow_slice_mut::<T>()?;
+                for (entity_id, component) in archetype.entities.iter().zip(data.iter_mut()) {
+                    f(*entity_id, component);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::components::{component_id, TComponent};
+
+    struct FPosition(f32);
+    struct FVelocity(f32);
+
+    impl TComponent for FPosition {
+        fn type_name() -> &'static str {
+            "FPosition"
+        }
+    }
+
+    impl TComponent for FVelocity {
+        fn type_name() -> &'static str {
+            "FVelocity"
+        }
+    }
+
+    fn position(value: f32) -> (u32, FComponentValue) {
+        (
+            component_id::<FPosition>(),
+            FComponentValue::new(FPosition(value)),
+        )
+    }
+
+    fn velocity(value: f32) -> (u32, FComponentValue) {
+        (
+            component_id::<FVelocity>(),
+            FComponentValue::new(FVelocity(value)),
+        )
+    }
+
+    fn get_position(storage: &FArchetypeStorage, entity_id: FEntityId) -> f32 {
+        let position = storage
+            .get_component::<FPosition>(entity_id, component_id::<FPosition>())
+            .unwrap()
+            .unwrap();
+        position.0
+    }
+
+    fn entity(index: u32) -> FEntityId {
+        FEntityId::new(index, 0)
+    }
+
+    #[test]
+    fn spawn_groups_entities_by_component_set() {
+        let mut storage = FArchetypeStorage::new();
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+        storage
+            .spawn(entity(1), vec![velocity(1.0), position(1.0)], 1)
+            .unwrap();
+        storage
+            .spawn(entity(2), vec![position(2.0), velocity(2.0)], 1)
+            .unwrap();
+        storage.spawn(entity(3), vec![], 1).unwrap();
+        // 同一个 component 出现多次时保留后面的
+        storage
+            .spawn(entity(4), vec![position(4.0), position(5.0)], 1)
+            .unwrap();
+
+        let location = |index| storage.get_location(entity(index)).unwrap();
+        // component 的顺序不影响 archetype
+        assert_eq!(location(1).archetype, location(2).archetype);
+        assert_ne!(location(0).archetype, location(1).archetype);
+        assert_eq!(location(3).archetype, 0);
+        assert_eq!((location(1).row, location(2).row), (0, 1));
+        assert_eq!(storage.len(), 5);
+        assert_eq!(get_position(&storage, entity(4)), 5.0);
+        assert_eq!(location(4).archetype, location(0).archetype);
+    }
+
+    #[test]
+    fn insert_and_remove_move_entity_between_archetypes() {
+        let mut storage = FArchetypeStorage::new();
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+        storage.spawn(entity(1), vec![position(1.0)], 1).unwrap();
+        storage
+            .spawn(entity(2), vec![position(2.0), velocity(2.0)], 1)
+            .unwrap();
+        let position_only = storage.get_location(entity(0)).unwrap().archetype;
+        let with_velocity = storage.get_location(entity(2)).unwrap().archetype;
+
+        let (velocity_id, component) = velocity(0.0);
+        storage
+            .insert_component(entity(0), velocity_id, component, 2)
+            .unwrap();
+        assert_eq!(
+            storage.get_location(entity(0)),
+            Some(FEntityLocation {
+                archetype: with_velocity,
+                row: 1
+            })
+        );
+        // 最后一行填补了 entity 0 留下的空位
+        assert_eq!(
+            storage.get_location(entity(1)),
+            Some(FEntityLocation {
+                archetype: position_only,
+                row: 0
+            })
+        );
+        assert_eq!(get_position(&storage, entity(0)), 0.0);
+        assert_eq!(get_position(&storage, entity(1)), 1.0);
+
+        // 已经有同类 component 时原地替换
+        let (position_id, component) = position(10.0);
+        storage
+            .insert_component(entity(0), position_id, component, 3)
+            .unwrap();
+        assert_eq!(
+            storage.get_location(entity(0)).unwrap().archetype,
+            with_velocity
+        );
+        assert_eq!(get_position(&storage, entity(0)), 10.0);
+
+        let removed = storage.remove_component(entity(2), velocity_id, 4).unwrap();
+        let removed = removed.downcast::<FVelocity>().ok().unwrap();
+        assert_eq!(removed.0, 2.0);
+        assert_eq!(
+            storage.get_location(entity(2)).unwrap().archetype,
+            position_only
+        );
+        assert_eq!(storage.get_location(entity(0)).unwrap().row, 0);
+        assert_eq!(
+            storage.get_component_ids(entity(2)),
+            Some(&[position_id][..])
+        );
+        assert!(storage
+            .remove_component(entity(2), velocity_id, 5)
+            .is_none());
+
+        let (position_id, component) = position(0.0);
+        assert!(storage
+            .insert_component(entity(9), position_id, component, 5)
+            .is_err());
+    }
+
+    #[test]
+    fn despawn_returns_components_and_fills_the_row() {
+        let mut storage = FArchetypeStorage::new();
+        storage
+            .spawn(entity(0), vec![position(0.0), velocity(0.0)], 1)
+            .unwrap();
+        storage
+            .spawn(entity(1), vec![position(1.0), velocity(1.0)], 1)
+            .unwrap();
+
+        let components = storage.despawn(entity(0), 2).unwrap();
+        let ids: Vec<u32> = components.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&component_id::<FPosition>()));
+        assert!(ids.contains(&component_id::<FVelocity>()));
+
+        assert!(!storage.contains(entity(0)));
+        assert_eq!(storage.get_location(entity(1)).unwrap().row, 0);
+        assert_eq!(get_position(&storage, entity(1)), 1.0);
+        assert!(storage.despawn(entity(0), 3).is_none());
+    }
+
+    #[test]
+    fn query_cache_picks_up_new_archetypes() {
+        let mut storage = FArchetypeStorage::new();
+        let desc = FQueryDesc {
+            components: vec![
+                FComponentAccess {
+                    component_id: component_id::<FPosition>(),
+                    write: false,
+                    optional: false,
+                    filter: None,
+                },
+                FComponentAccess {
+                    component_id: component_id::<FVelocity>(),
+                    write: false,
+                    optional: true,
+                    filter: None,
+                },
+            ],
+        };
+
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+        let matches = storage.query(&desc);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].columns[1], None);
+
+        // 之后才出现的 archetype 也要匹配上
+        storage
+            .spawn(entity(1), vec![position(1.0), velocity(1.0)], 1)
+            .unwrap();
+        storage.spawn(entity(2), vec![velocity(2.0)], 1).unwrap();
+        let matches = storage.query(&desc);
+        assert_eq!(matches.len(), 2);
+        assert!(matches[1].columns[1].is_some());
+
+        let mut entities = storage.query_entities(&desc);
+        entities.sort();
+        assert_eq!(entities, vec![entity(0), entity(1)]);
+    }
+
+    #[test]
+    fn query_desc_rejects_invalid_queries() {
+        let position_id = component_id::<FPosition>();
+        assert!(FQueryDesc::from_ids(&[position_id]).validate().is_ok());
+        assert!(FQueryDesc::from_ids(&[]).validate().is_err());
+        let err = FQueryDesc::from_ids(&[position_id, position_id])
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("FPosition"));
+    }
+
+    fn access(
+        component_id: u32,
+        optional: bool,
+        filter: Option<EChangeFilter>,
+    ) -> FComponentAccess {
+        FComponentAccess {
+            component_id,
+            write: false,
+            optional,
+            filter,
+        }
+    }
+
+    // 和 HSpace::run_batch 一样先匹配 archetype 再逐行筛选
+    fn get_filtered(storage: &FArchetypeStorage, desc: &FQueryDesc, since: u32) -> Vec<FEntityId> {
+        let mut result = vec![];
+        for query_match in storage.query(desc).iter() {
+            let archetype = storage.get_archetype(query_match.archetype);
+            for (row, entity_id) in archetype.get_entities().iter().enumerate() {
+                if storage.filter_row(desc, query_match, row, since) {
+                    result.push(*entity_id);
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn added_and_changed_ticks_survive_archetype_moves() {
+        let position_id = component_id::<FPosition>();
+        let velocity_id = component_id::<FVelocity>();
+        let added = FQueryDesc {
+            components: vec![access(position_id, false, Some(EChangeFilter::Added))],
+        };
+        let changed = FQueryDesc {
+            components: vec![access(position_id, false, Some(EChangeFilter::Changed))],
+        };
+        let velocity_added = FQueryDesc {
+            components: vec![access(velocity_id, false, Some(EChangeFilter::Added))],
+        };
+
+        let mut storage = FArchetypeStorage::new();
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+        storage
+            .spawn(entity(1), vec![position(1.0), velocity(1.0)], 1)
+            .unwrap();
+        assert_eq!(
+            get_filtered(&storage, &added, 0),
+            vec![entity(0), entity(1)]
+        );
+        assert!(get_filtered(&storage, &added, 1).is_empty());
+
+        assert!(storage.mark_changed(entity(0), position_id, 3));
+        assert!(!storage.mark_changed(entity(0), velocity_id, 3));
+        assert_eq!(get_filtered(&storage, &changed, 2), vec![entity(0)]);
+        assert!(get_filtered(&storage, &added, 2).is_empty());
+
+        // 挪到别的 archetype 之后 FPosition 的 tick 不变
+        let (_, component) = velocity(0.0);
+        storage
+            .insert_component(entity(0), velocity_id, component, 4)
+            .unwrap();
+        let ticks = storage.get_component_ticks(entity(0), position_id).unwrap();
+        assert_eq!((ticks.get_added(), ticks.get_changed()), (1, 3));
+        assert_eq!(get_filtered(&storage, &changed, 2), vec![entity(0)]);
+        assert_eq!(get_filtered(&storage, &velocity_added, 3), vec![entity(0)]);
+        assert!(get_filtered(&storage, &added, 3).is_empty());
+
+        // 替换算作新加入
+        let (_, component) = position(5.0);
+        storage
+            .insert_component(entity(1), position_id, component, 5)
+            .unwrap();
+        assert_eq!(get_filtered(&storage, &added, 4), vec![entity(1)]);
+        assert_eq!(get_filtered(&storage, &changed, 4), vec![entity(1)]);
+    }
+
+    #[test]
+    fn removed_filter_sees_removals_until_cleared() {
+        let position_id = component_id::<FPosition>();
+        let velocity_id = component_id::<FVelocity>();
+        let removed = FQueryDesc {
+            components: vec![
+                access(position_id, false, None),
+                access(velocity_id, true, Some(EChangeFilter::Removed)),
+            ],
+        };
+        assert!(removed.validate().is_ok());
+
+        let mut storage = FArchetypeStorage::new();
+        storage
+            .spawn(entity(0), vec![position(0.0), velocity(0.0)], 1)
+            .unwrap();
+        storage
+            .spawn(entity(1), vec![position(1.0), velocity(1.0)], 1)
+            .unwrap();
+        storage.remove_component(entity(1), velocity_id, 3).unwrap();
+        assert_eq!(get_filtered(&storage, &removed, 2), vec![entity(1)]);
+        assert!(get_filtered(&storage, &removed, 3).is_empty());
+
+        // despawn 的 entity 查询不到，只能通过移除记录得知
+        storage.despawn(entity(0), 4);
+        assert!(storage.is_removed_since(position_id, entity(0), 3));
+        assert!(storage.is_removed_since(velocity_id, entity(0), 3));
+
+        storage.clear_removed_before(3);
+        assert!(get_filtered(&storage, &removed, 2).is_empty());
+        assert!(storage.is_removed_since(position_id, entity(0), 3));
+        storage.clear_removed_before(4);
+        assert!(!storage.is_removed_since(position_id, entity(0), 0));
+    }
+
+    #[test]
+    fn removed_since_lists_despawned_entities() {
+        let position_id = component_id::<FPosition>();
+        let velocity_id = component_id::<FVelocity>();
+
+        let mut storage = FArchetypeStorage::new();
+        storage
+            .spawn(entity(0), vec![position(0.0), velocity(0.0)], 1)
+            .unwrap();
+        storage.spawn(entity(1), vec![position(1.0)], 1).unwrap();
+        storage
+            .spawn(entity(2), vec![position(2.0), velocity(2.0)], 1)
+            .unwrap();
+
+        storage.despawn(entity(2), 3);
+        storage.remove_component(entity(0), velocity_id, 4).unwrap();
+        storage.despawn(entity(1), 5);
+
+        assert_eq!(
+            storage.get_removed_since(velocity_id, 2),
+            vec![entity(0), entity(2)]
+        );
+        assert_eq!(storage.get_removed_since(velocity_id, 3), vec![entity(0)]);
+        assert_eq!(
+            storage.get_removed_since(position_id, 2),
+            vec![entity(1), entity(2)]
+        );
+        assert!(storage.get_removed_since(position_id, 5).is_empty());
+
+        storage.clear_removed_before(4);
+        assert_eq!(storage.get_removed_since(position_id, 0), vec![entity(1)]);
+        assert!(storage.get_removed_since(velocity_id, 0).is_empty());
+    }
+
+    #[test]
+    fn columns_store_values_contiguously_and_borrow_as_a_whole() {
+        let position_id = component_id::<FPosition>();
+        let desc = FQueryDesc {
+            components: vec![FComponentAccess {
+                component_id: position_id,
+                write: true,
+                optional: false,
+                filter: None,
+            }],
+        };
+
+        let mut storage = FArchetypeStorage::new();
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+        storage.spawn(entity(1), vec![position(1.0)], 1).unwrap();
+        storage.spawn(entity(2), vec![position(2.0)], 1).unwrap();
+
+        let query_match = storage.query(&desc)[0].clone();
+        let column = storage
+            .get_archetype(query_match.archetype)
+            .get_column(query_match.columns[0].unwrap());
+        {
+            let positions = column.borrow_slice::<FPosition>().unwrap();
+            let values: Vec<f32> = positions.iter().map(|position| position.0).collect();
+            assert_eq!(values, vec![0.0, 1.0, 2.0]);
+            // 读的时候整列都不能写
+            let err = storage
+                .get_component_mut::<FPosition>(entity(2), position_id)
+                .err()
+                .unwrap();
+            assert_eq!(err, "FPosition is already borrowed");
+            assert!(storage.borrow_columns(&desc, &query_match).is_err());
+            assert!(column.borrow_slice::<FVelocity>().is_err());
+        }
+
+        {
+            let mut columns = storage.borrow_columns(&desc, &query_match).unwrap();
+            let column = columns[0].as_mut().unwrap();
+            if let FComponentRef::Write(position) = column.get(1) {
+                position.downcast_mut::<FPosition>().unwrap().0 = 10.0;
+            }
+            assert!(storage
+                .get_component::<FPosition>(entity(0), position_id)
+                .is_err());
+        }
+        assert_eq!(get_position(&storage, entity(1)), 10.0);
+
+        storage
+            .for_each_mut::<FPosition>(position_id, |_, position| position.0 += 1.0)
+            .unwrap();
+        assert_eq!(get_position(&storage, entity(2)), 3.0);
+    }
+
+    #[test]
+    fn a_component_id_keeps_one_type() {
+        let position_id = component_id::<FPosition>();
+        let mut storage = FArchetypeStorage::new();
+        storage.spawn(entity(0), vec![position(0.0)], 1).unwrap();
+
+        let wrong = FComponentValue::new(FVelocity(0.0));
+        assert!(storage
+            .insert_component(entity(0), position_id, wrong, 2)
+            .is_err());
+        let wrong = FComponentValue::new(FVelocity(1.0));
+        assert!(storage
+            .spawn(entity(1), vec![(position_id, wrong)], 2)
+            .is_err());
+        assert!(!storage.contains(entity(1)));
+        assert_eq!(get_position(&storage, entity(0)), 0.0);
+    }
+}
@@ -0,0 +1,135 @@
+// space tick benchmark：大量 entity 走一遍内置的 system，再交给 renderer 画出来
+// 相机要 FPipelineContext，离不开 HooEngine，所以会开一个不可见的窗口，需要 GPU
+
+use std::time::{Duration, Instant};
+
+use hoo_object::RcObject;
+use nalgebra_glm as glm;
+use winit::{dpi::PhysicalSize, event_loop::EventLoop, window::WindowBuilder};
+
+use crate::{initialize_hoo_engine, rcmut, HooEngine};
+
+use super::{
+    components::{HCameraComponent, HLightComponent, HStaticModelComponent, HTransformComponent},
+    entity::HEntity,
+    objects::{FCameraProjection, FColor, HCamera, HLight, HMaterial, HStaticMesh, HStaticModel},
+    resources::{FRenderQueue, FRenderTargets},
+    space::HSpace,
+};
+
+pub struct FSpaceTickTimings {
+    pub entity_count: usize,
+    pub frames: u32,
+    pub setup: Duration,
+    // 平均每帧的耗时，tick 只算 space.tick，render 是提交 pipeline 和 renderer.next_frame
+    pub tick: Duration,
+    pub render: Duration,
+}
+
+fn create_camera_entity() -> HEntity {
+    let mut entity = HEntity::new();
+    entity.insert(HTransformComponent::new_face_at(
+        &glm::vec3(0.0, 50.0, 30.0),
+        &glm::vec3(0.0, 0.0, 0.0),
+        &glm::vec3(0.0, 0.0, 1.0),
+    ));
+    let camera = RcObject::new(HCamera::new(FCameraProjection::Perspective {
+        fov: 45.0f32.to_radians(),
+        aspect: 800.0 / 600.0,
+        near: 0.1,
+        far: 1000.0,
+    }));
+    entity.insert(HCameraComponent::new(camera));
+    entity
+}
+
+// 每 4 个 entity 里 1 个是灯光，其余是模型（也会被 HRotatingSystem 旋转），另外还有一个主相机
+// 每个线程只能调用一次：HooEngine 是线程内的单例
+pub fn benchmark_space_tick(entity_count: usize, frames: u32) -> FSpaceTickTimings {
+    let frames = frames.max(1);
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_visible(false)
+        .with_inner_size(PhysicalSize::new(800, 600))
+        .build(&event_loop)
+        .unwrap();
+    let window = rcmut!(window);
+    let hoo_engine = futures::executor::block_on(HooEngine::new_async(&window));
+    initialize_hoo_engine(hoo_engine.clone());
+    // 不调用 HooEngine::prepare，不需要 demo space
+    hoo_engine.borrow().get_renderer_mut().prepare();
+
+    let begin = Instant::now();
+    let mut space = HSpace::new();
+    let model = RcObject::new(HStaticModel {
+        mesh: RcObject::new(HStaticMesh::new("meshes/cube.gltf".into())),
+        material: RcObject::new(HMaterial::new("shaders/main.wgsl".into())),
+    });
+    for i in 0..entity_count {
+        let mut entity = HEntity::new();
+        let transform = HTransformComponent::new_trs(
+            &glm::vec3(i as f32, 0.0, 0.0),
+            &glm::quat(0.0, 0.0, 0.0, 1.0),
+            &glm::vec3(1.0, 1.0, 1.0),
+        );
+        entity.insert(transform);
+
+        if i % 4 == 0 {
+            let light = HLightComponent {
+                light: RcObject::new(HLight::new_point(FColor::new(1.0, 1.0, 1.0), 10.0)),
+            };
+            entity.insert(light);
+        } else {
+            let static_model = HStaticModelComponent {
+                model: model.clone(),
+            };
+            entity.insert(static_model);
+        }
+        space.add_entity(entity).unwrap();
+    }
+    let camera_id = space.add_entity(create_camera_entity()).unwrap();
+    space.set_main_camera_entity(camera_id).unwrap();
+
+    space.add_builtin_systems().unwrap();
+    let setup = begin.elapsed();
+
+    // 和 HContext::tick_with_renderer 一样，每帧放入 FRenderTargets / FRenderQueue
+    let mut tick = Duration::ZERO;
+    let mut render = Duration::ZERO;
+    for _ in 0..frames {
+        let begin = Instant::now();
+        let render_targets = {
+            let engine = hoo_engine.borrow();
+            let renderer = engine.get_renderer();
+            FRenderTargets {
+                swapchain_size: renderer.get_swapchain_size(),
+                main_viewport_target: renderer.get_main_viewport_target(),
+            }
+        };
+        space.insert_resource(render_targets);
+        space.insert_resource(FRenderQueue::default());
+        space.tick(1.0 / 60.0);
+        let pipelines = match space.remove_resource::<FRenderQueue>() {
+            Some(render_queue) => std::mem::take(&mut render_queue.borrow_mut().pipelines),
+            None => vec![],
+        };
+        tick += begin.elapsed();
+
+        let begin = Instant::now();
+        let engine = hoo_engine.borrow();
+        for pipeline in pipelines {
+            engine.get_renderer().submit_pipeline(pipeline);
+        }
+        engine.get_renderer_mut().next_frame();
+        render += begin.elapsed();
+    }
+
+    FSpaceTickTimings {
+        entity_count,
+        frames,
+        setup,
+        tick: tick / frames,
+        render: render / frames,
+    }
+}
@@ -0,0 +1,103 @@
+// 延迟执行的结构性修改：system 只拿得到 &HSpace，spawn / despawn / 增删 component 先记下来，
+// 等到 system 之间的同步点再由 HSpace::apply_commands 执行
+// 同步点：所有 begin_frame 之后、每个 system 遍历完 entity 之后、所有 end_frame 之后
+
+use std::cell::RefCell;
+
+use super::{
+    archetype::FComponentValue,
+    components::{component_id, TComponent},
+    entity::{FEntityAllocator, FEntityId, HEntity},
+};
+
+pub enum ECommand {
+    Spawn(FEntityId, HEntity),
+    Despawn(FEntityId),
+    AddComponent(FEntityId, u32, FComponentValue),
+    RemoveComponent(FEntityId, u32),
+}
+
+// HSpace::commands 返回的句柄，可以同时存在多个，命令按调用顺序执行
+pub struct FCommands<'space> {
+    pub(crate) queue: &'space RefCell<Vec<ECommand>>,
+    pub(crate) entities: &'space RefCell<FEntityAllocator>,
+}
+
+impl<'space> FCommands<'space> {
+    // id 立即分配，后面的命令可以直接使用
+    pub fn spawn(&self, entity: HEntity) -> Result<FEntityId, String> {
+        let entity_id = self.entities.borrow_mut().allocate()?;
+        self.push(ECommand::Spawn(entity_id, entity));
+        Ok(entity_id)
+    }
+
+    pub fn despawn(&self, entity_id: FEntityId) {
+        self.push(ECommand::Despawn(entity_id));
+    }
+
+    pub fn add_component(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+        component: FComponentValue,
+    ) {
+        self.push(ECommand::AddComponent(entity_id, component_id, component));
+    }
+
+    pub fn insert<T: TComponent>(&self, entity_id: FEntityId, component: T) {
+        self.add_component(
+            entity_id,
+            component_id::<T>(),
+            FComponentValue::new(component),
+        );
+    }
+
+    pub fn remove_component_by_id(&self, entity_id: FEntityId, component_id: u32) {
+        self.push(ECommand::RemoveComponent(entity_id, component_id));
+    }
+
+    pub fn remove_component<T: TComponent>(&self, entity_id: FEntityId) {
+        self.remove_component_by_id(entity_id, component_id::<T>());
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    fn push(&self, command: ECommand) {
+        self.queue.borrow_mut().push(command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_reserves_the_id_and_commands_keep_their_order() {
+        let queue = RefCell::new(vec![]);
+        let entities = RefCell::new(FEntityAllocator::new());
+        let commands = FCommands {
+            queue: &queue,
+            entities: &entities,
+        };
+
+        let entity_id = commands.spawn(HEntity::new()).unwrap();
+        // 后面的命令可以直接用这个 id，之后分配的 id 也不会和它冲突
+        assert!(entities.borrow().is_alive(entity_id));
+        assert_ne!(entities.borrow_mut().allocate().unwrap(), entity_id);
+
+        commands.remove_component_by_id(entity_id, 0);
+        commands.despawn(entity_id);
+        assert_eq!(commands.len(), 3);
+
+        let queue = queue.borrow();
+        assert!(matches!(queue[0], ECommand::Spawn(id, _) if id == entity_id));
+        assert!(matches!(queue[1], ECommand::RemoveComponent(id, 0) if id == entity_id));
+        assert!(matches!(queue[2], ECommand::Despawn(id) if id == entity_id));
+    }
+}
@@ -0,0 +1,72 @@
+// 父子关系。只能通过 HSpace::set_parent 修改，两边的 component 由它一起维护
+// HTransformComponent 的 position / rotation / scale 是相对父节点的，
+// 父节点没有 HTransformComponent 时相对世界
+
+use nalgebra_glm as glm;
+
+use crate::object::entity::FEntityId;
+
+use super::TComponent;
+
+impl TComponent for HParentComponent {
+    fn type_name() -> &'static str {
+        "HParentComponent"
+    }
+}
+
+impl TComponent for HChildrenComponent {
+    fn type_name() -> &'static str {
+        "HChildrenComponent"
+    }
+}
+
+pub struct HParentComponent {
+    pub(crate) parent: FEntityId,
+}
+
+impl HParentComponent {
+    pub fn get_parent(&self) -> FEntityId {
+        self.parent
+    }
+}
+
+pub struct HChildrenComponent {
+    pub(crate) children: Vec<FEntityId>,
+}
+
+impl HChildrenComponent {
+    pub fn get_children(&self) -> &[FEntityId] {
+        &self.children
+    }
+}
+
+// HTransformComponent 里缓存的世界变换，由 HTransformSystem 维护
+#[derive(Clone, Default)]
+pub struct FWorldTransform {
+    pub(crate) matrix: glm::Mat4,
+    // 上次计算世界变换时的 position / rotation / scale，和当前值不同说明需要重新计算
+    pub(crate) propagated_local: Option<(glm::Vec3, glm::Quat, glm::Vec3)>,
+}
+
+// 把不含切变的矩阵拆成 position / rotation / scale
+pub fn decompose_matrix(matrix: &glm::Mat4) -> (glm::Vec3, glm::Quat, glm::Vec3) {
+    let position = matrix.column(3).xyz();
+    let axes: Vec<glm::Vec3> = (0..3).map(|i| matrix.column(i).xyz()).collect();
+    let scale = glm::vec3(
+        glm::length(&axes[0]),
+        glm::length(&axes[1]),
+        glm::length(&axes[2]),
+    );
+    let rotation = glm::mat3_to_quat(&glm::mat3(
+        axes[0].x / scale.x,
+        axes[1].x / scale.y,
+        axes[2].x / scale.z,
+        axes[0].y / scale.x,
+        axes[1].y / scale.y,
+        axes[2].y / scale.z,
+        axes[0].z / scale.x,
+        axes[1].z / scale.y,
+        axes[2].z / scale.z,
+    ));
+    (position, rotation, scale)
+}
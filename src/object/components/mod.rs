@@ -1,3 +1,11 @@
+mod hierarchy;
+mod name;
+mod registry;
+mod scripted;
 mod trivial;
 
+pub use hierarchy::*;
+pub use name::*;
+pub use registry::*;
+pub use scripted::*;
 pub use trivial::*;
@@ -0,0 +1,84 @@
+// entity 的名字和标签。创建后不能修改，只能通过 HSpace 的 set_name / add_tag 等方法整个替换，
+// HSpace 里的索引在 component 增删时一起维护
+// 名字不要求唯一，但不能为空，也不能包含路径分隔符 '/'
+
+use std::collections::BTreeSet;
+
+use super::TComponent;
+
+pub struct HNameComponent {
+    name: String,
+}
+
+impl HNameComponent {
+    pub fn new(name: &str) -> Result<Self, String> {
+        validate_name(name)?;
+        Ok(Self {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl TComponent for HNameComponent {
+    fn type_name() -> &'static str {
+        "HNameComponent"
+    }
+}
+
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("entity name cannot be empty".into());
+    }
+    if name.contains('/') {
+        return Err(format!("entity name {} cannot contain '/'", name));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+pub struct HTagsComponent {
+    tags: BTreeSet<String>,
+}
+
+impl HTagsComponent {
+    pub fn new(tags: &[&str]) -> Self {
+        Self {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn get_tags(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tags.iter().map(|tag| tag.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    // 返回新的 component，由 HSpace 替换掉原来的
+    pub(crate) fn with_tag(&self, tag: &str) -> Self {
+        let mut tags = self.clone();
+        tags.tags.insert(tag.to_string());
+        tags
+    }
+
+    pub(crate) fn without_tag(&self, tag: &str) -> Self {
+        let mut tags = self.clone();
+        tags.tags.remove(tag);
+        tags
+    }
+}
+
+impl TComponent for HTagsComponent {
+    fn type_name() -> &'static str {
+        "HTagsComponent"
+    }
+}
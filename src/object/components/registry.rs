@@ -0,0 +1,89 @@
+// component 类型注册表：Rust component 按类型自动分配 id，脚本 component 按注册时的名字分配 id
+// 两者共用一套 id 和名字，脚本用名字指代任意一种 component
+
+use std::{any::TypeId, cell::RefCell, collections::HashMap};
+
+use super::{
+    HAxisComponent, HCameraComponent, HChildrenComponent, HLightComponent, HNameComponent,
+    HParentComponent, HStaticModelComponent, HTagsComponent, HTransformComponent,
+};
+
+// 可以放进 HSpace 的 Rust component。脚本 component 都是 HScriptComponent，id 由 schema 决定，不实现这个 trait
+pub trait TComponent: 'static {
+    // 脚本里用这个名字指代 component
+    fn type_name() -> &'static str;
+}
+
+struct FComponentRegistry {
+    next_component_id: u32,
+    ids_by_type: HashMap<TypeId, u32>,
+    ids_by_name: HashMap<String, u32>,
+    names: HashMap<u32, String>,
+}
+
+impl FComponentRegistry {
+    fn new() -> Self {
+        let mut registry = Self {
+            next_component_id: 0,
+            ids_by_type: HashMap::new(),
+            ids_by_name: HashMap::new(),
+            names: HashMap::new(),
+        };
+        // 内置的 component 一开始就注册，脚本在用到之前就能按名字找到
+        registry.register::<HStaticModelComponent>();
+        registry.register::<HTransformComponent>();
+        registry.register::<HCameraComponent>();
+        registry.register::<HLightComponent>();
+        registry.register::<HAxisComponent>();
+        registry.register::<HParentComponent>();
+        registry.register::<HChildrenComponent>();
+        registry.register::<HNameComponent>();
+        registry.register::<HTagsComponent>();
+        registry
+    }
+
+    fn allocate(&mut self, name: &str) -> Result<u32, String> {
+        if self.ids_by_name.contains_key(name) {
+            return Err(format!("component type {} is already registered", name));
+        }
+        let component_id = self.next_component_id;
+        self.next_component_id += 1;
+        self.ids_by_name.insert(name.to_string(), component_id);
+        self.names.insert(component_id, name.to_string());
+        Ok(component_id)
+    }
+
+    fn register<T: TComponent>(&mut self) -> u32 {
+        if let Some(component_id) = self.ids_by_type.get(&TypeId::of::<T>()) {
+            return *component_id;
+        }
+        let component_id = self
+            .allocate(T::type_name())
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.ids_by_type.insert(TypeId::of::<T>(), component_id);
+        component_id
+    }
+}
+
+thread_local! {
+    static COMPONENT_REGISTRY: RefCell<FComponentRegistry> = RefCell::new(FComponentRegistry::new());
+}
+
+// 第一次用到时注册
+pub fn component_id<T: TComponent>() -> u32 {
+    COMPONENT_REGISTRY.with(|registry| registry.borrow_mut().register::<T>())
+}
+
+// 给脚本 component 分配 id，名字不能和已有的 component 重复
+pub(crate) fn allocate_component_id(type_name: &str) -> Result<u32, String> {
+    COMPONENT_REGISTRY.with(|registry| registry.borrow_mut().allocate(type_name))
+}
+
+// 脚本用类型名指代 component：内置的用 Rust 类型名，脚本 component 用注册时的名字
+pub fn get_component_id_by_type_name(type_name: &str) -> Option<u32> {
+    COMPONENT_REGISTRY.with(|registry| registry.borrow().ids_by_name.get(type_name).cloned())
+}
+
+pub fn get_component_type_name(component_id: u32) -> Option<String> {
+    COMPONENT_REGISTRY.with(|registry| registry.borrow().names.get(&component_id).cloned())
+}
@@ -0,0 +1,529 @@
+// 脚本定义的 component：脚本只声明数据结构（见 README），数据本身存在 Rust 侧
+// 这样 Rust 的 system、editor 和序列化都可以直接读取，不需要调用 js
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use hoo_meta::{GetJsValue, JsException, TryFromJsValue, TryFromJsValueError};
+use hoo_meta_macros::{js_impl, JsStruct};
+use hoo_object::exports::*;
+use nalgebra_glm as glm;
+use serde::{ser::SerializeMap, Deserialize, Serialize};
+
+use super::allocate_component_id;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EScriptFieldType {
+    Number,
+    String,
+    Bool,
+    Vec3,
+    Entity,
+}
+
+impl EScriptFieldType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "number" => Some(EScriptFieldType::Number),
+            "string" => Some(EScriptFieldType::String),
+            "bool" => Some(EScriptFieldType::Bool),
+            "vec3" => Some(EScriptFieldType::Vec3),
+            "entity" => Some(EScriptFieldType::Entity),
+            _ => None,
+        }
+    }
+
+    pub fn default_value(&self) -> EScriptFieldValue {
+        match self {
+            EScriptFieldType::Number => EScriptFieldValue::Number(0.0),
+            EScriptFieldType::String => EScriptFieldValue::String(String::new()),
+            EScriptFieldType::Bool => EScriptFieldValue::Bool(false),
+            EScriptFieldType::Vec3 => EScriptFieldValue::Vec3(glm::vec3(0.0, 0.0, 0.0)),
+            EScriptFieldType::Entity => EScriptFieldValue::Entity(None),
+        }
+    }
+
+    // Rust 侧赋值时类型必须和声明一致
+    pub fn check(&self, value: EScriptFieldValue) -> Result<EScriptFieldValue, String> {
+        match (self, value) {
+            (EScriptFieldType::Number, value @ EScriptFieldValue::Number(_))
+            | (EScriptFieldType::String, value @ EScriptFieldValue::String(_))
+            | (EScriptFieldType::Bool, value @ EScriptFieldValue::Bool(_))
+            | (EScriptFieldType::Vec3, value @ EScriptFieldValue::Vec3(_))
+            | (EScriptFieldType::Entity, value @ EScriptFieldValue::Entity(_)) => Ok(value),
+            (field_type, value) => Err(format!(
+                "expected a value of type {:?}, got {:?}",
+                field_type, value
+            )),
+        }
+    }
+
+    // js 侧的值按声明的类型转换，比如 entity 字段接受整数 id。
+    // 只有 entity 字段接受 null（表示不引用任何 entity），undefined 一律拒绝
+    pub fn from_js_value(&self, value: EScriptJsValue) -> Result<EScriptFieldValue, String> {
+        match (self, value) {
+            (EScriptFieldType::Number, EScriptJsValue::Number(v)) => {
+                Ok(EScriptFieldValue::Number(v))
+            }
+            (EScriptFieldType::String, EScriptJsValue::String(v)) => {
+                Ok(EScriptFieldValue::String(v))
+            }
+            (EScriptFieldType::Bool, EScriptJsValue::Boolean(v)) => Ok(EScriptFieldValue::Bool(v)),
+            (EScriptFieldType::Vec3, EScriptJsValue::Object(v)) => v
+                .map(EScriptFieldValue::Vec3)
+                .map_err(|err| format!("expected a vec3: {}", err)),
+            (EScriptFieldType::Entity, EScriptJsValue::Null) => Ok(EScriptFieldValue::Entity(None)),
+            (EScriptFieldType::Entity, EScriptJsValue::Number(id))
+                if id >= 0.0 && id <= u32::MAX as f64 && id.fract() == 0.0 =>
+            {
+                Ok(EScriptFieldValue::Entity(Some(id as u32)))
+            }
+            (field_type, value) => Err(format!(
+                "expected a value of type {:?}, got {}",
+                field_type,
+                value.get_description()
+            )),
+        }
+    }
+}
+
+// js 传进来的字段值。只记录 js 侧的类型，不猜是哪种字段，由字段声明的类型转换
+#[derive(Clone, Debug, PartialEq)]
+pub enum EScriptJsValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    // 只有 vec3 字段接受 object，不是 {x, y, z} 的原因留到赋值时报告
+    Object(Result<glm::Vec3, String>),
+}
+
+impl EScriptJsValue {
+    fn get_description(&self) -> String {
+        match self {
+            EScriptJsValue::Undefined => "undefined".into(),
+            EScriptJsValue::Null => "null".into(),
+            EScriptJsValue::Boolean(v) => format!("boolean {}", v),
+            EScriptJsValue::Number(v) => format!("number {}", v),
+            EScriptJsValue::String(v) => format!("string {:?}", v),
+            EScriptJsValue::Object(_) => "object".into(),
+        }
+    }
+}
+
+impl TryFromJsValue for EScriptJsValue {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        if val.is_undefined() {
+            Ok(EScriptJsValue::Undefined)
+        } else if val.is_null() {
+            Ok(EScriptJsValue::Null)
+        } else if val.is_boolean() {
+            Ok(EScriptJsValue::Boolean(TryFromJsValue::try_from(
+                scope, val,
+            )?))
+        } else if val.is_number() {
+            Ok(EScriptJsValue::Number(TryFromJsValue::try_from(
+                scope, val,
+            )?))
+        } else if val.is_string() {
+            Ok(EScriptJsValue::String(TryFromJsValue::try_from(
+                scope, val,
+            )?))
+        } else if val.is_object() && !val.is_function() {
+            let vec3: Result<glm::Vec3, _> = TryFromJsValue::try_from(scope, val);
+            Ok(EScriptJsValue::Object(
+                vec3.map_err(|err| err.error_message()),
+            ))
+        } else {
+            Err(TryFromJsValueError::new("unsupported field value"))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EScriptFieldValue {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Vec3(glm::Vec3),
+    Entity(Option<u32>),
+}
+
+impl GetJsValue for EScriptFieldValue {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        match self {
+            EScriptFieldValue::Number(v) => v.get_js_value(scope),
+            EScriptFieldValue::String(v) => v.get_js_value(scope),
+            EScriptFieldValue::Bool(v) => v.get_js_value(scope),
+            EScriptFieldValue::Vec3(v) => v.get_js_value(scope),
+            EScriptFieldValue::Entity(v) => v.get_js_value(scope),
+        }
+    }
+}
+
+// 脚本里写作 { hp: "number", target: "entity", ... }，保持声明顺序
+pub struct FScriptFieldDecls(pub Vec<(String, EScriptFieldType)>);
+
+impl TryFromJsValue for FScriptFieldDecls {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = val
+            .to_object(scope)
+            .ok_or(TryFromJsValueError::new("not an object"))?;
+        let names = object
+            .get_own_property_names(scope, v8::GetPropertyNamesArgs::default())
+            .ok_or(TryFromJsValueError::new("cannot get field names"))?;
+
+        let mut fields = vec![];
+        for i in 0..names.length() {
+            let key = names.get_index(scope, i).unwrap();
+            let name = key.to_rust_string_lossy(scope);
+            let value = object.get(scope, key).unwrap();
+            let type_name: String = TryFromJsValue::try_from(scope, &value)?;
+            let field_type =
+                EScriptFieldType::from_name(&type_name).ok_or(TryFromJsValueError::new(
+                    &format!("unknown type '{}' of field '{}'", type_name, name),
+                ))?;
+            fields.push((name, field_type));
+        }
+        Ok(FScriptFieldDecls(fields))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FScriptComponentSchema {
+    pub type_name: String,
+    pub component_id: Option<u32>, // None: 没有注册过
+    pub fields: Vec<(String, EScriptFieldType)>,
+}
+
+impl FScriptComponentSchema {
+    pub fn get_field_index(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .position(|(field_name, _)| field_name == name)
+    }
+}
+
+// id 和 Rust component 共用一个 registry 分配，所以类型名也不能和 Rust component 重复
+struct FScriptComponentRegistry {
+    schemas: HashMap<String, Rc<FScriptComponentSchema>>,
+}
+
+thread_local! {
+    static SCRIPT_COMPONENT_REGISTRY: RefCell<FScriptComponentRegistry> = RefCell::new(FScriptComponentRegistry {
+        schemas: HashMap::new(),
+    });
+}
+
+// 重复注册同一个类型时沿用原来的 id。字段变了（脚本热重载）时换成新的 schema，
+// 已有的 component 仍然引用旧的 schema，由 HSpace::migrate_script_components 迁移
+pub fn register_script_component(
+    type_name: &str,
+    fields: Vec<(String, EScriptFieldType)>,
+) -> Result<u32, String> {
+    for (i, (name, _)) in fields.iter().enumerate() {
+        if fields[..i].iter().any(|(other, _)| other == name) {
+            return Err(format!("duplicated field {} in {}", name, type_name));
+        }
+    }
+
+    SCRIPT_COMPONENT_REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+
+        let component_id = match registry.schemas.get(type_name) {
+            Some(schema) if schema.fields == fields => return Ok(schema.component_id.unwrap()),
+            Some(schema) => schema.component_id.unwrap(),
+            None => allocate_component_id(type_name)?,
+        };
+        registry.schemas.insert(
+            type_name.to_string(),
+            Rc::new(FScriptComponentSchema {
+                type_name: type_name.to_string(),
+                component_id: Some(component_id),
+                fields,
+            }),
+        );
+        Ok(component_id)
+    })
+}
+
+pub fn get_script_component_schema(type_name: &str) -> Option<Rc<FScriptComponentSchema>> {
+    SCRIPT_COMPONENT_REGISTRY.with(|registry| registry.borrow().schemas.get(type_name).cloned())
+}
+
+#[derive(Clone, JsStruct)]
+pub struct HScriptComponent {
+    schema: Rc<FScriptComponentSchema>,
+    values: Vec<EScriptFieldValue>,
+}
+
+#[js_impl]
+impl HScriptComponent {
+    // 类型没有注册时得到一个没有字段的 component，添加到 entity 时会报错
+    pub fn new(type_name: String) -> Self {
+        let schema = get_script_component_schema(&type_name).unwrap_or_else(|| {
+            Rc::new(FScriptComponentSchema {
+                type_name,
+                component_id: None,
+                fields: vec![],
+            })
+        });
+        Self::new_with_schema(schema)
+    }
+
+    pub fn get_type_name(&self) -> String {
+        self.schema.type_name.clone()
+    }
+
+    pub fn get(&self, field: String) -> Result<EScriptFieldValue, String> {
+        self.get_value(&field)
+            .cloned()
+            .ok_or(format!("{} has no field {}", self.schema.type_name, field))
+    }
+
+    pub fn set(&mut self, field: String, value: EScriptJsValue) -> Result<(), String> {
+        let (index, field_type) = self.get_field(&field)?;
+        self.values[index] = field_type
+            .from_js_value(value)
+            .map_err(|err| format!("{}.{}: {}", self.schema.type_name, field, err))?;
+        Ok(())
+    }
+}
+
+impl HScriptComponent {
+    pub fn new_with_schema(schema: Rc<FScriptComponentSchema>) -> Self {
+        let values = schema
+            .fields
+            .iter()
+            .map(|(_, field_type)| field_type.default_value())
+            .collect();
+        Self { schema, values }
+    }
+
+    pub fn get_schema(&self) -> &Rc<FScriptComponentSchema> {
+        &self.schema
+    }
+
+    pub fn get_component_id(&self) -> Option<u32> {
+        self.schema.component_id
+    }
+
+    pub fn get_value(&self, field: &str) -> Option<&EScriptFieldValue> {
+        let index = self.schema.get_field_index(field)?;
+        Some(&self.values[index])
+    }
+
+    fn get_field(&self, field: &str) -> Result<(usize, EScriptFieldType), String> {
+        let index = self
+            .schema
+            .get_field_index(field)
+            .ok_or(format!("{} has no field {}", self.schema.type_name, field))?;
+        Ok((index, self.schema.fields[index].1))
+    }
+
+    pub fn set_value(&mut self, field: &str, value: EScriptFieldValue) -> Result<(), String> {
+        let (index, field_type) = self.get_field(field)?;
+        self.values[index] = field_type
+            .check(value)
+            .map_err(|err| format!("{}.{}: {}", self.schema.type_name, field, err))?;
+        Ok(())
+    }
+
+    // 换成同一类型新注册的 schema，名字和类型都没变的字段保留原来的值，其他字段取默认值
+    pub fn migrate(&mut self, schema: Rc<FScriptComponentSchema>) {
+        if Rc::ptr_eq(&self.schema, &schema) {
+            return;
+        }
+        let values = schema
+            .fields
+            .iter()
+            .map(
+                |(name, field_type)| match self.schema.get_field_index(name) {
+                    Some(index) if self.schema.fields[index].1 == *field_type => {
+                        self.values[index].clone()
+                    }
+                    _ => field_type.default_value(),
+                },
+            )
+            .collect();
+        self.schema = schema;
+        self.values = values;
+    }
+
+    pub fn get_number(&self, field: &str) -> Option<f64> {
+        match self.get_value(field)? {
+            EScriptFieldValue::Number(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn get_entity(&self, field: &str) -> Option<u32> {
+        match self.get_value(field)? {
+            EScriptFieldValue::Entity(v) => *v,
+            _ => None,
+        }
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &EScriptFieldValue)> {
+        self.schema
+            .fields
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .zip(self.values.iter())
+    }
+}
+
+// 序列化为 { "type": ..., "fields": { name: value, ... } }
+impl Serialize for HScriptComponent {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct FFields<'a>(&'a HScriptComponent);
+
+        impl<'a> Serialize for FFields<'a> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.values.len()))?;
+                for (name, value) in self.0.fields() {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("type", &self.schema.type_name)?;
+        map.serialize_entry("fields", &FFields(self))?;
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn js_values_are_converted_by_the_declared_type() {
+        let entity = EScriptFieldType::Entity;
+        assert_eq!(
+            entity.from_js_value(EScriptJsValue::Number(3.0)),
+            Ok(EScriptFieldValue::Entity(Some(3)))
+        );
+        assert_eq!(
+            entity.from_js_value(EScriptJsValue::Null),
+            Ok(EScriptFieldValue::Entity(None))
+        );
+        assert!(entity.from_js_value(EScriptJsValue::Number(1.5)).is_err());
+        assert!(entity.from_js_value(EScriptJsValue::Undefined).is_err());
+
+        // null 和 undefined 不会变成其他字段的值
+        for field_type in [
+            EScriptFieldType::Number,
+            EScriptFieldType::String,
+            EScriptFieldType::Bool,
+            EScriptFieldType::Vec3,
+        ] {
+            assert!(field_type.from_js_value(EScriptJsValue::Null).is_err());
+            assert!(field_type.from_js_value(EScriptJsValue::Undefined).is_err());
+        }
+
+        assert_eq!(
+            EScriptFieldType::Number.from_js_value(EScriptJsValue::Number(2.0)),
+            Ok(EScriptFieldValue::Number(2.0))
+        );
+        assert!(EScriptFieldType::Number
+            .from_js_value(EScriptJsValue::String("2".into()))
+            .is_err());
+        assert_eq!(
+            EScriptFieldType::Vec3
+                .from_js_value(EScriptJsValue::Object(Ok(glm::vec3(1.0, 2.0, 3.0)))),
+            Ok(EScriptFieldValue::Vec3(glm::vec3(1.0, 2.0, 3.0)))
+        );
+        assert!(EScriptFieldType::Vec3
+            .from_js_value(EScriptJsValue::Object(
+                Err("field does not exist: x".into())
+            ))
+            .is_err());
+    }
+
+    #[test]
+    fn set_reports_the_field_and_keeps_the_old_value() {
+        register_script_component(
+            "ScriptedTestHealth",
+            vec![("hp".into(), EScriptFieldType::Number)],
+        )
+        .unwrap();
+        let mut component = HScriptComponent::new("ScriptedTestHealth".into());
+        component
+            .set("hp".into(), EScriptJsValue::Number(10.0))
+            .unwrap();
+
+        let err = component
+            .set("hp".into(), EScriptJsValue::Undefined)
+            .unwrap_err();
+        assert!(err.starts_with("ScriptedTestHealth.hp:"), "{}", err);
+        assert_eq!(component.get_number("hp"), Some(10.0));
+
+        assert!(component
+            .set_value("hp", EScriptFieldValue::Entity(None))
+            .is_err());
+    }
+
+    #[test]
+    fn reregistering_replaces_the_schema_and_migrates_values() {
+        let fields = vec![
+            ("hp".into(), EScriptFieldType::Number),
+            ("target".into(), EScriptFieldType::Entity),
+        ];
+        let component_id = register_script_component("ScriptedTestEnemy", fields.clone()).unwrap();
+        assert_eq!(
+            register_script_component("ScriptedTestEnemy", fields),
+            Ok(component_id)
+        );
+
+        let mut component = HScriptComponent::new("ScriptedTestEnemy".into());
+        component
+            .set_value("hp", EScriptFieldValue::Number(5.0))
+            .unwrap();
+        component
+            .set_value("target", EScriptFieldValue::Entity(Some(7)))
+            .unwrap();
+
+        // 热重载：target 改成了 number，新加了 name
+        let new_fields = vec![
+            ("hp".into(), EScriptFieldType::Number),
+            ("target".into(), EScriptFieldType::Number),
+            ("name".into(), EScriptFieldType::String),
+        ];
+        assert_eq!(
+            register_script_component("ScriptedTestEnemy", new_fields.clone()),
+            Ok(component_id)
+        );
+        let schema = get_script_component_schema("ScriptedTestEnemy").unwrap();
+        assert_eq!(schema.fields, new_fields);
+        assert_eq!(schema.component_id, Some(component_id));
+
+        component.migrate(schema);
+        assert_eq!(component.get_number("hp"), Some(5.0));
+        assert_eq!(component.get_number("target"), Some(0.0));
+        assert_eq!(
+            component.get_value("name"),
+            Some(&EScriptFieldValue::String(String::new()))
+        );
+
+        assert!(register_script_component(
+            "ScriptedTestEnemy",
+            vec![
+                ("hp".into(), EScriptFieldType::Number),
+                ("hp".into(), EScriptFieldType::Bool),
+            ],
+        )
+        .is_err());
+    }
+}
@@ -7,35 +7,117 @@ use crate::{
     },
     utils::RcMut,
 };
+use hoo_meta_macros::{js_impl, JsStruct};
+use hoo_object::exports::*;
 use hoo_object::RcObject;
 use lazy_static::lazy_static;
 use nalgebra_glm as glm;
 
-pub const COMPONENT_ID_STATIC_MODEL: u32 = 0;
-pub const COMPONENT_ID_TRANSFORM: u32 = 1;
-pub const COMPONENT_ID_CAMERA: u32 = 2;
-pub const COMPONENT_ID_LIGHT: u32 = 3;
-pub const COMPONENT_ID_AXIS: u32 = 4;
+use super::{decompose_matrix, FWorldTransform, TComponent};
 
+// component id 由 registry 按类型分配，见 component_id::<T>()
+
+impl TComponent for HStaticModelComponent {
+    fn type_name() -> &'static str {
+        "HStaticModelComponent"
+    }
+}
+
+impl TComponent for HTransformComponent {
+    fn type_name() -> &'static str {
+        "HTransformComponent"
+    }
+}
+
+impl TComponent for HCameraComponent {
+    fn type_name() -> &'static str {
+        "HCameraComponent"
+    }
+}
+
+impl TComponent for HLightComponent {
+    fn type_name() -> &'static str {
+        "HLightComponent"
+    }
+}
+
+impl TComponent for HAxisComponent {
+    fn type_name() -> &'static str {
+        "HAxisComponent"
+    }
+}
+
+#[derive(Clone, JsStruct)]
 pub struct HStaticModelComponent {
     pub model: RcObject<objects::HStaticModel>,
 }
 
+#[js_impl]
+impl HStaticModelComponent {
+    pub fn new(model: RcObject<objects::HStaticModel>) -> Self {
+        Self { model }
+    }
+}
+
+#[derive(Clone, JsStruct)]
 pub struct HTransformComponent {
+    // 相对父节点
     pub position: glm::Vec3,
     pub rotation: glm::Quat,
     pub scale: glm::Vec3,
+    #[js(skip)]
+    pub world: FWorldTransform,
 }
 
+#[js_impl]
 impl HTransformComponent {
-    pub fn new_trs(position: &glm::Vec3, rotation: &glm::Quat, scale: &glm::Vec3) -> Self {
-        HTransformComponent {
-            position: *position,
-            rotation: *rotation,
-            scale: *scale,
+    pub fn new(position: glm::Vec3, rotation: glm::Quat, scale: glm::Vec3) -> Self {
+        Self {
+            position,
+            rotation,
+            scale,
+            world: FWorldTransform::default(),
+        }
+    }
+
+    // 保持位置不变，旋转到朝向 facing_point
+    pub fn face_at(&mut self, facing_point: glm::Vec3, up: glm::Vec3) {
+        let faced = Self::new_face_at(&self.position, &facing_point, &up);
+        self.rotation = faced.rotation;
+    }
+
+    // 相对父节点的变换
+    pub fn get_matrix(&self) -> glm::Mat4 {
+        let mut matrix = glm::identity();
+        matrix = glm::scaling(&self.scale) * matrix;
+        matrix = glm::quat_to_mat4(&self.rotation) * matrix;
+        matrix = glm::translation(&self.position) * matrix;
+        matrix
+    }
+
+    // 上一次 HTransformSystem 算出的世界变换。还没算过时当作没有父节点
+    pub fn get_world_matrix(&self) -> glm::Mat4 {
+        match self.world.propagated_local {
+            Some(_) => self.world.matrix,
+            None => self.get_matrix(),
         }
     }
 
+    pub fn get_world_position(&self) -> glm::Vec3 {
+        self.get_world_matrix().column(3).xyz()
+    }
+
+    pub fn get_world_rotation(&self) -> glm::Quat {
+        decompose_matrix(&self.get_world_matrix()).1
+    }
+}
+
+// 参数是引用，不能绑定到脚本
+impl HTransformComponent {
+    pub fn new_trs(position: &glm::Vec3, rotation: &glm::Quat, scale: &glm::Vec3) -> Self {
+        HTransformComponent::new(*position, *rotation, *scale)
+    }
+
     pub fn new_face_at(position: &glm::Vec3, facing_point: &glm::Vec3, up: &glm::Vec3) -> Self {
         let new_y = glm::normalize(&(position - facing_point)); // 新坐标轴的 y, -y 才是正方向
         let new_x = glm::normalize(&glm::cross(&new_y, up)); // 新坐标轴的 x
@@ -45,38 +127,59 @@ impl HTransformComponent {
             &nalgebra::Rotation3::from_basis_unchecked(&[new_x, new_y, new_z]),
         );
 
-        HTransformComponent {
-            position: *position,
-            rotation: *rotation.quaternion(),
-            scale: glm::vec3(1.0, 1.0, 1.0),
-        }
+        HTransformComponent::new(*position, *rotation.quaternion(), glm::vec3(1.0, 1.0, 1.0))
     }
 
-    pub fn get_matrix(&self) -> glm::Mat4 {
+    pub fn get_matrix_ignoring_scale(&self) -> glm::Mat4 {
         let mut matrix = glm::identity();
-        matrix = glm::scaling(&self.scale) * matrix;
         matrix = glm::quat_to_mat4(&self.rotation) * matrix;
         matrix = glm::translation(&self.position) * matrix;
         matrix
     }
 
-    pub fn get_matrix_ignoring_scale(&self) -> glm::Mat4 {
-        let mut matrix = glm::identity();
-        matrix = glm::quat_to_mat4(&self.rotation) * matrix;
-        matrix = glm::translation(&self.position) * matrix;
-        matrix
+    pub fn get_world_matrix_ignoring_scale(&self) -> glm::Mat4 {
+        let (position, rotation, _) = decompose_matrix(&self.get_world_matrix());
+        glm::translation(&position) * glm::quat_to_mat4(&rotation)
+    }
+
+    // 修改 position / rotation / scale，使相对父节点的变换等于 matrix
+    pub fn set_matrix(&mut self, matrix: &glm::Mat4) {
+        let (position, rotation, scale) = decompose_matrix(matrix);
+        self.position = position;
+        self.rotation = rotation;
+        self.scale = scale;
     }
 }
 
+#[derive(Clone, JsStruct)]
 pub struct HCameraComponent {
     pub camera: RcObject<objects::HCamera>,
     pub main_camera: bool,
 }
 
+#[js_impl]
+impl HCameraComponent {
+    // 主相机通过 HSpace::set_main_camera_entity 设置
+    pub fn new(camera: RcObject<objects::HCamera>) -> Self {
+        Self {
+            camera,
+            main_camera: false,
+        }
+    }
+}
+
+#[derive(Clone, JsStruct)]
 pub struct HLightComponent {
     pub light: RcObject<objects::HLight>,
 }
 
+#[js_impl]
+impl HLightComponent {
+    pub fn new(light: RcObject<objects::HLight>) -> Self {
+        Self { light }
+    }
+}
+
 pub struct HAxisComponent {} // empty
 
 impl HAxisComponent {
@@ -90,8 +193,8 @@ impl HAxisComponent {
             if let Some(static_model) = static_model.deref() {
                 return static_model.clone();
             } else {
-                let mesh = HStaticMesh::new("meshes/arrow.gltf");
-                let mut material = HMaterial::new("shaders/main.wgsl");
+                let mesh = HStaticMesh::new("meshes/arrow.gltf".into());
+                let mut material = HMaterial::new("shaders/main.wgsl".into());
                 material.material.enable_shader_profile("model_axis".into());
                 let model = RcObject::new(HStaticModel {
                     mesh: RcObject::new(mesh),
@@ -1,76 +1,145 @@
-use hoo_object::{into_trait, RcObject, RcTrait};
+use hoo_meta_macros::{js_impl, JsStruct};
+use hoo_object::exports::*;
+use hoo_object::RcObject;
 
 use super::{
     components::{
-        HCameraComponent, HLightComponent, HStaticModelComponent, HTransformComponent,
-        COMPONENT_ID_CAMERA, COMPONENT_ID_LIGHT, COMPONENT_ID_STATIC_MODEL, COMPONENT_ID_TRANSFORM,
+        get_script_component_schema, HCameraComponent, HLightComponent, HNameComponent,
+        HStaticModelComponent, HTransformComponent,
     },
     entity::HEntity,
     objects::{FColor, HCamera, HCameraTarget, HLight, HMaterial, HStaticMesh, HStaticModel},
+    resources::{FRenderQueue, FRenderTargets},
     space::HSpace,
-    systems::{HCameraSystem, HGraphicsSystem, HLightingSystem, HRotatingSystem},
+    time::{FClock, DEFAULT_FIXED_DELTA_TIME},
 };
+use crate::{graphics::FPipelineContext, utils::RcMut};
 
 use nalgebra_glm as glm;
 
+#[derive(JsStruct)]
 pub struct HContext {
     spaces: Vec<RcObject<HSpace>>,
+    // 所有 space 共用一个时钟，暂停、单步、time scale 对所有 space 生效
+    clock: FClock,
+    fixed_delta_time: f64,
 }
 
+#[js_impl]
 impl HContext {
     pub fn new() -> Self {
-        HContext { spaces: Vec::new() }
+        HContext {
+            spaces: Vec::new(),
+            clock: FClock::new(),
+            fixed_delta_time: DEFAULT_FIXED_DELTA_TIME,
+        }
     }
 
-    pub fn create_demo_space(&mut self) {
+    pub fn create_space(&mut self) -> RcObject<HSpace> {
+        let space = RcObject::new(self.new_space());
+        self.spaces.push(space.clone());
+        space
+    }
+
+    pub fn get_spaces(&self) -> Vec<RcObject<HSpace>> {
+        self.spaces.clone()
+    }
+
+    pub fn get_time_scale(&self) -> f64 {
+        self.clock.get_time_scale()
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) -> Result<(), String> {
+        self.clock.set_time_scale(time_scale)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.clock.set_paused(paused);
+    }
+
+    // 暂停时下一帧前进一个固定步长
+    pub fn step(&mut self) {
+        self.clock.step();
+    }
+
+    pub fn get_fixed_delta_time(&self) -> f64 {
+        self.fixed_delta_time
+    }
+
+    pub fn set_fixed_delta_time(&mut self, fixed_delta_time: f64) -> Result<(), String> {
+        for space in self.spaces.iter() {
+            space.borrow_mut().set_fixed_delta_time(fixed_delta_time)?;
+        }
+        self.fixed_delta_time = fixed_delta_time;
+        self.clock.set_step_delta_time(fixed_delta_time);
+        Ok(())
+    }
+}
+
+impl HContext {
+    // 注册 component 后调用，字段变了时更新所有 space 里的同类 component
+    pub fn migrate_script_components(&self, type_name: &str) {
+        if let Some(schema) = get_script_component_schema(type_name) {
+            for space in self.spaces.iter() {
+                space.borrow().migrate_script_components(&schema);
+            }
+        }
+    }
+
+    fn new_space(&self) -> HSpace {
         let mut space = HSpace::new();
+        // 设置时已经检查过
+        space.set_fixed_delta_time(self.fixed_delta_time).unwrap();
+        space
+    }
+
+    pub fn create_demo_space(&mut self) {
+        let mut space = self.new_space();
 
         let mut entity1 = HEntity::new();
-        let transform_component1 = HTransformComponent {
-            position: glm::vec3(0.0, 0.0, 0.0),
-            rotation: glm::quat(0.0, 0.0, 0.0, 1.0),
-            scale: glm::vec3(0.5, 0.5, 0.5),
-        };
-        // TODO: 这个地方要做动态检查
-        entity1.add_component(
-            COMPONENT_ID_TRANSFORM,
-            RcObject::new(transform_component1).into_any(),
+        let transform_component1 = HTransformComponent::new(
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::quat(0.0, 0.0, 0.0, 1.0),
+            glm::vec3(0.5, 0.5, 0.5),
         );
+        // TODO: 这个地方要做动态检查
+        entity1.insert(transform_component1);
 
-        let mesh = HStaticMesh::new("meshes/cube.gltf");
-        let material = HMaterial::new("shaders/main.wgsl");
+        let mesh = HStaticMesh::new("meshes/cube.gltf".into());
+        let material = HMaterial::new("shaders/main.wgsl".into());
         let model = HStaticModelComponent {
             model: RcObject::new(HStaticModel {
                 mesh: RcObject::new(mesh),
                 material: RcObject::new(material),
             }),
         };
-        entity1.add_component(COMPONENT_ID_STATIC_MODEL, RcObject::new(model).into_any());
-        space.add_entity(entity1);
+        entity1.insert(model);
+        space.add_entity(entity1).unwrap();
 
         // entity1_2
         let mut entity1 = HEntity::new();
-        let transform_component1 = HTransformComponent {
-            position: glm::vec3(1.0, 0.0, 1.0),
-            rotation: glm::quat(0.0, 0.0, 0.0, 1.0),
-            scale: glm::vec3(0.5, 0.5, 0.5),
-        };
-        // TODO: 这个地方要做动态检查
-        entity1.add_component(
-            COMPONENT_ID_TRANSFORM,
-            RcObject::new(transform_component1).into_any(),
+        let transform_component1 = HTransformComponent::new(
+            glm::vec3(1.0, 0.0, 1.0),
+            glm::quat(0.0, 0.0, 0.0, 1.0),
+            glm::vec3(0.5, 0.5, 0.5),
         );
+        // TODO: 这个地方要做动态检查
+        entity1.insert(transform_component1);
 
-        let mesh = HStaticMesh::new("meshes/cube.gltf");
-        let material = HMaterial::new("shaders/main.wgsl");
+        let mesh = HStaticMesh::new("meshes/cube.gltf".into());
+        let material = HMaterial::new("shaders/main.wgsl".into());
         let model = HStaticModelComponent {
             model: RcObject::new(HStaticModel {
                 mesh: RcObject::new(mesh),
                 material: RcObject::new(material),
             }),
         };
-        entity1.add_component(COMPONENT_ID_STATIC_MODEL, RcObject::new(model).into_any());
-        space.add_entity(entity1);
+        entity1.insert(model);
+        space.add_entity(entity1).unwrap();
 
         let entity2 = {
             let mut entity = HEntity::new();
@@ -79,10 +148,7 @@ impl HContext {
                 &glm::vec3(0.0, 0.0, 0.0),
                 &glm::vec3(0.0, 0.0, 1.0),
             );
-            entity.add_component(
-                COMPONENT_ID_TRANSFORM,
-                RcObject::new(transform_component).into_any(),
-            );
+            entity.insert(transform_component);
             let camera = RcObject::new(HCamera::new(
                 super::objects::FCameraProjection::Perspective {
                     fov: 45.0f32.to_radians(),
@@ -95,15 +161,13 @@ impl HContext {
                 camera: camera,
                 main_camera: true,
             };
-            entity.add_component(
-                COMPONENT_ID_CAMERA,
-                RcObject::new(camera_component).into_any(),
-            );
+            entity.insert(camera_component);
+            entity.insert(HNameComponent::new("MainCamera").unwrap());
             entity
         };
         // TODO: component 里面也有一个 main camera。去掉
-        let entity2_id = space.add_entity(entity2);
-        space.set_main_camera_entity(entity2_id);
+        let entity2_id = space.add_entity(entity2).unwrap();
+        space.set_main_camera_entity(entity2_id).unwrap();
 
         let entity3 = {
             let mut entity = HEntity::new();
@@ -122,36 +186,42 @@ impl HContext {
                     10.0,
                 )),
             };
-            entity.add_component(
-                COMPONENT_ID_TRANSFORM,
-                RcObject::new(transform_component).into_any(),
-            );
-            entity.add_component(
-                COMPONENT_ID_LIGHT,
-                RcObject::new(light_component).into_any(),
-            );
+            entity.insert(transform_component);
+            entity.insert(light_component);
+            entity.insert(HNameComponent::new("Light").unwrap());
             entity
         };
-        space.add_entity(entity3);
-
-        let light_system = RcObject::new(HLightingSystem::new());
-        space.systems.push(into_trait!(light_system));
-
-        let rotating_system = RcObject::new(HRotatingSystem::new());
-        space.systems.push(into_trait!(rotating_system));
+        space.add_entity(entity3).unwrap();
 
-        let camera_system = RcObject::new(HCameraSystem::new());
-        space.systems.push(into_trait!(camera_system));
-
-        let graphics_system = RcObject::new(HGraphicsSystem::new());
-        space.systems.push(into_trait!(graphics_system));
+        space.add_builtin_systems().unwrap();
 
         self.spaces.push(RcObject::new(space));
     }
 
+    // delta_time 是真实经过的时间，经过时钟缩放、暂停后交给 space
     pub fn tick(&mut self, delta_time: f64) {
+        let frame_time = self.clock.advance(delta_time);
+        for space in self.spaces.iter_mut() {
+            space.borrow_mut().tick_frame(frame_time);
+        }
+    }
+
+    // 有窗口时引擎每帧调用，用真实时间前进，返回所有 space 要提交给 renderer 的 pipeline
+    pub fn tick_with_renderer(
+        &mut self,
+        render_targets: &FRenderTargets,
+    ) -> Vec<RcMut<FPipelineContext>> {
+        let frame_time = self.clock.advance_real_time();
+        let mut pipelines = vec![];
         for space in self.spaces.iter_mut() {
-            space.borrow_mut().tick(delta_time);
+            let mut space = space.borrow_mut();
+            space.insert_resource(render_targets.clone());
+            space.insert_resource(FRenderQueue::default());
+            space.tick_frame(frame_time);
+            if let Some(render_queue) = space.remove_resource::<FRenderQueue>() {
+                pipelines.append(&mut render_queue.borrow_mut().pipelines);
+            }
         }
+        pipelines
     }
 }
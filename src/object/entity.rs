@@ -1,23 +1,227 @@
-use std::{collections::HashMap, sync::atomic::AtomicU64};
+use std::fmt;
 
-use hoo_object::RcAny;
+use hoo_meta::{GetJsValue, JsException, TryFromJsValue, TryFromJsValueError};
 
-pub struct HEntity {
-    pub components: HashMap<u32, RcAny>, // 绝对不应该这么做，但是先这样～
+use super::{
+    archetype::FComponentValue,
+    components::{component_id, TComponent},
+};
+
+// entity 的句柄：低 24 位是槽位，高 8 位是代数。槽位被回收后代数加一，旧的 id 就失效了
+// 整体是一个 u32，可以直接写进 object id 的 render target，也可以作为数字交给脚本
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FEntityId(u32);
+
+const ENTITY_INDEX_BITS: u32 = 24;
+const ENTITY_INDEX_MASK: u32 = (1 << ENTITY_INDEX_BITS) - 1;
+// u32::MAX 在 object id 里表示没有物体，最后一个槽位不分配
+const ENTITY_MAX_INDEX: u32 = ENTITY_INDEX_MASK - 1;
+const ENTITY_MAX_GENERATION: u32 = u32::MAX >> ENTITY_INDEX_BITS;
+
+impl FEntityId {
+    pub fn new(index: u32, generation: u32) -> Self {
+        debug_assert!(index <= ENTITY_MAX_INDEX);
+        debug_assert!(generation <= ENTITY_MAX_GENERATION);
+        Self(generation << ENTITY_INDEX_BITS | index)
+    }
+
+    pub fn get_index(&self) -> u32 {
+        self.0 & ENTITY_INDEX_MASK
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.0 >> ENTITY_INDEX_BITS
+    }
+
+    pub fn to_bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
 }
 
-impl HEntity {
+impl fmt::Display for FEntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.get_index(), self.get_generation())
+    }
+}
+
+impl TryFromJsValue for FEntityId {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let bits: u32 = TryFromJsValue::try_from(scope, val)?;
+        Ok(Self::from_bits(bits))
+    }
+}
+
+impl GetJsValue for FEntityId {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        self.to_bits().get_js_value(scope)
+    }
+}
+
+// 每个 space 一个，负责分配和回收 entity id
+pub struct FEntityAllocator {
+    // 每个槽位当前的代数
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_indices: Vec<u32>,
+}
+
+impl FEntityAllocator {
     pub fn new() -> Self {
-        thread_local! {
-            static COUNTER: AtomicU64 = AtomicU64::new(0)
+        Self {
+            generations: vec![],
+            alive: vec![],
+            free_indices: vec![],
         }
+    }
 
-        HEntity {
-            components: HashMap::new(),
+    pub fn allocate(&mut self) -> Result<FEntityId, String> {
+        if let Some(index) = self.free_indices.pop() {
+            self.alive[index as usize] = true;
+            return Ok(FEntityId::new(index, self.generations[index as usize]));
         }
+        let index = self.generations.len() as u32;
+        if index > ENTITY_MAX_INDEX {
+            return Err("too many entities in one space".into());
+        }
+        self.generations.push(0);
+        self.alive.push(true);
+        Ok(FEntityId::new(index, 0))
+    }
+
+    pub fn is_alive(&self, entity_id: FEntityId) -> bool {
+        let index = entity_id.get_index() as usize;
+        index < self.generations.len()
+            && self.alive[index]
+            && self.generations[index] == entity_id.get_generation()
     }
 
-    pub fn add_component(&mut self, component_id: u32, component: RcAny) {
-        self.components.insert(component_id, component);
+    // 失效的 id 返回 false
+    pub fn free(&mut self, entity_id: FEntityId) -> bool {
+        if !self.is_alive(entity_id) {
+            return false;
+        }
+        let index = entity_id.get_index() as usize;
+        self.alive[index] = false;
+        // 代数用完的槽位不再复用，否则旧 id 会重新指向新的 entity
+        if self.generations[index] < ENTITY_MAX_GENERATION {
+            self.generations[index] += 1;
+            self.free_indices.push(index as u32);
+        }
+        true
+    }
+
+    // id 无效时说明原因
+    pub fn validate(&self, entity_id: FEntityId) -> Result<(), String> {
+        if self.is_alive(entity_id) {
+            return Ok(());
+        }
+        let index = entity_id.get_index() as usize;
+        if index < self.generations.len() {
+            Err(format!("entity {} has been despawned", entity_id))
+        } else {
+            Err(format!("entity {} does not exist", entity_id))
+        }
+    }
+}
+
+// 加入 space 之前用来收集 component。加入之后 component 存在 space 的 archetype 里，这个对象就没用了
+pub struct HEntity {
+    pub components: Vec<(u32, FComponentValue)>,
+}
+
+impl HEntity {
+    pub fn new() -> Self {
+        HEntity { components: vec![] }
+    }
+
+    pub fn add_component(&mut self, component_id: u32, component: FComponentValue) {
+        self.components.push((component_id, component));
+    }
+
+    pub fn insert<T: TComponent>(&mut self, component: T) {
+        self.add_component(component_id::<T>(), FComponentValue::new(component));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entity_id_packs_index_and_generation() {
+        let entity_id = FEntityId::new(42, 3);
+        assert_eq!(entity_id.get_index(), 42);
+        assert_eq!(entity_id.get_generation(), 3);
+        assert_eq!(FEntityId::from_bits(entity_id.to_bits()), entity_id);
+        assert_eq!(entity_id.to_string(), "42v3");
+    }
+
+    #[test]
+    fn freed_slots_are_reused_with_a_new_generation() {
+        let mut entities = FEntityAllocator::new();
+        let first = entities.allocate().unwrap();
+        let second = entities.allocate().unwrap();
+        assert_eq!((first.get_index(), second.get_index()), (0, 1));
+
+        assert!(entities.free(first));
+        let reused = entities.allocate().unwrap();
+        assert_eq!(reused.get_index(), first.get_index());
+        assert_eq!(reused.get_generation(), first.get_generation() + 1);
+        assert_ne!(reused, first);
+
+        // 空闲的槽位用完之后才分配新的
+        let third = entities.allocate().unwrap();
+        assert_eq!(third.get_index(), 2);
+    }
+
+    #[test]
+    fn stale_ids_are_rejected() {
+        let mut entities = FEntityAllocator::new();
+        let stale = entities.allocate().unwrap();
+        entities.free(stale);
+        let reused = entities.allocate().unwrap();
+
+        assert!(!entities.is_alive(stale));
+        assert!(entities.is_alive(reused));
+        // 重复释放、释放旧的 id 都不会影响复用了槽位的 entity
+        assert!(!entities.free(stale));
+        assert!(entities.is_alive(reused));
+
+        assert!(entities.validate(reused).is_ok());
+        assert!(entities.validate(stale).unwrap_err().contains("despawned"));
+        let unknown = FEntityId::new(100, 0);
+        assert!(!entities.is_alive(unknown));
+        assert!(entities
+            .validate(unknown)
+            .unwrap_err()
+            .contains("does not exist"));
+    }
+
+    #[test]
+    fn exhausted_slots_are_retired() {
+        let mut entities = FEntityAllocator::new();
+        let mut entity_id = entities.allocate().unwrap();
+        while entity_id.get_generation() < ENTITY_MAX_GENERATION {
+            entities.free(entity_id);
+            entity_id = entities.allocate().unwrap();
+            assert_eq!(entity_id.get_index(), 0);
+        }
+
+        // 代数用完的槽位不再分配，最后一代的 id 不会被复用
+        entities.free(entity_id);
+        let next = entities.allocate().unwrap();
+        assert_eq!(next.get_index(), 1);
+        assert!(!entities.is_alive(entity_id));
+        assert_ne!(next.to_bits(), u32::MAX);
     }
 }
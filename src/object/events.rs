@@ -0,0 +1,201 @@
+// space 内按类型区分的事件队列。事件在发出的这一帧和下一帧都能读到，之后丢弃
+// 每个 EventReader 记录自己读到了哪里，多个 reader 互不影响
+
+use std::{any::Any, cell::RefCell, rc::Rc};
+
+use super::entity::FEntityId;
+
+pub struct FEvents<T> {
+    // 上一帧发出的事件，第一个事件的序号是 previous_start
+    previous: Vec<T>,
+    previous_start: u64,
+    // 这一帧发出的事件
+    current: Vec<T>,
+    current_start: u64,
+}
+
+impl<T> FEvents<T> {
+    pub fn new() -> Self {
+        Self {
+            previous: vec![],
+            previous_start: 0,
+            current: vec![],
+            current_start: 0,
+        }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    // 每帧开始时调用，丢弃上上一帧的事件
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        self.previous_start = self.current_start;
+        self.current_start += self.previous.len() as u64;
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get_oldest(&self) -> u64 {
+        self.previous_start
+    }
+
+    fn get_end(&self) -> u64 {
+        self.current_start + self.current.len() as u64
+    }
+
+    // 序号不小于 cursor 的事件，已经丢弃的跳过
+    fn iter_from(&self, cursor: u64) -> impl Iterator<Item = &T> + '_ {
+        let skip = cursor.saturating_sub(self.previous_start) as usize;
+        self.previous.iter().chain(self.current.iter()).skip(skip)
+    }
+}
+
+pub(crate) type FSharedEvents<T> = Rc<RefCell<FEvents<T>>>;
+
+// 类型擦除后存在 HSpace 里
+pub(crate) trait TEventQueue {
+    fn update(&self);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: 'static> TEventQueue for FSharedEvents<T> {
+    fn update(&self) {
+        self.borrow_mut().update();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct EventWriter<T> {
+    events: FSharedEvents<T>,
+}
+
+impl<T> EventWriter<T> {
+    pub(crate) fn new(events: FSharedEvents<T>) -> Self {
+        Self { events }
+    }
+
+    pub fn send(&self, event: T) {
+        self.events.borrow_mut().send(event);
+    }
+}
+
+// system 通常在第一次 begin_frame 时创建并一直持有
+pub struct EventReader<T> {
+    events: FSharedEvents<T>,
+    // 下一个要读的事件的序号
+    cursor: u64,
+}
+
+impl<T> EventReader<T> {
+    // 新的 reader 能读到还没被丢弃的所有事件
+    pub(crate) fn new(events: FSharedEvents<T>) -> Self {
+        let cursor = events.borrow().get_oldest();
+        Self { events, cursor }
+    }
+
+    // 读出上次读取之后的事件
+    pub fn read(&mut self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let events = self.events.borrow();
+        let result = events.iter_from(self.cursor).cloned().collect();
+        self.cursor = events.get_end();
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.borrow().iter_from(self.cursor).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 跳过还没读的事件
+    pub fn clear(&mut self) {
+        self.cursor = self.events.borrow().get_end();
+    }
+}
+
+// 选中的 entity 变化时由 HSpace 发出
+#[derive(Clone, Debug, PartialEq)]
+pub struct FEntitySelectedEvent {
+    pub entity_id: Option<FEntityId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_events() -> FSharedEvents<u32> {
+        Rc::new(RefCell::new(FEvents::new()))
+    }
+
+    #[test]
+    fn events_live_for_two_frames() {
+        let events = new_events();
+        let writer = EventWriter::new(events.clone());
+        let mut reader = EventReader::new(events.clone());
+
+        writer.send(1);
+        writer.send(2);
+        assert_eq!(reader.read(), vec![1, 2]);
+        assert!(reader.read().is_empty());
+
+        // 上一帧的事件还在，新的 reader 也能读到
+        events.borrow_mut().update();
+        writer.send(3);
+        assert_eq!(events.borrow().len(), 3);
+        assert_eq!(EventReader::new(events.clone()).read(), vec![1, 2, 3]);
+        assert_eq!(reader.read(), vec![3]);
+
+        events.borrow_mut().update();
+        assert_eq!(EventReader::new(events.clone()).read(), vec![3]);
+        events.borrow_mut().update();
+        assert!(events.borrow().is_empty());
+        assert!(EventReader::new(events.clone()).is_empty());
+    }
+
+    #[test]
+    fn slow_readers_skip_expired_events() {
+        let events = new_events();
+        let writer = EventWriter::new(events.clone());
+        let mut reader = EventReader::new(events.clone());
+
+        writer.send(1);
+        events.borrow_mut().update();
+        writer.send(2);
+        events.borrow_mut().update();
+        writer.send(3);
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.read(), vec![2, 3]);
+    }
+
+    #[test]
+    fn readers_are_independent() {
+        let events = new_events();
+        let writer = EventWriter::new(events.clone());
+        let mut first = EventReader::new(events.clone());
+        let mut second = EventReader::new(events.clone());
+
+        writer.send(1);
+        writer.send(2);
+        first.clear();
+        assert!(first.is_empty());
+        writer.send(3);
+        assert_eq!(first.read(), vec![3]);
+        assert_eq!(second.read(), vec![1, 2, 3]);
+    }
+}
@@ -1,7 +1,15 @@
+pub mod archetype;
+#[cfg(feature = "bench")]
+pub mod benchmark;
+pub mod commands;
 pub mod components;
 pub mod context;
 pub mod entity;
+pub mod events;
 pub mod managers;
+pub mod names;
 pub mod objects;
+pub mod resources;
 pub mod space;
 pub mod systems;
+pub mod time;
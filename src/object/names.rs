@@ -0,0 +1,114 @@
+// 按名字、标签查找 entity 的索引，由 HSpace 在增删 HNameComponent / HTagsComponent 时维护
+// 同名、同标签的 entity 按 id 排序，查找结果是确定的
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::entity::FEntityId;
+
+pub struct FNameIndex {
+    names: HashMap<String, BTreeSet<FEntityId>>,
+    tags: HashMap<String, BTreeSet<FEntityId>>,
+}
+
+fn insert(index: &mut HashMap<String, BTreeSet<FEntityId>>, key: &str, entity_id: FEntityId) {
+    index.entry(key.to_string()).or_default().insert(entity_id);
+}
+
+fn remove(index: &mut HashMap<String, BTreeSet<FEntityId>>, key: &str, entity_id: FEntityId) {
+    if let Some(entities) = index.get_mut(key) {
+        entities.remove(&entity_id);
+        if entities.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+fn find(index: &HashMap<String, BTreeSet<FEntityId>>, key: &str) -> Vec<FEntityId> {
+    index
+        .get(key)
+        .map(|entities| entities.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+impl FNameIndex {
+    pub fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            tags: HashMap::new(),
+        }
+    }
+
+    pub fn insert_name(&mut self, name: &str, entity_id: FEntityId) {
+        insert(&mut self.names, name, entity_id);
+    }
+
+    pub fn remove_name(&mut self, name: &str, entity_id: FEntityId) {
+        remove(&mut self.names, name, entity_id);
+    }
+
+    pub fn insert_tag(&mut self, tag: &str, entity_id: FEntityId) {
+        insert(&mut self.tags, tag, entity_id);
+    }
+
+    pub fn remove_tag(&mut self, tag: &str, entity_id: FEntityId) {
+        remove(&mut self.tags, tag, entity_id);
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Vec<FEntityId> {
+        find(&self.names, name)
+    }
+
+    pub fn find_by_tag(&self, tag: &str) -> Vec<FEntityId> {
+        find(&self.tags, tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(index: u32) -> FEntityId {
+        FEntityId::new(index, 0)
+    }
+
+    #[test]
+    fn results_are_sorted_by_id() {
+        let mut index = FNameIndex::new();
+        for i in [3, 1, 2] {
+            index.insert_name("Enemy", entity(i));
+            index.insert_tag("hostile", entity(i));
+        }
+        index.insert_name("Player", entity(0));
+
+        assert_eq!(
+            index.find_by_name("Enemy"),
+            vec![entity(1), entity(2), entity(3)]
+        );
+        assert_eq!(
+            index.find_by_tag("hostile"),
+            vec![entity(1), entity(2), entity(3)]
+        );
+        assert_eq!(index.find_by_name("Player"), vec![entity(0)]);
+        // 名字和标签是分开的
+        assert!(index.find_by_tag("Player").is_empty());
+    }
+
+    #[test]
+    fn removing_the_last_entity_drops_the_key() {
+        let mut index = FNameIndex::new();
+        index.insert_name("Door", entity(0));
+        index.insert_name("Door", entity(1));
+        index.insert_tag("open", entity(0));
+
+        index.remove_name("Door", entity(0));
+        assert_eq!(index.find_by_name("Door"), vec![entity(1)]);
+        index.remove_name("Door", entity(1));
+        index.remove_tag("open", entity(0));
+        // 不存在的也可以移除
+        index.remove_tag("open", entity(0));
+        index.remove_name("Window", entity(0));
+        assert!(index.find_by_name("Door").is_empty());
+        assert!(index.names.is_empty());
+        assert!(index.tags.is_empty());
+    }
+}
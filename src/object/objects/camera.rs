@@ -1,6 +1,8 @@
-use crate::{
-    device::graphics::FTexture, graphics::FPipelineContext, hoo_engine, rcmut, utils::RcMut,
-};
+use hoo_meta::{GetJsValue, JsException, TryFromJsValue, TryFromJsValueError};
+use hoo_meta_macros::{js_impl, JsStruct};
+use hoo_object::exports::*;
+
+use crate::{device::graphics::FTexture, graphics::FPipelineContext, rcmut, utils::RcMut};
 
 #[derive(Clone)]
 pub enum FCameraProjection {
@@ -73,6 +75,98 @@ impl FCameraProjection {
     }
 }
 
+// 脚本侧：{ type: "perspective", fov, aspect, near, far }
+//       或 { type: "orthographic", width, height, near, far }
+impl TryFromJsValue for FCameraProjection {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let object = val
+            .to_object(scope)
+            .ok_or(TryFromJsValueError::new("not an object"))?;
+
+        let projection_type: String = {
+            let key = v8::String::new(scope, "type").unwrap();
+            let value = object
+                .get(scope, key.into())
+                .ok_or(TryFromJsValueError::new("field does not exist: type"))?;
+            TryFromJsValue::try_from(scope, &value)?
+        };
+
+        let mut get_field = |name: &str| {
+            let key = v8::String::new(scope, name).unwrap();
+            let value = object
+                .get(scope, key.into())
+                .ok_or(TryFromJsValueError::new(&format!("field does not exist: {}", name)))?;
+            <f32 as TryFromJsValue>::try_from(scope, &value)
+        };
+
+        match projection_type.as_str() {
+            "perspective" => Ok(FCameraProjection::Perspective {
+                fov: get_field("fov")?,
+                aspect: get_field("aspect")?,
+                near: get_field("near")?,
+                far: get_field("far")?,
+            }),
+            "orthographic" => Ok(FCameraProjection::Orthographic {
+                width: get_field("width")?,
+                height: get_field("height")?,
+                near: get_field("near")?,
+                far: get_field("far")?,
+            }),
+            _ => Err(TryFromJsValueError::new(&format!(
+                "unknown projection type: {}",
+                projection_type
+            ))),
+        }
+    }
+}
+
+impl GetJsValue for FCameraProjection {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        let (projection_type, fields) = match self {
+            FCameraProjection::Perspective {
+                fov,
+                aspect,
+                near,
+                far,
+            } => (
+                "perspective",
+                [("fov", fov), ("aspect", aspect), ("near", near), ("far", far)],
+            ),
+            FCameraProjection::Orthographic {
+                width,
+                height,
+                near,
+                far,
+            } => (
+                "orthographic",
+                [
+                    ("width", width),
+                    ("height", height),
+                    ("near", near),
+                    ("far", far),
+                ],
+            ),
+        };
+
+        let object = v8::Object::new(scope);
+        let key = v8::String::new(scope, "type").unwrap();
+        let value = v8::String::new(scope, projection_type).unwrap();
+        object.set(scope, key.into(), value.into());
+        for (name, value) in fields {
+            let key = v8::String::new(scope, name).unwrap();
+            let value = v8::Number::new(scope, *value as f64);
+            object.set(scope, key.into(), value.into());
+        }
+        Ok(object.into())
+    }
+}
+
 #[derive(Clone, Default)]
 pub enum HCameraTarget {
     #[default]
@@ -80,31 +174,36 @@ pub enum HCameraTarget {
     Texture(RcMut<FTexture>),
 }
 
+#[derive(JsStruct)]
 pub struct HCamera {
-    pub context: RcMut<FPipelineContext>,
+    pub(crate) context: RcMut<FPipelineContext>,
     pub camera_projection: FCameraProjection,
     pub auto_aspect: bool,
-    pub target: HCameraTarget,
+    pub(crate) target: HCameraTarget,
+    // 没有用 set_target 指定时画到主视口，由 HCameraSystem 按 FRenderTargets 每帧更新
+    pub(crate) follows_main_viewport: bool,
 }
 
+#[js_impl]
 impl HCamera {
     pub fn new(camera_projection: FCameraProjection) -> Self {
         Self {
             context: rcmut!(FPipelineContext::new()),
             camera_projection,
             auto_aspect: true,
-            target: hoo_engine()
-                .borrow()
-                .get_renderer()
-                .get_main_viewport_target(),
+            target: HCameraTarget::default(),
+            follows_main_viewport: true,
         }
     }
 
-    pub fn set_target(&mut self, target: HCameraTarget) {
-        self.target = target;
-    }
-
     pub fn get_projection_matrix(&self) -> nalgebra_glm::Mat4 {
         return self.camera_projection.get_projection_matrix();
     }
 }
+
+impl HCamera {
+    pub fn set_target(&mut self, target: HCameraTarget) {
+        self.target = target;
+        self.follows_main_viewport = false;
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(Clone)]
+use hoo_meta::{GetJsValue, JsException, TryFromJsValue, TryFromJsValueError};
+use hoo_meta_macros::{js_impl, JsStruct, JsStructNoConstructor};
+
+#[derive(Clone, JsStructNoConstructor)]
 pub struct FColor {
     pub r: f32,
     pub g: f32,
@@ -28,13 +31,47 @@ impl Into<u32> for ELightType {
     }
 }
 
-#[derive(Clone)]
+// 脚本侧用字符串表示："directional" / "point" / "spot"
+impl TryFromJsValue for ELightType {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        let name: String = TryFromJsValue::try_from(scope, val)?;
+        match name.as_str() {
+            "directional" => Ok(ELightType::Directional),
+            "point" => Ok(ELightType::Point),
+            "spot" => Ok(ELightType::Spot),
+            _ => Err(TryFromJsValueError::new(&format!(
+                "unknown light type: {}",
+                name
+            ))),
+        }
+    }
+}
+
+impl GetJsValue for ELightType {
+    fn get_js_value<'a>(
+        &self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Value>, JsException> {
+        let name = match self {
+            ELightType::Directional => "directional",
+            ELightType::Point => "point",
+            ELightType::Spot => "spot",
+        };
+        Ok(v8::String::new(scope, name).unwrap().into())
+    }
+}
+
+#[derive(Clone, JsStruct)]
 pub struct HLight {
     color: FColor,
     radius: f32,
     light_type: ELightType,
 }
 
+#[js_impl]
 impl HLight {
     pub fn new(color: FColor, radius: f32, light_type: ELightType) -> Self {
         Self {
@@ -43,7 +80,9 @@ impl HLight {
             light_type,
         }
     }
+}
 
+impl HLight {
     pub fn new_point(color: FColor, radius: f32) -> Self {
         Self::new(color, radius, ELightType::Point)
     }
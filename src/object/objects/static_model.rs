@@ -1,30 +1,35 @@
+use hoo_meta_macros::{js_impl, JsStruct};
 use hoo_object::RcObject;
 
 use crate::device::graphics::*;
 use crate::device::io::*;
 use crate::*;
 
+#[derive(JsStruct)]
 pub struct HMaterial {
-    pub material: FMaterial,
+    pub(crate) material: FMaterial,
 }
 
+#[js_impl]
 impl HMaterial {
-    pub fn new(shader_path: &str) -> Self {
-        let mut mat = FMaterial::new(load_string(shader_path).unwrap());
+    pub fn new(shader_path: String) -> Self {
+        let mut mat = FMaterial::new(load_string(&shader_path).unwrap());
         mat.enable_shader_profile("base".into());
         Self { material: mat }
     }
 }
 
+#[derive(JsStruct)]
 pub struct HStaticMesh {
-    pub mesh: FMesh,
+    pub(crate) mesh: FMesh,
 }
 
+#[js_impl]
 impl HStaticMesh {
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: String) -> Self {
         // TODO: 应当做烘焙
         let file_resource =
-            editor::importer::load_gltf_from_slice(load_string(path).unwrap()).unwrap();
+            editor::importer::load_gltf_from_slice(load_string(&path).unwrap()).unwrap();
         // println!(
         //     "{:?}",
         //     file_resource[0].sub_meshes[0].positions.iter().fold(
@@ -47,11 +52,19 @@ impl HStaticMesh {
     }
 }
 
+#[derive(JsStruct)]
 pub struct HStaticModel {
     pub material: RcObject<HMaterial>,
     pub mesh: RcObject<HStaticMesh>,
 }
 
+#[js_impl]
+impl HStaticModel {
+    pub fn new(mesh: RcObject<HStaticMesh>, material: RcObject<HMaterial>) -> Self {
+        Self { material, mesh }
+    }
+}
+
 impl HStaticModel {
     pub fn assemble_model(&self) -> FModel {
         let mesh = self.mesh.borrow().mesh.clone();
@@ -0,0 +1,19 @@
+// 引擎每帧放进 space 的资源，内置 system 通过它们和 renderer 交互，不直接访问 hoo_engine()
+// 没有窗口时（脚本测试、benchmark）这些资源不存在，system 要能处理
+
+use crate::{graphics::FPipelineContext, utils::RcMut};
+
+use super::objects::HCameraTarget;
+
+#[derive(Clone)]
+pub struct FRenderTargets {
+    pub swapchain_size: (u32, u32),
+    // 主相机画到这里
+    pub main_viewport_target: HCameraTarget,
+}
+
+// 这一帧要提交给 renderer 的 pipeline，tick 结束后由引擎取走
+#[derive(Default)]
+pub struct FRenderQueue {
+    pub pipelines: Vec<RcMut<FPipelineContext>>,
+}
@@ -1,70 +1,925 @@
-use std::{collections::HashMap, ops::Deref, sync::atomic::AtomicU32};
-
-use hoo_object::{RcAny, RcObject, RcTrait};
+use std::{
+    any::{Any, TypeId},
+    cell::{Cell, Ref, RefCell, RefMut},
+    collections::HashMap,
+    rc::Rc,
+};
 
-use crate::object::components::COMPONENT_ID_CAMERA;
+use hoo_meta_macros::{js_impl, JsStruct};
+use hoo_object::exports::*;
+use hoo_object::{into_trait, RcAny, RcObject, RcTrait};
+use nalgebra_glm as glm;
 
 use super::{
-    components::{HAxisComponent, HCameraComponent, COMPONENT_ID_AXIS},
-    entity::HEntity,
-    systems::{FSystemTickContext, TSystem}, objects::HCamera,
+    archetype::{FArchetypeStorage, FComponentValue},
+    commands::{ECommand, FCommands},
+    components::{
+        component_id, get_component_id_by_type_name, get_component_type_name,
+        get_script_component_schema, FScriptComponentSchema, HCameraComponent, HChildrenComponent,
+        HLightComponent, HNameComponent, HParentComponent, HScriptComponent, HStaticModelComponent,
+        HTagsComponent, HTransformComponent, TComponent,
+    },
+    entity::{FEntityAllocator, FEntityId, HEntity},
+    events::{EventReader, EventWriter, FEntitySelectedEvent, FEvents, FSharedEvents, TEventQueue},
+    names::FNameIndex,
+    objects::HCamera,
+    systems::{
+        build_system_batches, EExecutorMode, EStage, FSchedule, FScheduledSystem, FSystemDesc,
+        FSystemTickContext, HCameraSystem, HGraphicsSystem, HLightingSystem, HRotatingSystem,
+        HScriptSystem, HTransformSystem, RemovedComponents, TSystem,
+    },
+    time::{FFixedTimestep, FFrameTime, FTime, DEFAULT_FIXED_DELTA_TIME},
 };
 
+// 一批里的 system 和它用来比较变化的 last run tick
+type FBatchSystem = (RcTrait<dyn TSystem>, Rc<Cell<u32>>);
+
+#[derive(JsStruct)]
 pub struct HSpace {
-    entities: HashMap<u32, HEntity>,
-    pub systems: Vec<RcTrait<dyn TSystem>>,
-    pub executed_systems: Vec<RcTrait<dyn TSystem>>,
+    storage: FArchetypeStorage,
+    // FCommands 预留 id 时只有 &HSpace
+    entities: RefCell<FEntityAllocator>,
+    commands: RefCell<Vec<ECommand>>,
+    // 按事件类型，第一次用到时创建
+    events: RefCell<HashMap<TypeId, Box<dyn TEventQueue>>>,
+    // 按类型存放的单例，system 通过 resource / resource_mut 访问
+    resources: HashMap<TypeId, RcAny>,
+    // 每次修改 storage、每个 system 执行时递增，用来判断 component 是否在某个时刻之后变化过
+    change_tick: Cell<u32>,
+    // 正在执行的 system 上次执行时的 tick，RemovedComponents 和它比较。不在 system 里时是 0
+    system_last_run_tick: Cell<u32>,
+    pub(crate) schedule: FSchedule,
+    executor_mode: EExecutorMode,
+    // HNameComponent / HTagsComponent 的索引
+    names: FNameIndex,
+    fixed_timestep: FFixedTimestep,
+    // 执行失败的 command、取不到 component 的 system 记在这里，由 take_errors 取出
+    errors: RefCell<Vec<String>>,
 
     pub main_camera: Option<RcObject<HCamera>>,
-    pub selected_entity_id: Option<u32>,
+    pub selected_entity_id: Option<FEntityId>,
 }
 
+// 脚本接口。脚本侧用 entity id 指代 entity
+#[js_impl]
 impl HSpace {
     pub fn new() -> Self {
-        HSpace {
-            entities: HashMap::new(),
-            systems: Vec::new(),
-            executed_systems: Vec::new(),
+        let mut space = HSpace {
+            storage: FArchetypeStorage::new(),
+            entities: RefCell::new(FEntityAllocator::new()),
+            commands: RefCell::new(vec![]),
+            events: RefCell::new(HashMap::new()),
+            resources: HashMap::new(),
+            change_tick: Cell::new(0),
+            system_last_run_tick: Cell::new(0),
+            schedule: FSchedule::new(),
+            executor_mode: EExecutorMode::default(),
+            names: FNameIndex::new(),
+            fixed_timestep: FFixedTimestep::new(DEFAULT_FIXED_DELTA_TIME),
+            errors: RefCell::new(vec![]),
             main_camera: None,
             selected_entity_id: None,
+        };
+        space.insert_resource(FTime::default());
+        space
+    }
+
+    pub fn create_entity(&mut self) -> Result<FEntityId, String> {
+        self.add_entity(HEntity::new())
+    }
+
+    // 槽位会被之后创建的 entity 复用，但旧的 id 不会指向新的 entity
+    // 子节点不会一起 despawn，而是变成根节点，世界变换保持不变
+    pub fn despawn(&mut self, entity_id: FEntityId) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        self.set_parent(entity_id, None)?;
+        for child in self.get_children(entity_id) {
+            self.set_parent(child, None)?;
+        }
+        let tick = self.next_change_tick();
+        let components = self.storage.despawn(entity_id, tick).unwrap_or_default();
+        self.entities.get_mut().free(entity_id);
+        for (component_id, component) in components.iter() {
+            self.on_component_removed(entity_id, *component_id, component.as_any());
+        }
+        if self.selected_entity_id == Some(entity_id) {
+            self.set_selected_entity(None);
+        }
+        Ok(())
+    }
+
+    // 连同所有子孙节点一起 despawn
+    pub fn despawn_recursive(&mut self, entity_id: FEntityId) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        for child in self.get_children(entity_id) {
+            self.despawn_recursive(child)?;
         }
+        self.despawn(entity_id)
     }
 
-    pub fn set_main_camera_entity(&mut self, entity_id: u32) {
-        if let Some(entity) = self.entities.get(&entity_id) {
-            if let Some(camera_component) = entity.components.get(&COMPONENT_ID_CAMERA) {
-                if let Ok(camera_component) = camera_component.clone().try_downcast::<HCameraComponent>() {
-                    self.main_camera = Some(camera_component.borrow().camera.clone());
-                    return;
+    // parent 为 None 时变成根节点。世界变换保持不变，相对父节点的 position / rotation / scale 会重新计算
+    pub fn set_parent(
+        &mut self,
+        entity_id: FEntityId,
+        parent: Option<FEntityId>,
+    ) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        if let Some(parent) = parent {
+            self.entities.borrow().validate(parent)?;
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == entity_id {
+                    return Err(format!(
+                        "entity {} cannot be a descendant of itself",
+                        entity_id
+                    ));
+                }
+                ancestor = self.get_parent(current);
+            }
+        }
+
+        let old_parent = self.get_parent(entity_id);
+        if old_parent == parent {
+            return Ok(());
+        }
+        let world_matrix = self.get_world_matrix(entity_id);
+
+        // 借用要在增删 component 之前释放
+        if let Some(old_parent) = old_parent {
+            let children_left = self
+                .get_component_mut::<HChildrenComponent>(old_parent)
+                .map(|mut children| {
+                    children.children.retain(|child| *child != entity_id);
+                    children.children.len()
+                });
+            match children_left {
+                Some(0) => {
+                    self.remove_component::<HChildrenComponent>(old_parent)?;
+                }
+                Some(_) => {
+                    self.mark_changed::<HChildrenComponent>(old_parent);
+                }
+                None => {}
+            }
+        }
+        match parent {
+            Some(parent) => {
+                self.insert_component(entity_id, HParentComponent { parent })?;
+                let pushed = self
+                    .get_component_mut::<HChildrenComponent>(parent)
+                    .map(|mut children| children.children.push(entity_id))
+                    .is_some();
+                if pushed {
+                    self.mark_changed::<HChildrenComponent>(parent);
+                } else {
+                    self.insert_component(
+                        parent,
+                        HChildrenComponent {
+                            children: vec![entity_id],
+                        },
+                    )?;
                 }
             }
+            None => {
+                self.remove_component::<HParentComponent>(entity_id)?;
+            }
+        }
+
+        // 没有 HTransformComponent 时 world_matrix 是 None
+        if let Some(world_matrix) = world_matrix {
+            let parent_world = parent
+                .and_then(|parent| self.get_world_matrix(parent))
+                .unwrap_or(glm::identity());
+            if let Some(mut transform) = self.get_component_mut::<HTransformComponent>(entity_id) {
+                transform.set_matrix(&(glm::inverse(&parent_world) * world_matrix));
+            }
+            self.mark_changed::<HTransformComponent>(entity_id);
         }
-        assert!(false);
+        Ok(())
     }
 
-    pub fn add_entity(&mut self, entity: HEntity) -> u32 {
-        // TODO: reusable id
-        static COUNTER: AtomicU32 = AtomicU32::new(0);
-        let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        debug_assert_ne!(id, u32::MAX);
-        self.entities.insert(id, entity);
-        return id;
+    pub fn get_parent(&self, entity_id: FEntityId) -> Option<FEntityId> {
+        self.get_component::<HParentComponent>(entity_id)
+            .map(|parent| parent.get_parent())
     }
 
-    pub fn get_systems_by_type<T: TSystem>(&self) -> Vec<RcObject<T>> {
-        let mut result: Vec<RcObject<T>> = Vec::new();
-        for system in self.systems.iter() {
-            if let Ok(sys) = system.clone().try_downcast::<T>() {
-                result.push(sys);
+    pub fn get_children(&self, entity_id: FEntityId) -> Vec<FEntityId> {
+        self.get_component::<HChildrenComponent>(entity_id)
+            .map(|children| children.get_children().to_vec())
+            .unwrap_or_default()
+    }
+
+    // 按当前的 position / rotation / scale 沿父节点算出世界变换，不依赖 HTransformSystem 的结果
+    pub fn get_world_matrix(&self, entity_id: FEntityId) -> Option<glm::Mat4> {
+        let local = self
+            .get_component::<HTransformComponent>(entity_id)?
+            .get_matrix();
+        let parent_world = self
+            .get_parent(entity_id)
+            .and_then(|parent| self.get_world_matrix(parent));
+        Some(match parent_world {
+            Some(parent_world) => parent_world * local,
+            None => local,
+        })
+    }
+
+    // 整个替换 HNameComponent，索引在 component 增删时更新
+    pub fn set_name(&mut self, entity_id: FEntityId, name: String) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        if self.get_name(entity_id).as_deref() == Some(name.as_str()) {
+            return Ok(());
+        }
+        self.insert_component(entity_id, HNameComponent::new(&name)?)
+    }
+
+    pub fn get_name(&self, entity_id: FEntityId) -> Option<String> {
+        self.get_component::<HNameComponent>(entity_id)
+            .map(|name| name.get_name().to_string())
+    }
+
+    // 同名时返回 id 最小的
+    pub fn find_by_name(&self, name: String) -> Option<FEntityId> {
+        self.names.find_by_name(&name).first().cloned()
+    }
+
+    pub fn find_all_by_name(&self, name: String) -> Vec<FEntityId> {
+        self.names.find_by_name(&name)
+    }
+
+    // 从根节点开始按名字逐级查找子节点，比如 "Level/Door/Handle"。有多个匹配时返回 id 最小的
+    pub fn find_by_path(&self, path: String) -> Option<FEntityId> {
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let mut candidates: Vec<FEntityId> = self
+            .names
+            .find_by_name(segments.next()?)
+            .into_iter()
+            .filter(|entity_id| self.get_parent(*entity_id).is_none())
+            .collect();
+        for segment in segments {
+            candidates = candidates
+                .iter()
+                .flat_map(|parent| self.get_children(*parent))
+                .filter(|child| self.get_name(*child).as_deref() == Some(segment))
+                .collect();
+            candidates.sort();
+        }
+        candidates.first().cloned()
+    }
+
+    // 从根节点到这个 entity 的名字，中间有没有名字的节点时返回 None
+    pub fn get_path(&self, entity_id: FEntityId) -> Option<String> {
+        let mut names = vec![self.get_name(entity_id)?];
+        let mut current = entity_id;
+        while let Some(parent) = self.get_parent(current) {
+            names.push(self.get_name(parent)?);
+            current = parent;
+        }
+        names.reverse();
+        Some(names.join("/"))
+    }
+
+    pub fn add_tag(&mut self, entity_id: FEntityId, tag: String) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        if tag.is_empty() {
+            return Err("tag cannot be empty".into());
+        }
+        let tags = match self.get_component::<HTagsComponent>(entity_id) {
+            Some(tags) if tags.has_tag(&tag) => return Ok(()),
+            Some(tags) => tags.with_tag(&tag),
+            None => HTagsComponent::new(&[&tag]),
+        };
+        self.insert_component(entity_id, tags)
+    }
+
+    // 返回是否有这个标签。最后一个标签被移除时 HTagsComponent 也一起移除
+    pub fn remove_tag(&mut self, entity_id: FEntityId, tag: String) -> Result<bool, String> {
+        self.entities.borrow().validate(entity_id)?;
+        let tags = match self.get_component::<HTagsComponent>(entity_id) {
+            Some(tags) if tags.has_tag(&tag) => tags.without_tag(&tag),
+            _ => return Ok(false),
+        };
+        if tags.is_empty() {
+            self.remove_component::<HTagsComponent>(entity_id)?;
+        } else {
+            self.insert_component(entity_id, tags)?;
+        }
+        Ok(true)
+    }
+
+    pub fn has_tag(&self, entity_id: FEntityId, tag: String) -> bool {
+        self.get_component::<HTagsComponent>(entity_id)
+            .map_or(false, |tags| tags.has_tag(&tag))
+    }
+
+    pub fn get_tags(&self, entity_id: FEntityId) -> Vec<String> {
+        self.get_component::<HTagsComponent>(entity_id)
+            .map(|tags| tags.get_tags().map(|tag| tag.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    // 按 id 排序
+    pub fn find_by_tag(&self, tag: String) -> Vec<FEntityId> {
+        self.names.find_by_tag(&tag)
+    }
+
+    // 预留了 id 但还没执行 spawn 命令的 entity 不算
+    pub fn has_entity(&self, entity_id: FEntityId) -> bool {
+        self.storage.contains(entity_id)
+    }
+
+    pub fn get_entity_ids(&self) -> Vec<FEntityId> {
+        let mut ids: Vec<FEntityId> = self.storage.entity_ids().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn set_main_camera_entity(&mut self, entity_id: FEntityId) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        let camera = self
+            .get_component::<HCameraComponent>(entity_id)
+            .map(|camera_component| camera_component.camera.clone())
+            .ok_or(format!("entity {} has no camera component", entity_id))?;
+
+        self.storage.for_each_mut(
+            component_id::<HCameraComponent>(),
+            |other_id, other: &mut HCameraComponent| {
+                other.main_camera = other_id == entity_id;
+            },
+        )?;
+        self.main_camera = Some(camera);
+        Ok(())
+    }
+
+    // 脚本和 space 之间按值复制 component：add 时复制进 storage，get 返回拷贝
+    pub fn add_transform_component(
+        &mut self,
+        entity_id: FEntityId,
+        component: RcObject<HTransformComponent>,
+    ) -> Result<(), String> {
+        self.insert_component(entity_id, component.borrow().clone())
+    }
+
+    pub fn get_transform_component(
+        &self,
+        entity_id: FEntityId,
+    ) -> Result<Option<RcObject<HTransformComponent>>, String> {
+        self.copy_component(entity_id, component_id::<HTransformComponent>())
+    }
+
+    pub fn add_light_component(
+        &mut self,
+        entity_id: FEntityId,
+        component: RcObject<HLightComponent>,
+    ) -> Result<(), String> {
+        self.insert_component(entity_id, component.borrow().clone())
+    }
+
+    pub fn get_light_component(
+        &self,
+        entity_id: FEntityId,
+    ) -> Result<Option<RcObject<HLightComponent>>, String> {
+        self.copy_component(entity_id, component_id::<HLightComponent>())
+    }
+
+    pub fn add_camera_component(
+        &mut self,
+        entity_id: FEntityId,
+        component: RcObject<HCameraComponent>,
+    ) -> Result<(), String> {
+        self.insert_component(entity_id, component.borrow().clone())
+    }
+
+    pub fn get_camera_component(
+        &self,
+        entity_id: FEntityId,
+    ) -> Result<Option<RcObject<HCameraComponent>>, String> {
+        self.copy_component(entity_id, component_id::<HCameraComponent>())
+    }
+
+    pub fn add_static_model_component(
+        &mut self,
+        entity_id: FEntityId,
+        component: RcObject<HStaticModelComponent>,
+    ) -> Result<(), String> {
+        self.insert_component(entity_id, component.borrow().clone())
+    }
+
+    pub fn get_static_model_component(
+        &self,
+        entity_id: FEntityId,
+    ) -> Result<Option<RcObject<HStaticModelComponent>>, String> {
+        self.copy_component(entity_id, component_id::<HStaticModelComponent>())
+    }
+
+    pub fn add_script_component(
+        &mut self,
+        entity_id: FEntityId,
+        component: RcObject<HScriptComponent>,
+    ) -> Result<(), String> {
+        let component_id = component.borrow().get_component_id().ok_or(format!(
+            "component type {} is not registered",
+            component.borrow().get_type_name()
+        ))?;
+        let component = component.borrow().clone();
+        self.add_component(entity_id, component_id, FComponentValue::new(component))
+    }
+
+    pub fn get_script_component(
+        &self,
+        entity_id: FEntityId,
+        type_name: String,
+    ) -> Result<Option<RcObject<HScriptComponent>>, String> {
+        match get_script_component_schema(&type_name).and_then(|schema| schema.component_id) {
+            Some(component_id) => self.copy_component(entity_id, component_id),
+            None => Ok(None),
+        }
+    }
+
+    // 内置 component 用 Rust 类型名，脚本 component 用注册时的名字
+    pub fn remove_component_by_name(
+        &mut self,
+        entity_id: FEntityId,
+        type_name: String,
+    ) -> Result<(), String> {
+        let component_id = get_component_id_by_type_name(&type_name)
+            .ok_or(format!("unknown component type: {}", type_name))?;
+        self.remove_component_by_id(entity_id, component_id)
+            .map(|_| ())
+    }
+
+    // stage 和 before / after 来自脚本对象
+    pub fn add_script_system(&mut self, system: HScriptSystem) -> Result<(), String> {
+        let desc = system.get_desc();
+        self.add_system_with(system, desc).map(|_| ())
+    }
+
+    // 实际的执行顺序，每一项是 "Stage: SystemName"
+    pub fn get_system_order(&self) -> Vec<String> {
+        self.schedule
+            .get_order()
+            .into_iter()
+            .map(|(stage, name)| format!("{}: {}", stage.get_name(), name))
+            .collect()
+    }
+
+    // 调试用：打开后 get_system_batches 每个 system 单独列出，执行顺序和同步点不变
+    pub fn set_sequential(&mut self, sequential: bool) {
+        self.executor_mode = if sequential {
+            EExecutorMode::Sequential
+        } else {
+            EExecutorMode::Batched
+        };
+    }
+
+    // 这一帧的分批情况，每一项是 "Stage: SystemA, SystemB"
+    pub fn get_system_batches(&self) -> Vec<String> {
+        let systems = self.get_running_systems();
+        build_system_batches(&systems, self.executor_mode)
+            .into_iter()
+            .map(|batch| {
+                let names: Vec<&str> = batch
+                    .iter()
+                    .map(|index| systems[*index].name.as_str())
+                    .collect();
+                format!(
+                    "{}: {}",
+                    systems[batch[0]].desc.stage.get_name(),
+                    names.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    // 取出所有脚本 system 记录的异常
+    pub fn take_script_system_errors(&mut self) -> Vec<String> {
+        let mut errors = vec![];
+        for system in self.get_systems_by_type::<HScriptSystem>() {
+            errors.append(&mut system.borrow_mut().take_errors());
+        }
+        errors
+    }
+
+    // 取出执行 command、system 时记录的错误
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(self.errors.get_mut())
+    }
+}
+
+impl HSpace {
+    // 槽位用完时返回错误
+    pub fn add_entity(&mut self, entity: HEntity) -> Result<FEntityId, String> {
+        let id = self.entities.get_mut().allocate()?;
+        let tick = self.next_change_tick();
+        if let Err(err) = self.storage.spawn(id, entity.components, tick) {
+            self.entities.get_mut().free(id);
+            return Err(err);
+        }
+        self.on_entity_spawned(id);
+        Ok(id)
+    }
+
+    pub fn add_component(
+        &mut self,
+        entity_id: FEntityId,
+        component_id: u32,
+        component: FComponentValue,
+    ) -> Result<(), String> {
+        self.entities.borrow().validate(entity_id)?;
+        let tick = self.next_change_tick();
+        let replaced = self
+            .storage
+            .insert_component(entity_id, component_id, component, tick)?;
+        // 替换时旧的 component 相当于被移除
+        if let Some(replaced) = replaced {
+            self.on_component_removed(entity_id, component_id, replaced.as_any());
+        }
+        self.on_component_added(entity_id, component_id);
+        Ok(())
+    }
+
+    pub fn remove_component_by_id(
+        &mut self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<FComponentValue, String> {
+        self.entities.borrow().validate(entity_id)?;
+        let tick = self.next_change_tick();
+        let component = self
+            .storage
+            .remove_component(entity_id, component_id, tick)
+            .ok_or(format!(
+                "entity {} has no {} component",
+                entity_id,
+                get_component_type_name(component_id).unwrap_or(component_id.to_string())
+            ))?;
+        self.on_component_removed(entity_id, component_id, component.as_any());
+        Ok(component)
+    }
+
+    pub fn remove_component<T: TComponent>(&mut self, entity_id: FEntityId) -> Result<T, String> {
+        let component = self.remove_component_by_id(entity_id, component_id::<T>())?;
+        component
+            .downcast::<T>()
+            .map_err(|_| format!("removed component is not a {}", T::type_name()))
+    }
+
+    fn on_entity_spawned(&mut self, entity_id: FEntityId) {
+        let component_ids = self
+            .storage
+            .get_component_ids(entity_id)
+            .map(|component_ids| component_ids.to_vec())
+            .unwrap_or_default();
+        for component_id in component_ids {
+            self.on_component_added(entity_id, component_id);
+        }
+    }
+
+    // component 已经放进 storage，从 storage 借用
+    fn on_component_added(&mut self, entity_id: FEntityId, component_id: u32) {
+        if component_id == super::components::component_id::<HNameComponent>() {
+            if let Ok(Some(name)) = self
+                .storage
+                .get_component::<HNameComponent>(entity_id, component_id)
+            {
+                self.names.insert_name(name.get_name(), entity_id);
+            }
+        } else if component_id == super::components::component_id::<HTagsComponent>() {
+            if let Ok(Some(tags)) = self
+                .storage
+                .get_component::<HTagsComponent>(entity_id, component_id)
+            {
+                for tag in tags.get_tags() {
+                    self.names.insert_tag(tag, entity_id);
+                }
             }
         }
-        result
     }
 
-    pub fn get_executed_systems_by_type<T: TSystem>(&self) -> Vec<RcObject<T>> {
+    // 被移走的相机不能继续当主相机
+    fn on_component_removed(
+        &mut self,
+        entity_id: FEntityId,
+        component_id: u32,
+        component: &dyn Any,
+    ) {
+        if component_id == super::components::component_id::<HCameraComponent>() {
+            if let Some(camera_component) = component.downcast_ref::<HCameraComponent>() {
+                if self.main_camera.as_ref() == Some(&camera_component.camera) {
+                    self.main_camera = None;
+                }
+            }
+        } else if component_id == super::components::component_id::<HNameComponent>() {
+            if let Some(name) = component.downcast_ref::<HNameComponent>() {
+                self.names.remove_name(name.get_name(), entity_id);
+            }
+        } else if component_id == super::components::component_id::<HTagsComponent>() {
+            if let Some(tags) = component.downcast_ref::<HTagsComponent>() {
+                for tag in tags.get_tags() {
+                    self.names.remove_tag(tag, entity_id);
+                }
+            }
+        }
+    }
+
+    // 脚本热重载改了 component 的字段后，把已有的 component 换成新的 schema
+    pub fn migrate_script_components(&self, schema: &Rc<FScriptComponentSchema>) {
+        let component_id = match schema.component_id {
+            Some(component_id) => component_id,
+            None => return,
+        };
+        let result = self.storage.for_each_mut(
+            component_id,
+            |entity_id, component: &mut HScriptComponent| {
+                if !Rc::ptr_eq(component.get_schema(), schema) {
+                    component.migrate(schema.clone());
+                    self.mark_changed_by_id(entity_id, component_id);
+                }
+            },
+        );
+        if let Err(err) = result {
+            self.report_error(format!(
+                "failed to migrate {} components: {}",
+                schema.type_name, err
+            ));
+        }
+    }
+
+    // 借用 component 所在的整列。列正被别处借用时（比如执行中的 system 的查询）返回错误
+    pub fn try_get_component_by_id<T: 'static>(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<Option<Ref<'_, T>>, String> {
+        self.storage.get_component(entity_id, component_id)
+    }
+
+    pub fn try_get_component_mut_by_id<T: 'static>(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<Option<RefMut<'_, T>>, String> {
+        self.storage.get_component_mut(entity_id, component_id)
+    }
+
+    // 脚本拿到的是拷贝，修改后要通过 add_*_component 写回
+    fn copy_component<T: Clone + 'static>(
+        &self,
+        entity_id: FEntityId,
+        component_id: u32,
+    ) -> Result<Option<RcObject<T>>, String> {
+        let component = self.try_get_component_by_id::<T>(entity_id, component_id)?;
+        Ok(component.map(|component| RcObject::new(component.clone())))
+    }
+
+    pub fn insert_component<T: TComponent>(
+        &mut self,
+        entity_id: FEntityId,
+        component: T,
+    ) -> Result<(), String> {
+        self.add_component(
+            entity_id,
+            component_id::<T>(),
+            FComponentValue::new(component),
+        )
+    }
+
+    pub fn has_component<T: TComponent>(&self, entity_id: FEntityId) -> bool {
+        self.storage
+            .get_component_ids(entity_id)
+            .map_or(false, |component_ids| {
+                component_ids.contains(&component_id::<T>())
+            })
+    }
+
+    // 和 resource 一样，借用冲突时 panic
+    pub fn get_component<T: TComponent>(&self, entity_id: FEntityId) -> Option<Ref<'_, T>> {
+        self.try_get_component_by_id(entity_id, component_id::<T>())
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn get_component_mut<T: TComponent>(&self, entity_id: FEntityId) -> Option<RefMut<'_, T>> {
+        self.try_get_component_mut_by_id(entity_id, component_id::<T>())
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn next_change_tick(&self) -> u32 {
+        let tick = self.change_tick.get() + 1;
+        self.change_tick.set(tick);
+        tick
+    }
+
+    // 通过 get_component_mut 修改 component 不会被记录，需要手动标记
+    // system 通过 Mut 修改 component 时会自动标记
+    pub fn mark_changed_by_id(&self, entity_id: FEntityId, component_id: u32) -> bool {
+        self.storage
+            .mark_changed(entity_id, component_id, self.next_change_tick())
+    }
+
+    pub fn mark_changed<T: TComponent>(&self, entity_id: FEntityId) -> bool {
+        self.mark_changed_by_id(entity_id, component_id::<T>())
+    }
+
+    // 正在执行的 system 上次执行之后被移除了 T 的 entity，包括被 despawn 的
+    // 在 before_first_tick / tick 里调用。不在 system 里时返回所有还保留着的移除记录
+    pub fn removed_components<T: TComponent>(&self) -> RemovedComponents<T> {
+        RemovedComponents::new(
+            self.storage
+                .get_removed_since(component_id::<T>(), self.system_last_run_tick.get()),
+        )
+    }
+
+    // 加入 Update stage，没有顺序约束
+    pub fn add_system<T: TSystem + 'static>(&mut self, system: T) -> Result<RcObject<T>, String> {
+        self.add_system_with(system, FSystemDesc::new())
+    }
+
+    // 查询和顺序约束在这里检查，有问题的 system 不会加进来
+    pub fn add_system_with<T: TSystem + 'static>(
+        &mut self,
+        system: T,
+        desc: FSystemDesc,
+    ) -> Result<RcObject<T>, String> {
+        let name = system.get_name();
+        for query in system.get_queries().iter() {
+            query
+                .validate()
+                .map_err(|err| format!("system {}: {}", name, err))?;
+        }
+        let system = RcObject::new(system);
+        self.schedule.add(into_trait!(system.clone()), name, desc)?;
+        Ok(system)
+    }
+
+    // 相机和灯光在世界变换更新之后收集，graphics 在最后用它们提交绘制
+    pub fn add_builtin_systems(&mut self) -> Result<(), String> {
+        self.add_system(HRotatingSystem::new())?;
+        // 世界变换算好之后再收集
+        let post_update = || {
+            FSystemDesc::new()
+                .in_stage(EStage::PostUpdate)
+                .after("HTransformSystem")
+        };
+        self.add_system_with(
+            HTransformSystem::new(),
+            FSystemDesc::new().in_stage(EStage::PostUpdate),
+        )?;
+        self.add_system_with(HLightingSystem::new(), post_update())?;
+        self.add_system_with(HCameraSystem::new(), post_update())?;
+        self.add_system_with(
+            HGraphicsSystem::new(),
+            FSystemDesc::new().in_stage(EStage::Render),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_schedule(&self) -> &FSchedule {
+        &self.schedule
+    }
+
+    pub fn set_executor_mode(&mut self, mode: EExecutorMode) {
+        self.executor_mode = mode;
+    }
+
+    pub fn get_executor_mode(&self) -> EExecutorMode {
+        self.executor_mode
+    }
+
+    // 已经累积的时间保留，按新的步长计算
+    pub fn set_fixed_delta_time(&mut self, fixed_delta_time: f64) -> Result<(), String> {
+        self.fixed_timestep.set_fixed_delta_time(fixed_delta_time)
+    }
+
+    // 这一帧要执行的 system，按 schedule 的顺序
+    fn get_running_systems(&self) -> Vec<&FScheduledSystem> {
+        self.schedule
+            .iter()
+            .filter(|system| system.should_run(self))
+            .collect()
+    }
+
+    // system 在 tick 期间通过它修改 space，命令在下一个同步点执行
+    pub fn commands(&self) -> FCommands<'_> {
+        FCommands {
+            queue: &self.commands,
+            entities: &self.entities,
+        }
+    }
+
+    // 按记录的顺序执行，执行失败的命令跳过，错误可以用 take_errors 取出
+    pub fn apply_commands(&mut self) {
+        let commands = std::mem::take(self.commands.get_mut());
+        for command in commands {
+            let result = match command {
+                ECommand::Spawn(entity_id, entity) => {
+                    if self.entities.get_mut().is_alive(entity_id) {
+                        let tick = self.next_change_tick();
+                        match self.storage.spawn(entity_id, entity.components, tick) {
+                            Ok(()) => {
+                                self.on_entity_spawned(entity_id);
+                                Ok(())
+                            }
+                            Err(err) => {
+                                self.entities.get_mut().free(entity_id);
+                                Err(err)
+                            }
+                        }
+                    } else {
+                        Err(format!(
+                            "entity {} was despawned before spawning",
+                            entity_id
+                        ))
+                    }
+                }
+                ECommand::Despawn(entity_id) => self.despawn(entity_id),
+                ECommand::AddComponent(entity_id, component_id, component) => {
+                    self.add_component(entity_id, component_id, component)
+                }
+                ECommand::RemoveComponent(entity_id, component_id) => self
+                    .remove_component_by_id(entity_id, component_id)
+                    .map(|_| ()),
+            };
+            if let Err(err) = result {
+                self.report_error(format!("command skipped: {}", err));
+            }
+        }
+    }
+
+    pub(crate) fn report_error(&self, error: String) {
+        self.errors.borrow_mut().push(error);
+    }
+
+    // 已经有同类资源时替换
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources
+            .insert(TypeId::of::<T>(), RcObject::new(resource).into_any());
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<RcObject<T>> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        resource.try_downcast::<T>().ok()
+    }
+
+    pub fn contains_resource<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    // 和 RefCell 一样，借用冲突时 panic
+    pub fn resource<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        let resource = self.resources.get(&TypeId::of::<T>())?;
+        Ref::filter_map(resource.borrow(), |resource: &dyn Any| {
+            resource.downcast_ref::<T>()
+        })
+        .ok()
+    }
+
+    pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        let resource = self.resources.get(&TypeId::of::<T>())?;
+        RefMut::filter_map(resource.borrow_mut(), |resource: &mut dyn Any| {
+            resource.downcast_mut::<T>()
+        })
+        .ok()
+    }
+
+    // 变化时发出 FEntitySelectedEvent
+    fn set_selected_entity(&mut self, entity_id: Option<FEntityId>) {
+        if self.selected_entity_id != entity_id {
+            self.selected_entity_id = entity_id;
+            self.send_event(FEntitySelectedEvent { entity_id });
+        }
+    }
+
+    fn get_events<T: 'static>(&self) -> FSharedEvents<T> {
+        let mut events = self.events.borrow_mut();
+        let queue = events
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Rc::new(RefCell::new(FEvents::<T>::new()))));
+        queue
+            .as_any()
+            .downcast_ref::<FSharedEvents<T>>()
+            .unwrap()
+            .clone()
+    }
+
+    pub fn event_writer<T: 'static>(&self) -> EventWriter<T> {
+        EventWriter::new(self.get_events())
+    }
+
+    pub fn event_reader<T: 'static>(&self) -> EventReader<T> {
+        EventReader::new(self.get_events())
+    }
+
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.get_events().borrow_mut().send(event);
+    }
+
+    pub fn get_storage(&self) -> &FArchetypeStorage {
+        &self.storage
+    }
+
+    pub fn get_systems_by_type<T: TSystem>(&self) -> Vec<RcObject<T>> {
         let mut result: Vec<RcObject<T>> = Vec::new();
-        for system in self.executed_systems.iter() {
-            if let Ok(sys) = system.clone().try_downcast::<T>() {
+        for system in self.schedule.iter() {
+            if let Ok(sys) = system.system.clone().try_downcast::<T>() {
                 result.push(sys);
             }
         }
@@ -72,45 +927,668 @@ impl HSpace {
     }
 
     pub fn tick(&mut self, delta_time: f64) {
-        let hovered_id = self.main_camera.as_ref().unwrap().borrow().context.borrow().pipeline.borrow().get_properties().hovered_object_id.clone();
-        self.selected_entity_id = hovered_id.borrow().clone();
-
-        for system in self.systems.iter() {
-            system.borrow_mut().begin_frame(self);
-        }
-
-        for system in self.systems.iter() {
-            system.borrow_mut().before_first_tick(self, delta_time);
-            for (entity_id, entity) in self.entities.iter() {
-                let groups = system.borrow().get_interested_components();
-                for (i, interested_component_group) in groups.iter().enumerate() {
-                    let mut components: Vec<RcAny> = Vec::new();
-                    for interested_component in interested_component_group.iter() {
-                        if let Some(component) = entity.components.get(interested_component) {
-                            components.push(component.clone());
-                        } else {
-                            break;
-                        }
+        self.tick_frame(FFrameTime::new(delta_time));
+    }
+
+    fn update_time(&mut self, frame_time: &FFrameTime) -> u32 {
+        let fixed_steps = self.fixed_timestep.advance(frame_time.delta_time);
+        let alpha = self.fixed_timestep.get_alpha();
+        let fixed_delta_time = self.fixed_timestep.get_fixed_delta_time();
+        if !self.contains_resource::<FTime>() {
+            self.insert_resource(FTime::default());
+        }
+        let mut time = self.resource_mut::<FTime>().unwrap();
+        time.delta_time = frame_time.delta_time;
+        time.real_delta_time = frame_time.real_delta_time;
+        time.time_scale = frame_time.time_scale;
+        time.paused = frame_time.paused;
+        time.elapsed_time += frame_time.delta_time;
+        time.frame_count += 1;
+        time.fixed_delta_time = fixed_delta_time;
+        time.fixed_steps = fixed_steps;
+        time.alpha = alpha;
+        fixed_steps
+    }
+
+    // 分批，每一批带上 stage。fixed 时用 fixed_tick 的 last run tick
+    // 同步点不随 executor_mode 变化，否则切换模式会改变 commands 生效的时机
+    fn build_batches(
+        &self,
+        systems: &[&FScheduledSystem],
+        fixed: bool,
+    ) -> Vec<(EStage, Vec<FBatchSystem>)> {
+        build_system_batches(systems, EExecutorMode::Batched)
+            .into_iter()
+            .map(|batch| {
+                let stage = systems[batch[0]].desc.stage;
+                let batch = batch
+                    .into_iter()
+                    .map(|index| {
+                        let system = systems[index];
+                        let last_run_tick = match fixed {
+                            true => system.last_fixed_run_tick.clone(),
+                            false => system.last_run_tick.clone(),
+                        };
+                        (system.system.clone(), last_run_tick)
+                    })
+                    .collect();
+                (stage, batch)
+            })
+            .collect()
+    }
+
+    // 执行一批 system，之后是同步点。fixed 时调用的是 fixed_tick_entity
+    fn run_batch(&mut self, batch: &[FBatchSystem], delta_time: f64, fixed: bool) {
+        // 同一批的 system 互不冲突，执行顺序不影响结果
+        for (system, last_run_tick) in batch.iter() {
+            let last_run = last_run_tick.get();
+            let tick = self.next_change_tick();
+            self.system_last_run_tick.set(last_run);
+            if !fixed {
+                system.borrow_mut().before_first_tick(self, delta_time);
+            }
+            let queries = system.borrow().get_queries();
+            for (i, query) in queries.iter().enumerate() {
+                // 匹配的 archetype 是缓存的，只有新增 archetype 时才重新检查
+                let matches = self.storage.query(query);
+                for query_match in matches.iter() {
+                    let archetype = self.storage.get_archetype(query_match.archetype);
+                    if archetype.is_empty() {
+                        continue;
                     }
-                    if components.len() == interested_component_group.len() {
+                    // 每个 archetype 整列借用一次，system 持有别的借用时跳过这个 archetype
+                    let mut columns = match self.storage.borrow_columns(query, query_match) {
+                        Ok(columns) => columns,
+                        Err(err) => {
+                            self.report_error(format!(
+                                "{} skipped an archetype: {}",
+                                system.borrow().get_name(),
+                                err
+                            ));
+                            continue;
+                        }
+                    };
+                    for (row, entity_id) in archetype.get_entities().iter().enumerate() {
+                        if !self.storage.filter_row(query, query_match, row, last_run) {
+                            continue;
+                        }
+                        let components = columns
+                            .iter_mut()
+                            .map(|column| column.as_mut().map(|column| column.get(row)))
+                            .collect();
                         let context = FSystemTickContext {
                             space: self,
                             delta_time: delta_time,
                             group: i,
-                            components: components,
+                            components,
+                            ticks: self.storage.fetch_row_ticks(query_match, row),
+                            change_tick: tick,
                             entity_id: *entity_id,
                         };
-                        system.borrow_mut().tick_entity(context);
+                        if fixed {
+                            system.borrow_mut().fixed_tick_entity(context);
+                        } else {
+                            system.borrow_mut().tick_entity(context);
+                        }
                     }
                 }
             }
-            self.executed_systems.push(system.clone());
+            if !fixed {
+                system.borrow_mut().after_last_tick(self);
+            }
+            last_run_tick.set(tick);
+            self.system_last_run_tick.set(0);
+        }
+        // 后面批次的 system 能看到这一批做的修改
+        self.apply_commands();
+    }
+
+    // frame_time 一般由 HContext 的时钟产生
+    pub fn tick_frame(&mut self, frame_time: FFrameTime) {
+        let fixed_steps = self.update_time(&frame_time);
+        let delta_time = frame_time.delta_time;
+
+        // 上上一帧的事件在这里丢弃
+        for queue in self.events.get_mut().values() {
+            queue.update();
+        }
+
+        // 脚本搭建的 space 不一定有相机
+        if let Some(main_camera) = self.main_camera.as_ref() {
+            let hovered_id = main_camera
+                .borrow()
+                .context
+                .borrow()
+                .pipeline
+                .borrow()
+                .get_properties()
+                .hovered_object_id
+                .clone();
+            // object id 是几帧之前画出来的，对应的 entity 可能已经被 despawn 了
+            let selected_entity_id = hovered_id
+                .borrow()
+                .map(FEntityId::from_bits)
+                .filter(|entity_id| self.storage.contains(*entity_id));
+            self.set_selected_entity(selected_entity_id);
+        }
+
+        // 在 tick 之外记录的命令先执行
+        self.apply_commands();
+
+        let (batches, fixed_batches) = {
+            let systems = self.get_running_systems();
+            let fixed_systems: Vec<&FScheduledSystem> = systems
+                .iter()
+                .filter(|system| system.has_fixed_tick)
+                .cloned()
+                .collect();
+            (
+                self.build_batches(&systems, false),
+                self.build_batches(&fixed_systems, true),
+            )
+        };
+
+        for (_, batch) in batches.iter() {
+            for (system, _) in batch.iter() {
+                system.borrow_mut().begin_frame(self);
+            }
         }
+        self.apply_commands();
 
-        for system in self.systems.iter() {
-            system.borrow_mut().end_frame(self);
+        // stage 是按顺序排好的，fixed_tick 插在 PreUpdate 的批次之后
+        let (pre_update, batches_after): (Vec<_>, Vec<_>) = batches
+            .iter()
+            .partition(|(stage, _)| *stage == EStage::PreUpdate);
+        for (_, batch) in pre_update {
+            self.run_batch(batch, delta_time, false);
+        }
+        let fixed_delta_time = self.fixed_timestep.get_fixed_delta_time();
+        for _ in 0..fixed_steps {
+            for (_, batch) in fixed_batches.iter() {
+                self.run_batch(batch, fixed_delta_time, true);
+            }
+        }
+        for (_, batch) in batches_after {
+            self.run_batch(batch, delta_time, false);
         }
 
-        self.executed_systems.clear();
+        for (_, batch) in batches.iter() {
+            for (system, _) in batch.iter() {
+                system.borrow_mut().end_frame(self);
+            }
+        }
+        self.apply_commands();
+
+        let min_last_run_tick = self.schedule.get_min_last_run_tick();
+        self.storage.clear_removed_before(min_last_run_tick);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::systems::{QueryItem, TQuerySystem};
+
+    fn assert_near(actual: glm::Vec3, expected: glm::Vec3) {
+        assert!(
+            glm::distance(&actual, &expected) < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    struct FSpawner;
+    struct FCounter;
+
+    impl TComponent for FSpawner {
+        fn type_name() -> &'static str {
+            "FSpawner"
+        }
+    }
+
+    impl TComponent for FCounter {
+        fn type_name() -> &'static str {
+            "FCounter"
+        }
+    }
+
+    fn spawn_with<T: TComponent>(space: &mut HSpace, component: T) -> FEntityId {
+        let mut entity = HEntity::new();
+        entity.insert(component);
+        space.add_entity(entity).unwrap()
+    }
+
+    // 每个 FSpawner 每帧通过 commands 生成一个带 FCounter 的 entity
+    #[derive(Default)]
+    struct FSpawnSystem {
+        spawned: Vec<FEntityId>,
+        visible_in_tick: bool,
+    }
+
+    impl TQuerySystem for FSpawnSystem {
+        type Query = (&'static FSpawner,);
+
+        fn tick(&mut self, context: &FSystemTickContext, _components: QueryItem<'_, Self>) {
+            let mut entity = HEntity::new();
+            entity.insert(FCounter);
+            let entity_id = context.space.commands().spawn(entity).unwrap();
+            self.visible_in_tick |= context.space.has_entity(entity_id);
+            self.spawned.push(entity_id);
+        }
+    }
+
+    #[derive(Default)]
+    struct FCountSystem {
+        count: usize,
+    }
+
+    impl TQuerySystem for FCountSystem {
+        type Query = (&'static FCounter,);
+
+        fn tick(&mut self, _context: &FSystemTickContext, _components: QueryItem<'_, Self>) {
+            self.count += 1;
+        }
+    }
+
+    #[test]
+    fn commands_are_applied_at_sync_points() {
+        let mut space = HSpace::new();
+        spawn_with(&mut space, FSpawner);
+        let spawner = space.add_system(FSpawnSystem::default()).unwrap();
+        let counter = space
+            .add_system_with(
+                FCountSystem::default(),
+                FSystemDesc::new().after("FSpawnSystem"),
+            )
+            .unwrap();
+
+        space.tick(0.1);
+        let spawned = spawner.borrow().spawned.clone();
+        assert_eq!(spawned.len(), 1);
+        // 记录命令的 system 自己看不到，排在后面的批次这一帧就能看到
+        assert!(!spawner.borrow().visible_in_tick);
+        assert_eq!(counter.borrow().count, 1);
+        assert!(space.has_entity(spawned[0]));
+
+        // tick 之外记录的命令在下一帧开始时执行
+        space.commands().despawn(spawned[0]);
+        assert!(space.has_entity(spawned[0]));
+        space.tick(0.1);
+        assert!(!space.has_entity(spawned[0]));
+        assert_eq!(counter.borrow().count, 2);
+        assert!(space.take_errors().is_empty());
+    }
+
+    // FSpawnSystem 和 FCountSystem 互不冲突，也没有顺序约束，分在同一批
+    fn run_spawn_and_count(executor_mode: EExecutorMode) -> (usize, Vec<FEntityId>, Vec<String>) {
+        let mut space = HSpace::new();
+        space.set_executor_mode(executor_mode);
+        spawn_with(&mut space, FSpawner);
+        space.add_system(FSpawnSystem::default()).unwrap();
+        let counter = space.add_system(FCountSystem::default()).unwrap();
+
+        for _ in 0..3 {
+            space.tick(0.1);
+        }
+        assert!(space.take_errors().is_empty());
+        let count = counter.borrow().count;
+        (count, space.get_entity_ids(), space.get_system_batches())
+    }
+
+    #[test]
+    fn executor_modes_apply_commands_at_the_same_sync_points() {
+        let (batched_count, batched_entities, batched_batches) =
+            run_spawn_and_count(EExecutorMode::Batched);
+        let (sequential_count, sequential_entities, sequential_batches) =
+            run_spawn_and_count(EExecutorMode::Sequential);
+
+        assert_eq!(batched_batches, vec!["Update: FSpawnSystem, FCountSystem"]);
+        assert_eq!(
+            sequential_batches,
+            vec!["Update: FSpawnSystem", "Update: FCountSystem"]
+        );
+        // 同一批里生成的 entity 要到下一帧才被数到：0 + 1 + 2
+        assert_eq!(batched_count, 3);
+        assert_eq!(sequential_count, batched_count);
+        assert_eq!(batched_entities.len(), 4);
+        assert_eq!(sequential_entities, batched_entities);
+    }
+
+    #[test]
+    fn failed_commands_are_skipped_and_reported() {
+        let mut space = HSpace::new();
+        let despawned = space.create_entity().unwrap();
+        space.despawn(despawned).unwrap();
+        // id 预留之后、spawn 命令执行之前就被 despawn 了
+        let reserved = space.commands().spawn(HEntity::new()).unwrap();
+        space.despawn(reserved).unwrap();
+        let alive = space.create_entity().unwrap();
+
+        space.commands().insert(despawned, FCounter);
+        space.commands().spawn(HEntity::new()).unwrap();
+        space.commands().insert(alive, FCounter);
+        space.commands().remove_component::<FSpawner>(alive);
+        space.apply_commands();
+
+        let errors = space.take_errors();
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().all(|err| err.starts_with("command skipped")));
+        assert!(errors[0].contains("despawned before spawning"));
+        assert!(errors[1].contains("has been despawned"));
+        assert!(errors[2].contains("FSpawner"));
+        // 失败的命令不影响后面的命令
+        assert!(space.get_component::<FCounter>(alive).is_some());
+        assert!(space.take_errors().is_empty());
+    }
+
+    // 查询写 FCounter，tick 时再借同一列会失败
+    #[derive(Default)]
+    struct FBorrowCounterSystem {
+        errors: Vec<String>,
+    }
+
+    impl TQuerySystem for FBorrowCounterSystem {
+        type Query = (&'static mut FCounter,);
+
+        fn tick(&mut self, context: &FSystemTickContext, _components: QueryItem<'_, Self>) {
+            let result = context
+                .space
+                .try_get_component_by_id::<FCounter>(context.entity_id, component_id::<FCounter>());
+            if let Err(err) = result {
+                self.errors.push(err);
+            }
+        }
+
+        fn after_last_tick(&mut self, space: &HSpace) {
+            for entity_id in space.get_entity_ids() {
+                assert!(space.get_component_mut::<FCounter>(entity_id).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn query_columns_stay_borrowed_while_the_system_ticks() {
+        let mut space = HSpace::new();
+        spawn_with(&mut space, FCounter);
+        spawn_with(&mut space, FCounter);
+        let system = space.add_system(FBorrowCounterSystem::default()).unwrap();
+
+        space.tick(0.1);
+        assert_eq!(
+            system.borrow().errors,
+            vec!["FCounter is already mutably borrowed"; 2]
+        );
+        assert!(space.take_errors().is_empty());
+    }
+
+    // 每帧记录上次执行之后失去 FCounter 的 entity
+    #[derive(Default)]
+    struct FRemovedCounterSystem {
+        removed: Vec<Vec<FEntityId>>,
+    }
+
+    impl TQuerySystem for FRemovedCounterSystem {
+        type Query = (&'static FCounter,);
+
+        fn before_first_tick(&mut self, space: &HSpace, _delta_time: f64) {
+            let removed = space.removed_components::<FCounter>();
+            self.removed.push(removed.into_iter().collect());
+        }
+
+        fn tick(&mut self, _context: &FSystemTickContext, _components: QueryItem<'_, Self>) {}
+    }
+
+    #[test]
+    fn removed_components_include_despawned_entities() {
+        let mut space = HSpace::new();
+        let despawned = spawn_with(&mut space, FCounter);
+        let stripped = spawn_with(&mut space, FCounter);
+        let kept = spawn_with(&mut space, FCounter);
+        let system = space.add_system(FRemovedCounterSystem::default()).unwrap();
+
+        space.tick(0.1);
+        space.despawn(despawned).unwrap();
+        space.remove_component::<FCounter>(stripped).unwrap();
+        space.tick(0.1);
+        // 通过 commands despawn 的下一帧开始时执行
+        space.commands().despawn(kept);
+        space.tick(0.1);
+        space.tick(0.1);
+
+        let mut expected = vec![despawned, stripped];
+        expected.sort();
+        assert_eq!(
+            system.borrow().removed,
+            vec![vec![], expected, vec![kept], vec![]]
+        );
+        // 所有 system 都执行过之后移除记录就清理掉了
+        assert!(space.removed_components::<FCounter>().is_empty());
+        assert!(space.take_errors().is_empty());
+    }
+
+    fn spawn_transform(space: &mut HSpace, position: glm::Vec3, rotation: glm::Quat) -> FEntityId {
+        let transform =
+            HTransformComponent::new_trs(&position, &rotation, &glm::vec3(1.0, 1.0, 1.0));
+        spawn_with(space, transform)
+    }
+
+    fn get_position(space: &HSpace, entity_id: FEntityId) -> glm::Vec3 {
+        space
+            .get_component::<HTransformComponent>(entity_id)
+            .unwrap()
+            .position
+    }
+
+    fn get_world_position(space: &HSpace, entity_id: FEntityId) -> glm::Vec3 {
+        space.get_world_matrix(entity_id).unwrap().column(3).xyz()
+    }
+
+    // HTransformSystem 算出来的世界变换
+    fn get_propagated_position(space: &HSpace, entity_id: FEntityId) -> glm::Vec3 {
+        space
+            .get_component::<HTransformComponent>(entity_id)
+            .unwrap()
+            .get_world_position()
+    }
+
+    #[test]
+    fn reparenting_keeps_the_world_transform() {
+        let mut space = HSpace::new();
+        let parent = spawn_transform(&mut space, glm::vec3(1.0, 0.0, 0.0), glm::quat_identity());
+        let other = spawn_transform(&mut space, glm::vec3(0.0, 0.0, 5.0), glm::quat_identity());
+        let child = spawn_transform(&mut space, glm::vec3(0.0, 2.0, 0.0), glm::quat_identity());
+
+        space.set_parent(child, Some(parent)).unwrap();
+        assert_eq!(space.get_parent(child), Some(parent));
+        assert_eq!(space.get_children(parent), vec![child]);
+        assert_near(get_position(&space, child), glm::vec3(-1.0, 2.0, 0.0));
+        assert_near(get_world_position(&space, child), glm::vec3(0.0, 2.0, 0.0));
+
+        // 换一个父节点，原来的父节点没有子节点了就去掉 HChildrenComponent
+        space.set_parent(child, Some(other)).unwrap();
+        assert!(space.get_component::<HChildrenComponent>(parent).is_none());
+        assert_eq!(space.get_children(other), vec![child]);
+        assert_near(get_position(&space, child), glm::vec3(0.0, 2.0, -5.0));
+        assert_near(get_world_position(&space, child), glm::vec3(0.0, 2.0, 0.0));
+
+        space.set_parent(child, None).unwrap();
+        assert_eq!(space.get_parent(child), None);
+        assert!(space.get_children(other).is_empty());
+        assert_near(get_position(&space, child), glm::vec3(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn reparenting_rejects_cycles_and_stale_ids() {
+        let mut space = HSpace::new();
+        let root = space.create_entity().unwrap();
+        let child = space.create_entity().unwrap();
+        let grandchild = space.create_entity().unwrap();
+        space.set_parent(child, Some(root)).unwrap();
+        space.set_parent(grandchild, Some(child)).unwrap();
+
+        let err = space.set_parent(root, Some(grandchild)).unwrap_err();
+        assert!(err.contains("descendant of itself"));
+        assert!(space.set_parent(root, Some(root)).is_err());
+        assert_eq!(space.get_parent(root), None);
+
+        let stale = space.create_entity().unwrap();
+        space.despawn(stale).unwrap();
+        assert!(space.set_parent(child, Some(stale)).is_err());
+        assert_eq!(space.get_parent(child), Some(root));
+    }
+
+    #[test]
+    fn despawn_orphans_children_and_despawn_recursive_removes_them() {
+        let mut space = HSpace::new();
+        let parent = spawn_transform(&mut space, glm::vec3(1.0, 0.0, 0.0), glm::quat_identity());
+        let child = spawn_transform(&mut space, glm::vec3(0.0, 1.0, 0.0), glm::quat_identity());
+        space.set_parent(child, Some(parent)).unwrap();
+
+        // 子节点变成根节点，世界变换不变
+        space.despawn(parent).unwrap();
+        assert!(!space.has_entity(parent));
+        assert_eq!(space.get_parent(child), None);
+        assert_near(get_position(&space, child), glm::vec3(0.0, 1.0, 0.0));
+
+        let root = space.create_entity().unwrap();
+        let grandchild = space.create_entity().unwrap();
+        space.set_parent(child, Some(root)).unwrap();
+        space.set_parent(grandchild, Some(child)).unwrap();
+        space.despawn_recursive(root).unwrap();
+        assert!(space.get_entity_ids().is_empty());
+        assert!(space.despawn(grandchild).is_err());
+    }
+
+    #[test]
+    fn transform_system_propagates_world_transforms() {
+        let mut space = HSpace::new();
+        space
+            .add_system_with(
+                HTransformSystem::new(),
+                FSystemDesc::new().in_stage(EStage::PostUpdate),
+            )
+            .unwrap();
+        let quarter_turn = glm::quat_angle_axis(90f32.to_radians(), &glm::vec3(0.0, 0.0, 1.0));
+        let parent = spawn_transform(&mut space, glm::vec3(1.0, 0.0, 0.0), quarter_turn);
+        let child = spawn_transform(&mut space, glm::vec3(1.0, 1.0, 0.0), glm::quat_identity());
+        // 中间没有 transform 的节点不影响子节点，子节点相对世界
+        let empty = space.create_entity().unwrap();
+        let detached = spawn_transform(&mut space, glm::vec3(0.0, 0.0, 3.0), glm::quat_identity());
+        space.set_parent(child, Some(parent)).unwrap();
+        space.set_parent(empty, Some(parent)).unwrap();
+        space.set_parent(detached, Some(empty)).unwrap();
+
+        space.tick(0.1);
+        assert_near(
+            get_propagated_position(&space, child),
+            glm::vec3(1.0, 1.0, 0.0),
+        );
+        assert_near(
+            get_propagated_position(&space, detached),
+            glm::vec3(0.0, 0.0, 3.0),
+        );
+
+        // 只移动父节点，子节点的世界变换跟着更新
+        space
+            .get_component_mut::<HTransformComponent>(parent)
+            .unwrap()
+            .position = glm::vec3(2.0, 0.0, 0.0);
+        space.mark_changed::<HTransformComponent>(parent);
+        space.tick(0.1);
+        assert_near(
+            get_propagated_position(&space, parent),
+            glm::vec3(2.0, 0.0, 0.0),
+        );
+        assert_near(
+            get_propagated_position(&space, child),
+            glm::vec3(2.0, 1.0, 0.0),
+        );
+        assert_near(get_position(&space, child), glm::vec3(1.0, 0.0, 0.0));
+    }
+
+    fn spawn_named(space: &mut HSpace, name: &str, parent: Option<FEntityId>) -> FEntityId {
+        let entity_id = space.create_entity().unwrap();
+        space.set_name(entity_id, name.to_string()).unwrap();
+        space.set_parent(entity_id, parent).unwrap();
+        entity_id
+    }
+
+    #[test]
+    fn name_index_follows_renames_and_despawn() {
+        let mut space = HSpace::new();
+        let level = spawn_named(&mut space, "Level", None);
+        let door = spawn_named(&mut space, "Door", Some(level));
+        let handle = spawn_named(&mut space, "Handle", Some(door));
+        let other_door = spawn_named(&mut space, "Door", None);
+
+        assert_eq!(
+            space.find_all_by_name("Door".into()),
+            vec![door, other_door]
+        );
+        assert_eq!(space.find_by_path("Level/Door/Handle".into()), Some(handle));
+        assert_eq!(
+            space.get_path(handle),
+            Some("Level/Door/Handle".to_string())
+        );
+        assert_eq!(space.find_by_path("Door".into()), Some(other_door));
+        assert_eq!(space.find_by_path("".into()), None);
+        assert!(space.set_name(door, "A/B".into()).is_err());
+
+        space.set_name(door, "Gate".into()).unwrap();
+        assert_eq!(space.find_all_by_name("Door".into()), vec![other_door]);
+        assert_eq!(space.find_by_path("Level/Door/Handle".into()), None);
+        assert_eq!(
+            space.get_path(handle),
+            Some("Level/Gate/Handle".to_string())
+        );
+
+        // 直接替换 component 也会更新索引
+        space
+            .insert_component(door, HNameComponent::new("Portal").unwrap())
+            .unwrap();
+        assert_eq!(space.find_by_name("Gate".into()), None);
+        assert_eq!(space.find_by_name("Portal".into()), Some(door));
+
+        space.despawn(level).unwrap();
+        assert_eq!(space.find_by_name("Level".into()), None);
+        assert_eq!(space.find_by_path("Portal/Handle".into()), Some(handle));
+        assert_eq!(space.get_path(handle), Some("Portal/Handle".to_string()));
+
+        // 路径中间有没有名字的节点
+        let unnamed = space.create_entity().unwrap();
+        space.set_parent(door, Some(unnamed)).unwrap();
+        assert_eq!(space.get_path(handle), None);
+        assert_eq!(space.find_by_path("Portal/Handle".into()), None);
+    }
+
+    #[test]
+    fn tag_index_follows_add_remove_and_despawn() {
+        let mut space = HSpace::new();
+        let first = space.create_entity().unwrap();
+        let second = space.create_entity().unwrap();
+        space.add_tag(second, "enemy".into()).unwrap();
+        space.add_tag(first, "enemy".into()).unwrap();
+        space.add_tag(first, "boss".into()).unwrap();
+        space.add_tag(first, "boss".into()).unwrap();
+        assert!(space.add_tag(first, "".into()).is_err());
+
+        assert_eq!(space.find_by_tag("enemy".into()), vec![first, second]);
+        assert_eq!(space.get_tags(first), vec!["boss", "enemy"]);
+        assert!(space.has_tag(first, "boss".into()));
+
+        assert_eq!(space.remove_tag(first, "enemy".into()), Ok(true));
+        assert_eq!(space.remove_tag(first, "enemy".into()), Ok(false));
+        assert_eq!(space.find_by_tag("enemy".into()), vec![second]);
+        assert_eq!(space.find_by_tag("boss".into()), vec![first]);
+
+        // 最后一个标签移除后 component 也没了
+        assert_eq!(space.remove_tag(first, "boss".into()), Ok(true));
+        assert!(space.get_component::<HTagsComponent>(first).is_none());
+        assert!(space.find_by_tag("boss".into()).is_empty());
+
+        space.despawn(second).unwrap();
+        assert!(space.find_by_tag("enemy".into()).is_empty());
+        assert!(space.add_tag(second, "enemy".into()).is_err());
     }
 }
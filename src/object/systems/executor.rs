@@ -0,0 +1,287 @@
+// 按 system 声明的访问把一帧要执行的 system 分批：同一批里的 system 互不冲突，批次之间是同步点
+// 冲突的 system 分在不同的批次，按 schedule 的顺序执行
+// 注意这里只做分批，不做并行：component、system 都是 Rc，脚本 system 还绑定在 v8 isolate 上，
+// 所有 system 都在当前线程按 schedule 的顺序依次执行。分批的作用是确定同步点（在批次之间应用 commands），
+// 同步点总是按 Batched 分出的批次确定，和 EExecutorMode 无关，所以切换模式不会改变执行结果
+
+use std::any::TypeId;
+
+use crate::object::{
+    archetype::FQueryDesc,
+    components::{component_id, TComponent},
+};
+
+use super::FScheduledSystem;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FSystemAccess {
+    pub reads: Vec<u32>,
+    pub writes: Vec<u32>,
+    pub resource_reads: Vec<TypeId>,
+    pub resource_writes: Vec<TypeId>,
+    // 会访问任意数据（比如脚本），不能和其他 system 放在同一批
+    pub exclusive: bool,
+}
+
+impl FSystemAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn exclusive() -> Self {
+        Self {
+            exclusive: true,
+            ..Self::default()
+        }
+    }
+
+    // 变化筛选虽然不借用 component，但要读其他 system 写入的 change tick，算作读
+    pub fn from_queries(queries: &[FQueryDesc]) -> Self {
+        let mut access = Self::new();
+        for component in queries.iter().flat_map(|query| query.components.iter()) {
+            if component.write {
+                access.writes.push(component.component_id);
+            } else {
+                access.reads.push(component.component_id);
+            }
+        }
+        access
+    }
+
+    pub fn read_component<T: TComponent>(mut self) -> Self {
+        self.reads.push(component_id::<T>());
+        self
+    }
+
+    pub fn write_component<T: TComponent>(mut self) -> Self {
+        self.writes.push(component_id::<T>());
+        self
+    }
+
+    pub fn read_resource<T: 'static>(mut self) -> Self {
+        self.resource_reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write_resource<T: 'static>(mut self) -> Self {
+        self.resource_writes.push(TypeId::of::<T>());
+        self
+    }
+
+    // 两边都只读的数据不冲突
+    pub fn is_compatible(&self, other: &FSystemAccess) -> bool {
+        fn overlaps<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+            a.iter().any(|item| b.contains(item))
+        }
+
+        !self.exclusive
+            && !other.exclusive
+            && !overlaps(&self.writes, &other.writes)
+            && !overlaps(&self.writes, &other.reads)
+            && !overlaps(&self.reads, &other.writes)
+            && !overlaps(&self.resource_writes, &other.resource_writes)
+            && !overlaps(&self.resource_writes, &other.resource_reads)
+            && !overlaps(&self.resource_reads, &other.resource_writes)
+    }
+}
+
+// 只影响 HSpace::get_system_batches 怎么列出 system，执行顺序和同步点都一样
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EExecutorMode {
+    // 互不冲突的相邻 system 合并成一批
+    Batched,
+    // 调试用：每个 system 单独列出
+    Sequential,
+}
+
+impl Default for EExecutorMode {
+    fn default() -> Self {
+        EExecutorMode::Batched
+    }
+}
+
+// systems 按执行顺序排列，返回每一批在 systems 里的下标
+// 只把相邻的 system 合并成一批，不会越过冲突的 system 提前执行
+pub fn build_system_batches(systems: &[&FScheduledSystem], mode: EExecutorMode) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = vec![];
+    for (index, system) in systems.iter().enumerate() {
+        let joinable = match (mode, batches.last()) {
+            (EExecutorMode::Batched, Some(batch)) => batch.iter().all(|other| {
+                let other = systems[*other];
+                // stage 之间、有顺序约束的 system 之间都要有同步点
+                other.desc.stage == system.desc.stage
+                    && !other.is_ordered_with(system)
+                    && other.access.is_compatible(&system.access)
+            }),
+            _ => false,
+        };
+        match batches.last_mut() {
+            Some(batch) if joinable => batch.push(index),
+            _ => batches.push(vec![index]),
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use hoo_object::{into_trait, RcObject, RcTrait};
+
+    use super::*;
+    use crate::object::{
+        archetype::{EChangeFilter, FComponentAccess},
+        systems::{EStage, FSchedule, FSystemDesc, FSystemTickContext, TSystem},
+    };
+
+    struct FMass;
+    struct FSpeed;
+    struct FScore;
+
+    impl TComponent for FMass {
+        fn type_name() -> &'static str {
+            "FMass"
+        }
+    }
+
+    impl TComponent for FSpeed {
+        fn type_name() -> &'static str {
+            "FSpeed"
+        }
+    }
+
+    // 只声明访问，不做任何事
+    struct FAccessSystem(FSystemAccess);
+
+    impl TSystem for FAccessSystem {
+        fn get_queries(&self) -> Vec<FQueryDesc> {
+            vec![]
+        }
+
+        fn get_access(&self) -> FSystemAccess {
+            self.0.clone()
+        }
+
+        fn tick_entity(&mut self, _context: FSystemTickContext) {}
+    }
+
+    fn add(schedule: &mut FSchedule, name: &str, access: FSystemAccess, desc: FSystemDesc) {
+        let system = RcObject::new(FAccessSystem(access));
+        let system: RcTrait<dyn TSystem> = into_trait!(system);
+        schedule.add(system, name.to_string(), desc).unwrap();
+    }
+
+    fn get_batch_names(schedule: &FSchedule, mode: EExecutorMode) -> Vec<String> {
+        let systems: Vec<_> = schedule.iter().collect();
+        build_system_batches(&systems, mode)
+            .into_iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|index| systems[*index].name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect()
+    }
+
+    fn build_schedule() -> FSchedule {
+        let mut schedule = FSchedule::new();
+        let read_mass = || FSystemAccess::new().read_component::<FMass>();
+        add(&mut schedule, "R1", read_mass(), FSystemDesc::new());
+        add(&mut schedule, "R2", read_mass(), FSystemDesc::new());
+        add(
+            &mut schedule,
+            "W",
+            FSystemAccess::new().write_component::<FMass>(),
+            FSystemDesc::new(),
+        );
+        // 和 W 不冲突，但有顺序约束
+        add(
+            &mut schedule,
+            "L",
+            FSystemAccess::new().read_component::<FSpeed>(),
+            FSystemDesc::new().after("W"),
+        );
+        add(
+            &mut schedule,
+            "X",
+            FSystemAccess::exclusive(),
+            FSystemDesc::new(),
+        );
+        add(
+            &mut schedule,
+            "Q1",
+            FSystemAccess::new().read_resource::<FScore>(),
+            FSystemDesc::new(),
+        );
+        add(
+            &mut schedule,
+            "Q2",
+            FSystemAccess::new().read_resource::<FScore>(),
+            FSystemDesc::new(),
+        );
+        // 不冲突，但在另一个 stage
+        add(
+            &mut schedule,
+            "P",
+            FSystemAccess::new().read_resource::<FScore>(),
+            FSystemDesc::new().in_stage(EStage::PostUpdate),
+        );
+        schedule
+    }
+
+    #[test]
+    fn batches_split_at_conflicts_constraints_and_stages() {
+        let schedule = build_schedule();
+        assert_eq!(
+            get_batch_names(&schedule, EExecutorMode::Batched),
+            ["R1,R2", "W", "L", "X", "Q1,Q2", "P"]
+        );
+        assert_eq!(
+            get_batch_names(&schedule, EExecutorMode::Sequential),
+            ["R1", "R2", "W", "L", "X", "Q1", "Q2", "P"]
+        );
+    }
+
+    #[test]
+    fn access_conflicts() {
+        let read_mass = FSystemAccess::new().read_component::<FMass>();
+        let write_mass = FSystemAccess::new().write_component::<FMass>();
+        let write_speed = FSystemAccess::new().write_component::<FSpeed>();
+        let read_score = FSystemAccess::new().read_resource::<FScore>();
+        let write_score = FSystemAccess::new().write_resource::<FScore>();
+
+        assert!(read_mass.is_compatible(&read_mass));
+        assert!(!read_mass.is_compatible(&write_mass));
+        assert!(!write_mass.is_compatible(&read_mass));
+        assert!(!write_mass.is_compatible(&write_mass));
+        assert!(write_mass.is_compatible(&write_speed));
+        assert!(read_score.is_compatible(&read_score));
+        assert!(!read_score.is_compatible(&write_score));
+        assert!(write_score.is_compatible(&write_mass));
+        assert!(!FSystemAccess::exclusive().is_compatible(&FSystemAccess::new()));
+        assert!(!FSystemAccess::new().is_compatible(&FSystemAccess::exclusive()));
+    }
+
+    #[test]
+    fn filters_count_as_reads() {
+        let mass = component_id::<FMass>();
+        let speed = component_id::<FSpeed>();
+        let access = |component_id, write, filter| FComponentAccess {
+            component_id,
+            write,
+            optional: false,
+            filter,
+        };
+        let query = FQueryDesc {
+            components: vec![
+                access(mass, true, None),
+                access(speed, false, Some(EChangeFilter::Changed)),
+            ],
+        };
+        let access = FSystemAccess::from_queries(&[query, FQueryDesc::from_ids(&[speed])]);
+        assert_eq!(access.writes, vec![mass]);
+        assert_eq!(access.reads, vec![speed, speed]);
+        assert!(!access.exclusive);
+    }
+}
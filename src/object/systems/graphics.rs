@@ -1,101 +1,94 @@
-use std::any::Any;
-
-use hoo_object::RcObject;
+use std::collections::HashMap;
 
 use nalgebra_glm as glm;
 
 use crate::{
-    device::graphics::{FRenderObject, BRenderObjectFlags},
+    device::graphics::{BRenderObjectFlags, FModel, FRenderObject},
     graphics::FPipelineContext,
-    hoo_engine,
-    object::{components::*, objects::HCameraTarget, space::HSpace},
+    object::{
+        archetype::FQueryDesc,
+        components::*,
+        entity::FEntityId,
+        events::{EventReader, FEvents},
+        objects::{FShaderLight, HCameraTarget},
+        resources::{FRenderQueue, FRenderTargets},
+        space::HSpace,
+    },
     utils::RcMut,
 };
 
-use super::{FSystemTickContext, HCameraSystem, HLightingSystem};
+use super::{
+    Changed, FCameraViewEvent, FLightEvent, FSystemAccess, FSystemTickContext, Query, TSystem,
+};
+
+// 第一组：HStaticModelComponent 新加入或者被修改过的 entity，重新组装模型
+// 只检测 component 本身，直接修改 HStaticModel 里的 mesh / material 不会被发现
+type FModelChangedQuery = (
+    Changed<HStaticModelComponent>,
+    &'static HStaticModelComponent,
+);
+// 第二组：有模型的画模型，被选中的再画坐标轴
+type FDrawQuery = (
+    &'static HTransformComponent,
+    Option<&'static HStaticModelComponent>,
+);
+
+const MODEL_CHANGED_GROUP: usize = 0;
+const DRAW_GROUP: usize = 1;
 
 pub struct HGraphicsSystem {
     pipelines: Vec<RcMut<FPipelineContext>>,
+    // 相机和灯光由 HCameraSystem / HLightingSystem 在这一帧更早的时候发出
+    camera_views: Option<EventReader<FCameraViewEvent>>,
+    lights: Option<EventReader<FLightEvent>>,
+    // 组装好的模型，HStaticModelComponent 被移除（包括 entity 被 despawn）时丢掉
+    models: HashMap<FEntityId, FModel>,
 }
 
 impl HGraphicsSystem {
     pub fn new() -> Self {
-        Self { pipelines: vec![] }
-    }
-}
-
-impl super::traits::TSystem for HGraphicsSystem {
-    fn begin_frame(&mut self, _space: &HSpace) {
-        self.pipelines.clear();
-    }
-
-    fn before_first_tick(&mut self, space: &HSpace, _delta_time: f64) {
-        let camera_systems = space.get_executed_systems_by_type::<HCameraSystem>();
-
-        if let Some(camera_system) = camera_systems.first() {
-            for (camera, transform) in camera_system.borrow().cameras.iter() {
-                let camera = camera.borrow();
-
-                let projection_mat = {
-                    let mut proj = camera.camera_projection.clone();
-                    if camera.auto_aspect {
-                        let target_size = match camera.target.clone() {
-                            HCameraTarget::Texture(target) => target.borrow().size(),
-                            HCameraTarget::Screen => {
-                                hoo_engine().borrow().get_renderer().get_swapchain_size()
-                            }
-                        };
-                        let aspect_ratio = 1.0 * target_size.0 as f32 / target_size.1 as f32;
-                        proj.set_aspect_ratio(aspect_ratio);
-                    }
-                    proj.get_projection_matrix()
-                };
-
-                let context_ref = camera.context.borrow_mut();
-                let mut pipeline = context_ref.data.borrow_mut();
-                pipeline.camera_transform = *transform;
-                pipeline.camera_projection = projection_mat;
-                pipeline.set_render_target(camera.target.clone());
-
-                self.pipelines.push(camera.context.clone());
-            }
+        Self {
+            pipelines: vec![],
+            camera_views: None,
+            lights: None,
+            models: HashMap::new(),
         }
     }
 
-    fn tick_entity(&mut self, context: FSystemTickContext) {
-        if context.group == 1 {
-            let transform: RcObject<HTransformComponent> =
-                context.components[0].clone().try_downcast().unwrap();
-            let static_model: RcObject<HStaticModelComponent> =
-                context.components[1].clone().try_downcast().unwrap();
-
-            let transform_ref = transform.borrow();
+    fn get_model(&mut self, entity_id: FEntityId, static_model: &HStaticModelComponent) -> FModel {
+        self.models
+            .entry(entity_id)
+            .or_insert_with(|| static_model.model.borrow().assemble_model())
+            .clone()
+    }
 
-            // todo: better api?
-            let transform = transform_ref.get_matrix();
+    fn draw(
+        &mut self,
+        context: &FSystemTickContext,
+        transform: &HTransformComponent,
+        static_model: Option<&HStaticModelComponent>,
+    ) {
+        // todo: better api?
+        let transform = transform.get_world_matrix();
 
+        if let Some(static_model) = static_model {
             // TODO: model 和 material 为啥要 view 呢？
-            let model = static_model.borrow().model.borrow().assemble_model();
+            let model = self.get_model(context.entity_id, static_model);
 
             for pipeline in self.pipelines.iter_mut() {
                 let mut render_object = FRenderObject::new(model.clone());
                 render_object.set_transform_model(transform);
                 // TODO: 理论上 entity id 和 object id 是不一样的，要考虑单个 entity 多个 mesh 的情况
-                render_object.set_object_id(context.entity_id);
+                render_object.set_object_id(context.entity_id.to_bits());
                 pipeline
                     .borrow_mut()
                     .data
                     .borrow_mut()
                     .add_render_object(render_object);
             }
-        } else if context.group == 0 && Some(context.entity_id) == context.space.selected_entity_id {
-            
-            let transform: RcObject<HTransformComponent> =
-                context.components[0].clone().try_downcast().unwrap();
-
-            let transform_ref = transform.borrow();
-            let transform = transform_ref.get_matrix();
+        }
 
+        if Some(context.entity_id) == context.space.selected_entity_id {
             let obj_model = HAxisComponent::GetAxisModel();
             let model = obj_model.borrow().assemble_model();
 
@@ -104,11 +97,15 @@ impl super::traits::TSystem for HGraphicsSystem {
                 let mut pipeline = pipeline.data.borrow_mut();
 
                 let scale_matrix = glm::scaling(&glm::vec3(0.1, 0.1, 0.1));
-                
+
                 // x
-                {   
+                {
                     let mut render_object = FRenderObject::new(model.clone());
-                    render_object.set_transform_model(transform * glm::rotation(90f32.to_radians(), &glm::vec3(0.0, 0.0, 1.0)) * scale_matrix);
+                    render_object.set_transform_model(
+                        transform
+                            * glm::rotation(90f32.to_radians(), &glm::vec3(0.0, 0.0, 1.0))
+                            * scale_matrix,
+                    );
                     render_object.set_flags(BRenderObjectFlags::MODEL_AXIS);
                     pipeline.add_render_object(render_object);
                 }
@@ -116,50 +113,146 @@ impl super::traits::TSystem for HGraphicsSystem {
                 // y
                 {
                     let mut render_object = FRenderObject::new(model.clone());
-                    render_object.set_transform_model(transform * glm::rotation(180f32.to_radians(), &glm::vec3(0.0, 0.0, 1.0)) * scale_matrix);
+                    render_object.set_transform_model(
+                        transform
+                            * glm::rotation(180f32.to_radians(), &glm::vec3(0.0, 0.0, 1.0))
+                            * scale_matrix,
+                    );
                     render_object.set_flags(BRenderObjectFlags::MODEL_AXIS);
                     pipeline.add_render_object(render_object);
-
                 }
 
                 // z
                 {
                     let mut render_object = FRenderObject::new(model.clone());
-                    render_object.set_transform_model(transform * glm::rotation(90f32.to_radians(), &glm::vec3(1.0, 0.0, 0.0)) * scale_matrix);
+                    render_object.set_transform_model(
+                        transform
+                            * glm::rotation(90f32.to_radians(), &glm::vec3(1.0, 0.0, 0.0))
+                            * scale_matrix,
+                    );
                     render_object.set_flags(BRenderObjectFlags::MODEL_AXIS);
                     pipeline.add_render_object(render_object);
                 }
             }
         }
     }
+}
+
+impl TSystem for HGraphicsSystem {
+    fn get_queries(&self) -> Vec<FQueryDesc> {
+        vec![
+            Query::<FModelChangedQuery>::desc(),
+            Query::<FDrawQuery>::desc(),
+        ]
+    }
+
+    fn get_access(&self) -> FSystemAccess {
+        FSystemAccess::from_queries(&self.get_queries())
+            .read_resource::<FRenderTargets>()
+            .write_resource::<FRenderQueue>()
+            .read_resource::<FEvents<FCameraViewEvent>>()
+            .read_resource::<FEvents<FLightEvent>>()
+    }
+
+    // 之前几帧的事件跳过，只用这一帧发出的
+    fn begin_frame(&mut self, space: &HSpace) {
+        self.pipelines.clear();
+        self.camera_views
+            .get_or_insert_with(|| space.event_reader())
+            .clear();
+        self.lights
+            .get_or_insert_with(|| space.event_reader())
+            .clear();
+    }
+
+    fn before_first_tick(&mut self, space: &HSpace, _delta_time: f64) {
+        // 重新加入的 component 会被 MODEL_CHANGED_GROUP 查到，重新组装
+        for entity_id in space.removed_components::<HStaticModelComponent>() {
+            self.models.remove(&entity_id);
+        }
+
+        let camera_views = match self.camera_views.as_mut() {
+            Some(camera_views) => camera_views.read(),
+            None => vec![],
+        };
+
+        for FCameraViewEvent { camera, transform } in camera_views.iter() {
+            let camera = camera.borrow();
+
+            let projection_mat = {
+                let mut proj = camera.camera_projection.clone();
+                if camera.auto_aspect {
+                    // 没有窗口时不知道屏幕大小，保持原来的宽高比
+                    let target_size = match camera.target.clone() {
+                        HCameraTarget::Texture(target) => Some(target.borrow().size()),
+                        HCameraTarget::Screen => space
+                            .resource::<FRenderTargets>()
+                            .map(|render_targets| render_targets.swapchain_size),
+                    };
+                    if let Some(target_size) = target_size {
+                        let aspect_ratio = 1.0 * target_size.0 as f32 / target_size.1 as f32;
+                        proj.set_aspect_ratio(aspect_ratio);
+                    }
+                }
+                proj.get_projection_matrix()
+            };
+
+            let context_ref = camera.context.borrow_mut();
+            let mut pipeline = context_ref.data.borrow_mut();
+            pipeline.camera_transform = *transform;
+            pipeline.camera_projection = projection_mat;
+            pipeline.set_render_target(camera.target.clone());
+
+            self.pipelines.push(camera.context.clone());
+        }
+    }
+
+    fn tick_entity(&mut self, mut context: FSystemTickContext) {
+        let mut components = std::mem::take(&mut context.components);
+        let result = match context.group {
+            MODEL_CHANGED_GROUP => Query::<FModelChangedQuery>::fetch(
+                &mut components,
+                &context.ticks,
+                context.change_tick,
+            )
+            .map(|(_, static_model)| {
+                let model = static_model.model.borrow().assemble_model();
+                self.models.insert(context.entity_id, model);
+            }),
+            DRAW_GROUP => {
+                Query::<FDrawQuery>::fetch(&mut components, &context.ticks, context.change_tick)
+                    .map(|(transform, static_model)| self.draw(&context, transform, static_model))
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            context.space.report_error(format!(
+                "{} skipped entity {}: {}",
+                self.get_name(),
+                context.entity_id,
+                err
+            ));
+        }
+    }
 
     fn end_frame(&mut self, space: &HSpace) {
-        let lighting_system = space.get_executed_systems_by_type::<HLightingSystem>();
+        let lights: Vec<FShaderLight> = match self.lights.as_mut() {
+            Some(lights) => lights.read().into_iter().map(|event| event.light).collect(),
+            None => vec![],
+        };
 
         for pipeline in self.pipelines.clone() {
-            if let Some(lighting_system) = lighting_system.first() {
-                let lighting_system = lighting_system.borrow();
-                let lights = lighting_system.get_lights();
-                pipeline
-                    .borrow_mut()
-                    .data
-                    .borrow_mut()
-                    .set_lights(lights.clone());
+            pipeline
+                .borrow_mut()
+                .data
+                .borrow_mut()
+                .set_lights(lights.clone());
 
-                hoo_engine()
-                    .borrow()
-                    .get_renderer()
-                    .submit_pipeline(pipeline.clone());
+            if let Some(mut render_queue) = space.resource_mut::<FRenderQueue>() {
+                render_queue.pipelines.push(pipeline.clone());
             }
         }
 
         self.pipelines.clear();
     }
-
-    fn get_interested_components(&self) -> &'static [&'static [u32]] {
-        &[
-            &[COMPONENT_ID_TRANSFORM],
-            &[COMPONENT_ID_TRANSFORM, COMPONENT_ID_STATIC_MODEL],
-        ]
-    }
 }
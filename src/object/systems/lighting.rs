@@ -1,53 +1,41 @@
-use std::{any::Any, ops::Deref};
+use std::ops::Deref;
 
-use hoo_object::RcObject;
+use crate::object::{components::*, events::FEvents, objects::FShaderLight};
 
-use crate::object::{components::*, objects::FShaderLight, space::HSpace};
+use super::{FSystemAccess, FSystemTickContext, QueryItem, TQueryData, TQuerySystem};
 
-use super::FSystemTickContext;
-
-pub struct HLightingSystem {
-    lights: Vec<FShaderLight>,
+// 每帧为每个灯光发出一次，HGraphicsSystem 读取后交给渲染管线
+#[derive(Clone, Copy)]
+pub struct FLightEvent {
+    pub light: FShaderLight,
 }
 
-impl HLightingSystem {
-    pub fn new() -> Self {
-        Self { lights: vec![] }
-    }
-}
+pub struct HLightingSystem {}
 
 impl HLightingSystem {
-    pub fn get_lights(&self) -> &Vec<FShaderLight> {
-        &self.lights
+    pub fn new() -> Self {
+        Self {}
     }
 }
 
-impl super::traits::TSystem for HLightingSystem {
-    fn begin_frame(&mut self, _space: &HSpace) {
-        self.lights.clear();
+impl TQuerySystem for HLightingSystem {
+    type Query = (&'static HTransformComponent, &'static HLightComponent);
 
-        // end-frame 后，其他 system 会从中读取数据
+    // 事件队列按资源算
+    fn get_access(&self) -> FSystemAccess {
+        FSystemAccess::from_queries(&[Self::Query::desc()]).write_resource::<FEvents<FLightEvent>>()
     }
 
-    fn tick_entity(&mut self, context: FSystemTickContext) {
-        let transform: RcObject<HTransformComponent> =
-            context.components[0].clone().try_downcast().unwrap();
-        let transform_ref = transform.borrow();
-
-        let light: RcObject<HLightComponent> =
-            context.components[1].clone().try_downcast().unwrap();
-        let light_ref = light.borrow();
-        let light_desc = light_ref.light.borrow();
+    fn tick(&mut self, context: &FSystemTickContext, (transform, light): QueryItem<'_, Self>) {
+        let light_desc = light.light.borrow();
 
         let shader_light = FShaderLight::new_from_component(
             light_desc.deref(),
-            &transform_ref.position,
-            &transform_ref.rotation,
+            &transform.get_world_position(),
+            &transform.get_world_rotation(),
         );
-        self.lights.push(shader_light);
-    }
-
-    fn get_interested_components(&self) -> &'static [&'static [u32]] {
-        &[&[COMPONENT_ID_TRANSFORM, COMPONENT_ID_LIGHT]]
+        context.space.send_event(FLightEvent {
+            light: shader_light,
+        });
     }
 }
@@ -1,11 +1,21 @@
 mod camera;
+mod executor;
 mod graphics;
 mod lighting;
+mod query;
 mod rotating;
+mod schedule;
+mod scripted;
 mod traits;
+mod transform;
 
 pub use camera::*;
+pub use executor::*;
 pub use graphics::*;
 pub use lighting::*;
+pub use query::*;
 pub use rotating::*;
+pub use schedule::*;
+pub use scripted::*;
 pub use traits::*;
+pub use transform::*;
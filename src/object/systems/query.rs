@@ -0,0 +1,403 @@
+// 带类型的查询：system 声明 Query<(&HTransformComponent, &mut HStaticModelComponent)>，
+// tick 时直接拿到 component 的引用，不需要自己 downcast。借用在执行 system 之前按整列做好
+// &mut T 拿到的是 Mut<T>，只有真正修改时才会被 Changed<T> 筛选到
+// Added<T> / Changed<T> / Removed<T> 只筛选 entity，对应的 item 是 ()
+// despawn 的 entity 不在任何查询里，通过 RemovedComponents<T> 得知
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::object::{
+    archetype::{EChangeFilter, FComponentAccess, FComponentRef, FComponentTicks, FQueryDesc},
+    components::{component_id, TComponent},
+    entity::FEntityId,
+};
+
+// 查询里的一项：&T、&mut T、Option<&T>、Option<&mut T>，或者变化筛选
+// ticks 和 component 对应，change_tick 是这次执行 system 的 tick
+pub trait TQueryParam {
+    type Item<'a>;
+
+    fn access() -> FComponentAccess;
+    fn fetch<'a>(
+        component: Option<&'a mut FComponentRef<'_>>,
+        ticks: Option<&'a FComponentTicks>,
+        change_tick: u32,
+    ) -> Result<Self::Item<'a>, String>;
+}
+
+// 可写的 component。通过 DerefMut 访问时才记录修改，只读不会触发 Changed<T>
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    ticks: &'a FComponentTicks,
+    change_tick: u32,
+}
+
+impl<'a, T> Deref for Mut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> DerefMut for Mut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.set_changed(self.change_tick);
+        self.value
+    }
+}
+
+fn fetch_component<'a, T: TComponent>(component: &'a FComponentRef<'_>) -> Result<&'a T, String> {
+    component
+        .as_any()
+        .downcast_ref::<T>()
+        .ok_or(format!("component is not a {}", T::type_name()))
+}
+
+fn fetch_component_mut<'a, T: TComponent>(
+    component: &'a mut FComponentRef<'_>,
+    ticks: Option<&'a FComponentTicks>,
+    change_tick: u32,
+) -> Result<Mut<'a, T>, String> {
+    let ticks = ticks.ok_or(format!("missing change ticks of {}", T::type_name()))?;
+    let value = component
+        .as_any_mut()
+        .ok_or(format!("{} is borrowed as read-only", T::type_name()))?
+        .downcast_mut::<T>()
+        .ok_or(format!("component is not a {}", T::type_name()))?;
+    Ok(Mut {
+        value,
+        ticks,
+        change_tick,
+    })
+}
+
+fn required<'a, 'b, T: TComponent>(
+    component: Option<&'a mut FComponentRef<'b>>,
+) -> Result<&'a mut FComponentRef<'b>, String> {
+    component.ok_or(format!("missing required component {}", T::type_name()))
+}
+
+impl<T: TComponent> TQueryParam for &T {
+    type Item<'a> = &'a T;
+
+    fn access() -> FComponentAccess {
+        FComponentAccess {
+            component_id: component_id::<T>(),
+            write: false,
+            optional: false,
+            filter: None,
+        }
+    }
+
+    fn fetch<'a>(
+        component: Option<&'a mut FComponentRef<'_>>,
+        _ticks: Option<&'a FComponentTicks>,
+        _change_tick: u32,
+    ) -> Result<Self::Item<'a>, String> {
+        fetch_component(required::<T>(component)?)
+    }
+}
+
+impl<T: TComponent> TQueryParam for &mut T {
+    type Item<'a> = Mut<'a, T>;
+
+    fn access() -> FComponentAccess {
+        FComponentAccess {
+            component_id: component_id::<T>(),
+            write: true,
+            optional: false,
+            filter: None,
+        }
+    }
+
+    fn fetch<'a>(
+        component: Option<&'a mut FComponentRef<'_>>,
+        ticks: Option<&'a FComponentTicks>,
+        change_tick: u32,
+    ) -> Result<Self::Item<'a>, String> {
+        fetch_component_mut(required::<T>(component)?, ticks, change_tick)
+    }
+}
+
+impl<T: TComponent> TQueryParam for Option<&T> {
+    type Item<'a> = Option<&'a T>;
+
+    fn access() -> FComponentAccess {
+        FComponentAccess {
+            optional: true,
+            ..<&T as TQueryParam>::access()
+        }
+    }
+
+    fn fetch<'a>(
+        component: Option<&'a mut FComponentRef<'_>>,
+        _ticks: Option<&'a FComponentTicks>,
+        _change_tick: u32,
+    ) -> Result<Self::Item<'a>, String> {
+        component
+            .map(|component| fetch_component(component))
+            .transpose()
+    }
+}
+
+impl<T: TComponent> TQueryParam for Option<&mut T> {
+    type Item<'a> = Option<Mut<'a, T>>;
+
+    fn access() -> FComponentAccess {
+        FComponentAccess {
+            optional: true,
+            ..<&mut T as TQueryParam>::access()
+        }
+    }
+
+    fn fetch<'a>(
+        component: Option<&'a mut FComponentRef<'_>>,
+        ticks: Option<&'a FComponentTicks>,
+        change_tick: u32,
+    ) -> Result<Self::Item<'a>, String> {
+        component
+            .map(|component| fetch_component_mut(component, ticks, change_tick))
+            .transpose()
+    }
+}
+
+// 上次执行之后加入的 T，替换也算加入
+pub struct Added<T: TComponent>(PhantomData<T>);
+
+// 上次执行之后加入或者通过 Mut 修改过的 T
+pub struct Changed<T: TComponent>(PhantomData<T>);
+
+// 上次执行之后被移除了 T、但 entity 还在。被 despawn 的 entity 不会再被遍历到，要用 RemovedComponents<T>
+pub struct Removed<T: TComponent>(PhantomData<T>);
+
+macro_rules! impl_change_filter {
+    ($name:ident, $filter:ident, $optional:expr) => {
+        impl<T: TComponent> TQueryParam for $name<T> {
+            type Item<'a> = ();
+
+            fn access() -> FComponentAccess {
+                FComponentAccess {
+                    component_id: component_id::<T>(),
+                    write: false,
+                    optional: $optional,
+                    filter: Some(EChangeFilter::$filter),
+                }
+            }
+
+            fn fetch<'a>(
+                _component: Option<&'a mut FComponentRef<'_>>,
+                _ticks: Option<&'a FComponentTicks>,
+                _change_tick: u32,
+            ) -> Result<Self::Item<'a>, String> {
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_change_filter!(Added, Added, false);
+impl_change_filter!(Changed, Changed, false);
+// 被移除后 entity 已经没有 T，不能作为匹配条件
+impl_change_filter!(Removed, Removed, true);
+
+// system 上次执行之后被移除了 T 的 entity，包括被 despawn 的。由 HSpace::removed_components 得到
+pub struct RemovedComponents<T: TComponent> {
+    entity_ids: Vec<FEntityId>,
+    marker: PhantomData<T>,
+}
+
+impl<T: TComponent> RemovedComponents<T> {
+    pub fn new(entity_ids: Vec<FEntityId>) -> Self {
+        Self {
+            entity_ids,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = FEntityId> + '_ {
+        self.entity_ids.iter().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entity_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entity_ids.is_empty()
+    }
+}
+
+impl<T: TComponent> IntoIterator for RemovedComponents<T> {
+    type Item = FEntityId;
+    type IntoIter = std::vec::IntoIter<FEntityId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entity_ids.into_iter()
+    }
+}
+
+// 查询的整体，是 TQueryParam 组成的 tuple
+pub trait TQueryData {
+    type Item<'a>;
+
+    fn desc() -> FQueryDesc;
+    fn fetch<'a>(
+        components: &'a mut [Option<FComponentRef<'_>>],
+        ticks: &'a [Option<&'a FComponentTicks>],
+        change_tick: u32,
+    ) -> Result<Self::Item<'a>, String>;
+}
+
+macro_rules! impl_query_data {
+    ($($param:ident $index:tt),*) => {
+        impl<$($param: TQueryParam),*> TQueryData for ($($param,)*) {
+            type Item<'a> = ($($param::Item<'a>,)*);
+
+            fn desc() -> FQueryDesc {
+                FQueryDesc {
+                    components: vec![$($param::access()),*],
+                }
+            }
+
+            // 每一项从 components 里拿走各自的 &mut
+            fn fetch<'a>(
+                components: &'a mut [Option<FComponentRef<'_>>],
+                ticks: &'a [Option<&'a FComponentTicks>],
+                change_tick: u32,
+            ) -> Result<Self::Item<'a>, String> {
+                let mut components = components.iter_mut();
+                Ok(($($param::fetch(
+                    components.next().and_then(Option::as_mut),
+                    ticks[$index],
+                    change_tick,
+                )?,)*))
+            }
+        }
+    };
+}
+
+impl_query_data!(A 0);
+impl_query_data!(A 0, B 1);
+impl_query_data!(A 0, B 1, C 2);
+impl_query_data!(A 0, B 1, C 2, D 3);
+impl_query_data!(A 0, B 1, C 2, D 3, E 4);
+impl_query_data!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+pub struct Query<D: TQueryData>(PhantomData<D>);
+
+impl<D: TQueryData> Query<D> {
+    pub fn desc() -> FQueryDesc {
+        D::desc()
+    }
+
+    pub fn fetch<'a>(
+        components: &'a mut [Option<FComponentRef<'_>>],
+        ticks: &'a [Option<&'a FComponentTicks>],
+        change_tick: u32,
+    ) -> Result<D::Item<'a>, String> {
+        D::fetch(components, ticks, change_tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FHealth(i32);
+    struct FArmor(i32);
+
+    impl TComponent for FHealth {
+        fn type_name() -> &'static str {
+            "FHealth"
+        }
+    }
+
+    impl TComponent for FArmor {
+        fn type_name() -> &'static str {
+            "FArmor"
+        }
+    }
+
+    #[test]
+    fn desc_follows_the_tuple() {
+        let desc = Query::<(&mut FHealth, Option<&FArmor>, Removed<FArmor>)>::desc();
+        let accesses: Vec<(u32, bool, bool, Option<EChangeFilter>)> = desc
+            .components
+            .iter()
+            .map(|access| {
+                (
+                    access.component_id,
+                    access.write,
+                    access.optional,
+                    access.filter,
+                )
+            })
+            .collect();
+        let health = component_id::<FHealth>();
+        let armor = component_id::<FArmor>();
+        assert_eq!(
+            accesses,
+            vec![
+                (health, true, false, None),
+                (armor, false, true, None),
+                (armor, false, true, Some(EChangeFilter::Removed)),
+            ]
+        );
+        assert!(desc.validate().is_ok());
+        assert!(!Query::<(&FHealth, Changed<FHealth>)>::desc().components[1].optional);
+    }
+
+    #[test]
+    fn mut_marks_changed_only_on_write() {
+        let mut health = FHealth(10);
+        let ticks = FComponentTicks::new(1);
+        let row_ticks = vec![Some(&ticks), None];
+
+        {
+            let mut components = vec![Some(FComponentRef::Write(&mut health)), None];
+            let (health, armor) =
+                Query::<(&mut FHealth, Option<&FArmor>)>::fetch(&mut components, &row_ticks, 5)
+                    .unwrap();
+            assert_eq!(health.0, 10);
+            assert!(armor.is_none());
+        }
+        assert_eq!(ticks.get_changed(), 1);
+
+        {
+            let mut components = vec![Some(FComponentRef::Write(&mut health))];
+            let (mut health,) =
+                Query::<(&mut FHealth,)>::fetch(&mut components, &row_ticks, 6).unwrap();
+            health.0 -= 1;
+        }
+        assert_eq!(ticks.get_changed(), 6);
+        assert_eq!(ticks.get_added(), 1);
+        let mut components = vec![Some(FComponentRef::Read(&health))];
+        let (health,) = Query::<(&FHealth,)>::fetch(&mut components, &row_ticks, 7).unwrap();
+        assert_eq!(health.0, 9);
+    }
+
+    #[test]
+    fn fetch_reports_missing_and_read_only_components() {
+        let mut components = vec![None];
+        let err = Query::<(&FHealth,)>::fetch(&mut components, &[None], 1)
+            .err()
+            .unwrap();
+        assert_eq!(err, "missing required component FHealth");
+
+        let health = FHealth(1);
+        let mut components = vec![Some(FComponentRef::Read(&health))];
+        let ticks = FComponentTicks::new(1);
+        let err = Query::<(&mut FHealth,)>::fetch(&mut components, &[Some(&ticks)], 2)
+            .err()
+            .unwrap();
+        assert_eq!(err, "FHealth is borrowed as read-only");
+        let err = Query::<(&FArmor,)>::fetch(&mut components, &[None], 2)
+            .err()
+            .unwrap();
+        assert_eq!(err, "component is not a FArmor");
+    }
+}
@@ -1,38 +1,36 @@
-use std::any::Any;
-
-use hoo_object::RcObject;
-
 use crate::object::{components::*, space::HSpace};
 
-use super::FSystemTickContext;
+use super::{FSystemTickContext, QueryItem, TQuerySystem};
+
+// 弧度每秒。原来是每帧 0.001，按 60 帧换算
+const ANGULAR_SPEED: f64 = 0.06;
 
 pub struct HRotatingSystem {
-    counter: i64,
+    angle: f64,
 }
 
 impl HRotatingSystem {
     pub fn new() -> Self {
-        Self { counter: 0 }
+        Self { angle: 0.0 }
     }
 }
 
-impl super::traits::TSystem for HRotatingSystem {
-    fn begin_frame(&mut self, _space: &HSpace) {
-        self.counter += 1;
-    }
-
-    fn tick_entity(&mut self, context: FSystemTickContext) {
-        let transform: RcObject<HTransformComponent> =
-            context.components[0].clone().try_downcast().unwrap();
-
-        let mut transform_ref = transform.borrow_mut();
+impl TQuerySystem for HRotatingSystem {
+    type Query = (
+        &'static mut HTransformComponent,
+        &'static HStaticModelComponent,
+    );
 
-        let angle = self.counter as f32 * 0.001;
-        transform_ref.rotation =
-            nalgebra_glm::quat_angle_axis(angle, &nalgebra_glm::vec3(0.0, 0.0, 1.0));
+    fn before_first_tick(&mut self, _space: &HSpace, delta_time: f64) {
+        self.angle += delta_time * ANGULAR_SPEED;
     }
 
-    fn get_interested_components(&self) -> &'static [&'static [u32]] {
-        &[&[COMPONENT_ID_TRANSFORM, COMPONENT_ID_STATIC_MODEL]]
+    fn tick(&mut self, _context: &FSystemTickContext, (mut transform, _): QueryItem<'_, Self>) {
+        let angle = self.angle as f32;
+        let rotation = nalgebra_glm::quat_angle_axis(angle, &nalgebra_glm::vec3(0.0, 0.0, 1.0));
+        // 暂停时角度不变，不要触发 Changed
+        if transform.rotation != rotation {
+            transform.rotation = rotation;
+        }
     }
 }
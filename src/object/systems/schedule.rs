@@ -0,0 +1,400 @@
+// system 的执行顺序：先按 stage，同一个 stage 里按 before / after 约束排序，没有约束的保持加入顺序
+// 约束用 system 的名字（TSystem::get_name）指代，引用的 system 还没加入时约束暂不生效
+
+use std::{cell::Cell, rc::Rc};
+
+use hoo_object::RcTrait;
+
+use crate::object::space::HSpace;
+
+use super::{FSystemAccess, TSystem};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EStage {
+    // 固定步长的 fixed_tick 在 PreUpdate 之后、Update 之前执行
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl EStage {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            EStage::PreUpdate => "PreUpdate",
+            EStage::Update => "Update",
+            EStage::PostUpdate => "PostUpdate",
+            EStage::Render => "Render",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "PreUpdate" => Some(EStage::PreUpdate),
+            "Update" => Some(EStage::Update),
+            "PostUpdate" => Some(EStage::PostUpdate),
+            "Render" => Some(EStage::Render),
+            _ => None,
+        }
+    }
+}
+
+impl Default for EStage {
+    fn default() -> Self {
+        EStage::Update
+    }
+}
+
+// 每帧开始时检查一次，返回 false 的 system 这一帧整个跳过
+pub type FRunCondition = Box<dyn Fn(&HSpace) -> bool>;
+
+#[derive(Default)]
+pub struct FSystemDesc {
+    pub stage: EStage,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    pub run_condition: Option<FRunCondition>,
+}
+
+impl FSystemDesc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn in_stage(mut self, stage: EStage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(name.to_string());
+        self
+    }
+
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+
+    pub fn run_if(mut self, condition: impl Fn(&HSpace) -> bool + 'static) -> Self {
+        self.run_condition = Some(Box::new(condition));
+        self
+    }
+}
+
+pub struct FScheduledSystem {
+    pub system: RcTrait<dyn TSystem>,
+    pub name: String,
+    pub desc: FSystemDesc,
+    // 上次执行时的 change tick，Added / Changed / Removed 筛选和它比较
+    pub last_run_tick: Rc<Cell<u32>>,
+    // fixed_tick 单独记录，和 tick 各自看到对方的修改
+    pub last_fixed_run_tick: Rc<Cell<u32>>,
+    // 加入时从 TSystem::get_access / has_fixed_tick 取得
+    pub access: FSystemAccess,
+    pub has_fixed_tick: bool,
+}
+
+impl FScheduledSystem {
+    pub fn should_run(&self, space: &HSpace) -> bool {
+        match self.desc.run_condition.as_ref() {
+            Some(condition) => condition(space),
+            None => true,
+        }
+    }
+
+    // 任意一方用 before / after 指定了和另一方的顺序
+    pub fn is_ordered_with(&self, other: &FScheduledSystem) -> bool {
+        let refers_to = |system: &FScheduledSystem, name: &String| {
+            system.desc.before.contains(name) || system.desc.after.contains(name)
+        };
+        refers_to(self, &other.name) || refers_to(other, &self.name)
+    }
+}
+
+pub struct FSchedule {
+    // 加入顺序
+    systems: Vec<FScheduledSystem>,
+    // 执行顺序，是 systems 的下标
+    order: Vec<usize>,
+}
+
+impl FSchedule {
+    pub fn new() -> Self {
+        Self {
+            systems: vec![],
+            order: vec![],
+        }
+    }
+
+    // 加入后重新排序。约束无法满足时不加入
+    pub fn add(
+        &mut self,
+        system: RcTrait<dyn TSystem>,
+        name: String,
+        desc: FSystemDesc,
+    ) -> Result<(), String> {
+        let access = system.borrow().get_access();
+        let has_fixed_tick = system.borrow().has_fixed_tick();
+        self.systems.push(FScheduledSystem {
+            system,
+            name,
+            desc,
+            last_run_tick: Rc::new(Cell::new(0)),
+            last_fixed_run_tick: Rc::new(Cell::new(0)),
+            access,
+            has_fixed_tick,
+        });
+        match self.sort() {
+            Ok(order) => {
+                self.order = order;
+                Ok(())
+            }
+            Err(err) => {
+                self.systems.pop();
+                Err(err)
+            }
+        }
+    }
+
+    // 按执行顺序
+    pub fn iter(&self) -> impl Iterator<Item = &FScheduledSystem> + '_ {
+        self.order.iter().map(move |index| &self.systems[*index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    // 比它早的移除记录所有 system 都已经看过了
+    pub fn get_min_last_run_tick(&self) -> u32 {
+        self.systems
+            .iter()
+            .map(|system| match system.has_fixed_tick {
+                true => system
+                    .last_run_tick
+                    .get()
+                    .min(system.last_fixed_run_tick.get()),
+                false => system.last_run_tick.get(),
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn get_order(&self) -> Vec<(EStage, String)> {
+        self.iter()
+            .map(|system| (system.desc.stage, system.name.clone()))
+            .collect()
+    }
+
+    // 同一个 stage 里 from 必须在 to 之前；跨 stage 的约束只检查是否和 stage 顺序矛盾
+    fn get_edges(&self) -> Result<Vec<(usize, usize)>, String> {
+        let mut edges = vec![];
+        for (index, system) in self.systems.iter().enumerate() {
+            let afters = system.desc.after.iter().map(|name| (name, false));
+            let befores = system.desc.before.iter().map(|name| (name, true));
+            for (name, before) in afters.chain(befores) {
+                for (other, _) in self
+                    .systems
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, other_system)| *other != index && other_system.name == *name)
+                {
+                    let (from, to) = if before {
+                        (index, other)
+                    } else {
+                        (other, index)
+                    };
+                    let (from_stage, to_stage) =
+                        (self.systems[from].desc.stage, self.systems[to].desc.stage);
+                    if from_stage > to_stage {
+                        return Err(format!(
+                            "{} in {} cannot run before {} in {}",
+                            self.systems[from].name,
+                            from_stage.get_name(),
+                            self.systems[to].name,
+                            to_stage.get_name()
+                        ));
+                    }
+                    if from_stage == to_stage {
+                        edges.push((from, to));
+                    }
+                }
+            }
+        }
+        Ok(edges)
+    }
+
+    fn sort(&self) -> Result<Vec<usize>, String> {
+        let count = self.systems.len();
+        let mut predecessors = vec![vec![]; count];
+        for (from, to) in self.get_edges()? {
+            predecessors[to].push(from);
+        }
+
+        let mut order = vec![];
+        let mut scheduled = vec![false; count];
+        while order.len() < count {
+            // 前驱都已经排好的 system 里，选 stage 最靠前、加入最早的
+            let next = (0..count)
+                .filter(|index| !scheduled[*index])
+                .filter(|index| predecessors[*index].iter().all(|from| scheduled[*from]))
+                .min_by_key(|index| (self.systems[*index].desc.stage, *index));
+            match next {
+                Some(index) => {
+                    scheduled[index] = true;
+                    order.push(index);
+                }
+                None => return Err(self.describe_cycle(&predecessors, &scheduled)),
+            }
+        }
+        Ok(order)
+    }
+
+    // 剩下的 system 都有未排好的前驱，沿着前驱走一定会回到走过的 system
+    fn describe_cycle(&self, predecessors: &[Vec<usize>], scheduled: &[bool]) -> String {
+        let mut path = vec![];
+        let mut current = (0..scheduled.len())
+            .find(|index| !scheduled[*index])
+            .unwrap();
+        while !path.contains(&current) {
+            path.push(current);
+            current = *predecessors[current]
+                .iter()
+                .find(|from| !scheduled[**from])
+                .unwrap();
+        }
+        let start = path.iter().position(|index| *index == current).unwrap();
+        // path 是逆着执行顺序走的，反过来再接上起点
+        let cycle: Vec<&str> = std::iter::once(&current)
+            .chain(path[start..].iter().rev())
+            .map(|index| self.systems[*index].name.as_str())
+            .collect();
+        format!("systems form a cycle: {}", cycle.join(" -> "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hoo_object::{into_trait, RcObject, RcTrait};
+
+    use super::*;
+    use crate::object::{archetype::FQueryDesc, systems::FSystemTickContext};
+
+    struct FEmptySystem;
+
+    impl TSystem for FEmptySystem {
+        fn get_queries(&self) -> Vec<FQueryDesc> {
+            vec![]
+        }
+
+        fn tick_entity(&mut self, _context: FSystemTickContext) {}
+    }
+
+    fn add(schedule: &mut FSchedule, name: &str, desc: FSystemDesc) -> Result<(), String> {
+        let system = RcObject::new(FEmptySystem);
+        let system: RcTrait<dyn TSystem> = into_trait!(system);
+        schedule.add(system, name.to_string(), desc)
+    }
+
+    fn get_names(schedule: &FSchedule) -> Vec<String> {
+        schedule.iter().map(|system| system.name.clone()).collect()
+    }
+
+    #[test]
+    fn systems_are_ordered_by_stage_then_constraints() {
+        let mut schedule = FSchedule::new();
+        add(
+            &mut schedule,
+            "Draw",
+            FSystemDesc::new().in_stage(EStage::Render),
+        )
+        .unwrap();
+        add(&mut schedule, "Move", FSystemDesc::new().after("Input")).unwrap();
+        add(&mut schedule, "Input", FSystemDesc::new()).unwrap();
+        add(
+            &mut schedule,
+            "Time",
+            FSystemDesc::new().in_stage(EStage::PreUpdate),
+        )
+        .unwrap();
+        add(&mut schedule, "Spawn", FSystemDesc::new().before("Move")).unwrap();
+        add(&mut schedule, "Audio", FSystemDesc::new()).unwrap();
+
+        // 没有约束的保持加入顺序
+        assert_eq!(
+            get_names(&schedule),
+            ["Time", "Input", "Spawn", "Move", "Audio", "Draw"]
+        );
+        assert_eq!(
+            schedule.get_order()[0],
+            (EStage::PreUpdate, "Time".to_string())
+        );
+        assert_eq!(schedule.len(), 6);
+    }
+
+    #[test]
+    fn constraints_on_missing_systems_take_effect_once_added() {
+        let mut schedule = FSchedule::new();
+        add(&mut schedule, "Physics", FSystemDesc::new().after("Input")).unwrap();
+        add(&mut schedule, "Camera", FSystemDesc::new()).unwrap();
+        assert_eq!(get_names(&schedule), ["Physics", "Camera"]);
+
+        add(&mut schedule, "Input", FSystemDesc::new()).unwrap();
+        assert_eq!(get_names(&schedule), ["Camera", "Input", "Physics"]);
+    }
+
+    #[test]
+    fn contradicting_stage_constraint_is_rejected() {
+        let mut schedule = FSchedule::new();
+        add(&mut schedule, "Move", FSystemDesc::new()).unwrap();
+        let err = add(
+            &mut schedule,
+            "Draw",
+            FSystemDesc::new().in_stage(EStage::Render).before("Move"),
+        )
+        .unwrap_err();
+        assert_eq!(err, "Draw in Render cannot run before Move in Update");
+        assert_eq!(get_names(&schedule), ["Move"]);
+
+        // 和 stage 顺序一致的约束不需要额外的边
+        add(
+            &mut schedule,
+            "Draw",
+            FSystemDesc::new().in_stage(EStage::Render).after("Move"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn cycle_is_rejected_and_described() {
+        let mut schedule = FSchedule::new();
+        add(&mut schedule, "A", FSystemDesc::new().before("B")).unwrap();
+        add(&mut schedule, "B", FSystemDesc::new().before("C")).unwrap();
+        let err = add(&mut schedule, "C", FSystemDesc::new().before("A")).unwrap_err();
+        assert_eq!(err, "systems form a cycle: A -> B -> C -> A");
+
+        // 出错的 system 不会加入，之前的顺序保持不变
+        assert_eq!(get_names(&schedule), ["A", "B"]);
+        add(&mut schedule, "C", FSystemDesc::new().after("B")).unwrap();
+        assert_eq!(get_names(&schedule), ["A", "B", "C"]);
+    }
+
+    #[test]
+    fn stage_names_round_trip() {
+        for stage in [
+            EStage::PreUpdate,
+            EStage::Update,
+            EStage::PostUpdate,
+            EStage::Render,
+        ] {
+            assert_eq!(EStage::from_name(stage.get_name()), Some(stage));
+        }
+        assert_eq!(EStage::from_name("Fixed"), None);
+        assert_eq!(EStage::default(), EStage::Update);
+    }
+}
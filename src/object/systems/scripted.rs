@@ -0,0 +1,373 @@
+// 用 js 对象实现的 system：
+// {
+//     name: "Spin",
+//     components: [["HTransformComponent", "Health"]],
+//     stage: "Update", // 可选，PreUpdate / Update / PostUpdate / Render
+//     before: [], after: ["Physics"], // 可选，其他 system 的名字
+//     beginFrame() {},
+//     tickEntity(ctx) {}, // ctx: { deltaTime, entityId, group, components }
+//     fixedTickEntity(ctx) {}, // 每个固定步长一次，deltaTime 是固定步长
+//     endFrame() {},
+// }
+// 这些方法都是可选的。异常只会记录在这个 system 上，不会打断这一帧
+// ctx.components 是 component 的拷贝，tickEntity 返回后写回 space
+
+use std::{
+    any::Any,
+    rc::{Rc, Weak},
+};
+
+use hoo_meta::{GetJsValue, JsException, JsRuntimeError, TryFromJsValue, TryFromJsValueError};
+use hoo_object::{RcAny, RcObject};
+
+use crate::object::{
+    archetype::{FComponentAccess, FQueryDesc},
+    components::*,
+    space::HSpace,
+};
+
+use super::{EStage, FSystemAccess, FSystemDesc, FSystemTickContext};
+
+pub struct HScriptSystem {
+    name: String,
+    queries: Vec<FQueryDesc>,
+    stage: EStage,
+    before: Vec<String>,
+    after: Vec<String>,
+    // 创建时有没有 fixedTickEntity 方法
+    has_fixed_tick: bool,
+    errors: Vec<String>,
+
+    // isolate_alive 还能 upgrade 时才能解引用。space 可能比 isolate 活得更久（比如脚本退出后引擎继续运行）
+    isolate: *mut v8::Isolate,
+    isolate_alive: Weak<()>,
+    context: v8::Global<v8::Context>,
+    object: v8::Global<v8::Object>,
+}
+
+impl HScriptSystem {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_desc(&self) -> FSystemDesc {
+        FSystemDesc {
+            stage: self.stage,
+            before: self.before.clone(),
+            after: self.after.clone(),
+            run_condition: None,
+        }
+    }
+
+    // 取出到目前为止脚本抛出的异常
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn report_error(&mut self, method_name: &str, message: &str) {
+        let error = format!(
+            "script system {} threw in {}: {}",
+            self.name, method_name, message
+        );
+        self.errors.push(error);
+    }
+
+    fn call_method(
+        &mut self,
+        method_name: &str,
+        tick_context: Option<(&FSystemTickContext, &[Option<RcAny>])>,
+    ) {
+        // isolate 没了之后只报告一次
+        if self.isolate_alive.upgrade().is_none() {
+            if !self.isolate.is_null() {
+                self.isolate = std::ptr::null_mut();
+                self.report_error(method_name, "isolate has been disposed");
+            }
+            return;
+        }
+        // tick 可能发生在 js 回调内部（比如脚本调用 hoo.tick），也可能不在，
+        // 所以用 CallbackScope 重新进入 isolate
+        let isolate = unsafe { &mut *self.isolate };
+        let scope = &mut unsafe { v8::CallbackScope::new(isolate) };
+        let scope = &mut v8::HandleScope::new(scope);
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        let scope = &mut v8::TryCatch::new(scope);
+
+        let object = v8::Local::new(scope, &self.object);
+        let key = v8::String::new(scope, method_name).unwrap();
+        let method = object
+            .get(scope, key.into())
+            .and_then(|method| v8::Local::<v8::Function>::try_from(method).ok());
+        let method = match method {
+            Some(method) => method,
+            None => return,
+        };
+
+        let mut args = vec![];
+        if let Some((tick_context, components)) = tick_context {
+            match create_tick_context_object(scope, tick_context, components) {
+                Ok(arg) => args.push(arg),
+                Err(err) => {
+                    self.report_error(method_name, &err.error_message());
+                    return;
+                }
+            }
+        }
+
+        if method.call(scope, object.into(), &args).is_none() {
+            let err = JsRuntimeError::from_try_catch(scope);
+            self.report_error(method_name, &err.to_string());
+        }
+    }
+
+    // 检测不到脚本有没有修改，写回的 component 都算作修改过
+    fn call_tick_method(&mut self, method_name: &str, mut context: FSystemTickContext) {
+        let copies = match copy_components(&context) {
+            Ok(copies) => copies,
+            Err(err) => {
+                self.report_error(method_name, &err);
+                return;
+            }
+        };
+        self.call_method(method_name, Some((&context, copies.as_slice())));
+
+        let mut components = std::mem::take(&mut context.components);
+        for (index, (component, copy)) in components.iter_mut().zip(copies.iter()).enumerate() {
+            if let (Some(component), Some(copy)) = (component, copy) {
+                let written = component
+                    .as_any_mut()
+                    .map_or(false, |component| write_back_component(copy, component));
+                if written {
+                    context.mark_changed(index);
+                }
+            }
+        }
+    }
+}
+
+// 放在 isolate 的 slot 里，isolate 销毁时一起释放，script system 和 FScriptInspector 持有它的 Weak
+struct FIsolateAlive(Rc<()>);
+
+pub(crate) fn get_isolate_alive(isolate: &mut v8::Isolate) -> Weak<()> {
+    if isolate.get_slot::<FIsolateAlive>().is_none() {
+        isolate.set_slot(FIsolateAlive(Rc::new(())));
+    }
+    Rc::downgrade(&isolate.get_slot::<FIsolateAlive>().unwrap().0)
+}
+
+// 交给脚本的拷贝。脚本 system 的查询都是必需的 component，不会是 None
+fn copy_components(context: &FSystemTickContext) -> Result<Vec<Option<RcAny>>, String> {
+    fn copy<T: Clone + 'static>(component: &dyn Any) -> Option<RcAny> {
+        component
+            .downcast_ref::<T>()
+            .map(|component| RcObject::new(component.clone()).into_any())
+    }
+
+    let mut copies = vec![];
+    for component in context.components.iter() {
+        let component = match component {
+            Some(component) => component.as_any(),
+            None => {
+                copies.push(None);
+                continue;
+            }
+        };
+        let copied = copy::<HTransformComponent>(component)
+            .or_else(|| copy::<HLightComponent>(component))
+            .or_else(|| copy::<HCameraComponent>(component))
+            .or_else(|| copy::<HStaticModelComponent>(component))
+            .or_else(|| copy::<HScriptComponent>(component))
+            .ok_or("component type is not exposed to scripts")?;
+        copies.push(Some(copied));
+    }
+    Ok(copies)
+}
+
+fn write_back_component(copy: &RcAny, component: &mut dyn Any) -> bool {
+    fn write_back<T: Clone + 'static>(copy: &dyn Any, component: &mut dyn Any) -> bool {
+        match (copy.downcast_ref::<T>(), component.downcast_mut::<T>()) {
+            (Some(copy), Some(component)) => {
+                *component = copy.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    let copy = copy.borrow();
+    let copy: &dyn Any = &*copy;
+    write_back::<HTransformComponent>(copy, component)
+        || write_back::<HLightComponent>(copy, component)
+        || write_back::<HCameraComponent>(copy, component)
+        || write_back::<HStaticModelComponent>(copy, component)
+        || write_back::<HScriptComponent>(copy, component)
+}
+
+fn component_to_js_value<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    component: &RcAny,
+) -> Result<v8::Local<'a, v8::Value>, JsException> {
+    if let Ok(c) = component.clone().try_downcast::<HTransformComponent>() {
+        return c.get_js_value(scope);
+    }
+    if let Ok(c) = component.clone().try_downcast::<HLightComponent>() {
+        return c.get_js_value(scope);
+    }
+    if let Ok(c) = component.clone().try_downcast::<HCameraComponent>() {
+        return c.get_js_value(scope);
+    }
+    if let Ok(c) = component.clone().try_downcast::<HStaticModelComponent>() {
+        return c.get_js_value(scope);
+    }
+    if let Ok(c) = component.clone().try_downcast::<HScriptComponent>() {
+        return c.get_js_value(scope);
+    }
+    Err(JsException::new("component type is not exposed to scripts"))
+}
+
+fn create_tick_context_object<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    tick_context: &FSystemTickContext,
+    copies: &[Option<RcAny>],
+) -> Result<v8::Local<'a, v8::Value>, JsException> {
+    let mut components = vec![];
+    for component in copies.iter() {
+        match component {
+            Some(component) => components.push(component_to_js_value(scope, component)?),
+            None => components.push(v8::null(scope).into()),
+        }
+    }
+    let components = v8::Array::new_with_elements(scope, &components);
+
+    let fields = [
+        ("deltaTime", tick_context.delta_time.get_js_value(scope)?),
+        ("entityId", tick_context.entity_id.get_js_value(scope)?),
+        ("group", (tick_context.group as u32).get_js_value(scope)?),
+        ("components", components.into()),
+    ];
+
+    let object = v8::Object::new(scope);
+    for (name, value) in fields {
+        let key = v8::String::new(scope, name).unwrap();
+        object.set(scope, key.into(), value);
+    }
+    Ok(object.into())
+}
+
+impl TryFromJsValue for HScriptSystem {
+    fn try_from<'a>(
+        scope: &mut v8::HandleScope<'a>,
+        val: &v8::Local<'a, v8::Value>,
+    ) -> Result<Self, TryFromJsValueError> {
+        if !val.is_object() {
+            return Err(TryFromJsValueError::new("not an object"));
+        }
+        let object = val.to_object(scope).unwrap();
+
+        let key = v8::String::new(scope, "name").unwrap();
+        let name = object.get(scope, key.into()).unwrap();
+        let name: Option<String> = TryFromJsValue::try_from(scope, &name)?;
+
+        let key = v8::String::new(scope, "components").unwrap();
+        let components = object.get(scope, key.into()).unwrap();
+        let components: Vec<Vec<String>> = TryFromJsValue::try_from(scope, &components)?;
+
+        let key = v8::String::new(scope, "stage").unwrap();
+        let stage = object.get(scope, key.into()).unwrap();
+        let stage: Option<String> = TryFromJsValue::try_from(scope, &stage)?;
+        let stage = match stage {
+            Some(stage) => EStage::from_name(&stage).ok_or(TryFromJsValueError::new(&format!(
+                "unknown stage: {}",
+                stage
+            )))?,
+            None => EStage::default(),
+        };
+
+        let key = v8::String::new(scope, "before").unwrap();
+        let before = object.get(scope, key.into()).unwrap();
+        let before: Option<Vec<String>> = TryFromJsValue::try_from(scope, &before)?;
+
+        let key = v8::String::new(scope, "after").unwrap();
+        let after = object.get(scope, key.into()).unwrap();
+        let after: Option<Vec<String>> = TryFromJsValue::try_from(scope, &after)?;
+
+        // 脚本拿到的 component 都可以修改
+        let mut queries = vec![];
+        for group in components.iter() {
+            let mut query = FQueryDesc::default();
+            for type_name in group.iter() {
+                let component_id = get_component_id_by_type_name(type_name).ok_or(
+                    TryFromJsValueError::new(&format!("unknown component type: {}", type_name)),
+                )?;
+                query.components.push(FComponentAccess {
+                    component_id,
+                    write: true,
+                    optional: false,
+                    filter: None,
+                });
+            }
+            queries.push(query);
+        }
+
+        let key = v8::String::new(scope, "fixedTickEntity").unwrap();
+        let has_fixed_tick = object
+            .get(scope, key.into())
+            .map_or(false, |method| method.is_function());
+
+        let context = scope.get_current_context();
+        let context = v8::Global::new(scope, context);
+        let object = v8::Global::new(scope, object);
+        let isolate: &mut v8::Isolate = scope;
+        let isolate_alive = get_isolate_alive(isolate);
+
+        Ok(HScriptSystem {
+            name: name.unwrap_or("ScriptSystem".into()),
+            queries,
+            stage,
+            before: before.unwrap_or_default(),
+            after: after.unwrap_or_default(),
+            has_fixed_tick,
+            errors: vec![],
+            isolate: isolate as *mut v8::Isolate,
+            isolate_alive,
+            context,
+            object,
+        })
+    }
+}
+
+impl super::traits::TSystem for HScriptSystem {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn begin_frame(&mut self, _space: &HSpace) {
+        self.call_method("beginFrame", None);
+    }
+
+    fn tick_entity(&mut self, context: FSystemTickContext) {
+        self.call_tick_method("tickEntity", context);
+    }
+
+    fn has_fixed_tick(&self) -> bool {
+        self.has_fixed_tick
+    }
+
+    fn fixed_tick_entity(&mut self, context: FSystemTickContext) {
+        self.call_tick_method("fixedTickEntity", context);
+    }
+
+    fn end_frame(&mut self, _space: &HSpace) {
+        self.call_method("endFrame", None);
+    }
+
+    fn get_queries(&self) -> Vec<FQueryDesc> {
+        self.queries.clone()
+    }
+
+    // 脚本可以通过 space 访问任何数据
+    fn get_access(&self) -> FSystemAccess {
+        FSystemAccess::exclusive()
+    }
+}
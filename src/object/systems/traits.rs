@@ -1,21 +1,172 @@
-use hoo_object::RcAny;
+use std::cell::{Ref, RefMut};
 
-use crate::object::space::HSpace;
+use crate::object::{
+    archetype::{FComponentRef, FComponentTicks, FQueryDesc},
+    components::TComponent,
+    entity::FEntityId,
+    space::HSpace,
+};
+
+use super::{FSystemAccess, RemovedComponents, TQueryData};
 
 pub struct FSystemTickContext<'stack> {
     pub space: &'stack HSpace,
     pub delta_time: f64,
     pub group: usize,
-    pub components: Vec<RcAny>,
-    pub entity_id: u32,
+    // 和查询的 component 一一对应，可选的 component 不存在时是 None
+    // 列在执行 system 之前已经整列借用好，写的 component 是 FComponentRef::Write
+    pub components: Vec<Option<FComponentRef<'stack>>>,
+    // 和 components 一一对应，通过 Mut 修改 component 时记录 change tick
+    pub ticks: Vec<Option<&'stack FComponentTicks>>,
+    // 这个 system 这次执行的 tick
+    pub change_tick: u32,
+    pub entity_id: FEntityId,
+}
+
+impl<'stack> FSystemTickContext<'stack> {
+    pub fn resource<T: 'static>(&self) -> Option<Ref<'stack, T>> {
+        self.space.resource::<T>()
+    }
+
+    pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'stack, T>> {
+        self.space.resource_mut::<T>()
+    }
+
+    // 见 HSpace::removed_components
+    pub fn removed_components<T: TComponent>(&self) -> RemovedComponents<T> {
+        self.space.removed_components::<T>()
+    }
+
+    // 没有经过 Mut 修改了第 index 个 component 时（比如脚本），手动标记
+    pub fn mark_changed(&self, index: usize) {
+        if let Some(Some(ticks)) = self.ticks.get(index) {
+            ticks.set_changed(self.change_tick);
+        }
+    }
 }
 
 pub trait TSystem {
     // 也是不太好的抽象。体现不了调用一次后不应该更改的特点。
-    fn get_interested_components(&self) -> &'static [&'static [u32]];
+    // 脚本 system 的查询是运行时决定的，所以不能是 &'static
+    fn get_queries(&self) -> Vec<FQueryDesc>;
+
+    // 执行器据此判断哪些 system 可以同时执行。查询以外还访问了别的数据时要加上
+    fn get_access(&self) -> FSystemAccess {
+        FSystemAccess::from_queries(&self.get_queries())
+    }
+
+    fn get_name(&self) -> String {
+        std::any::type_name::<Self>()
+            .rsplit("::")
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
 
+    // 这几个方法里都不能直接修改 space，spawn / despawn / 增删 component 通过 space.commands() 记录
     fn begin_frame(&mut self, _space: &HSpace) {}
     fn before_first_tick(&mut self, _space: &HSpace, _delta_time: f64) {}
     fn tick_entity(&mut self, context: FSystemTickContext);
+    // 所有 entity 都 tick 过，列的借用已经释放，可以通过 space.get_component_mut 访问别的 entity
+    fn after_last_tick(&mut self, _space: &HSpace) {}
+    fn end_frame(&mut self, _space: &HSpace) {}
+
+    // 固定步长的模拟。has_fixed_tick 返回 true 时，每个固定步长对查询到的 entity 调用一次
+    // fixed_tick_entity，delta time 是固定步长。一帧里可能调用多次或者不调用，在 PreUpdate 之后、Update 之前
+    fn has_fixed_tick(&self) -> bool {
+        false
+    }
+    fn fixed_tick_entity(&mut self, _context: FSystemTickContext) {}
+}
+
+// 只有一个带类型查询的 system。实现这个 trait 就自动实现了 TSystem
+// 比如 type Query = (&'static HTransformComponent, &'static mut HStaticModelComponent);
+pub trait TQuerySystem: 'static {
+    type Query: TQueryData;
+
+    fn get_access(&self) -> FSystemAccess {
+        FSystemAccess::from_queries(&[Self::Query::desc()])
+    }
+
+    fn begin_frame(&mut self, _space: &HSpace) {}
+    fn before_first_tick(&mut self, _space: &HSpace, _delta_time: f64) {}
+    fn tick(&mut self, context: &FSystemTickContext, components: QueryItem<'_, Self>);
+    // 见 TSystem::after_last_tick
+    fn after_last_tick(&mut self, _space: &HSpace) {}
     fn end_frame(&mut self, _space: &HSpace) {}
+
+    // 见 TSystem::has_fixed_tick
+    fn has_fixed_tick(&self) -> bool {
+        false
+    }
+    fn fixed_tick(&mut self, _context: &FSystemTickContext, _components: QueryItem<'_, Self>) {}
+}
+
+// tick 时拿到的 components，&T 对应 &T，&mut T 对应 Mut<T>
+pub type QueryItem<'a, S> = <<S as TQuerySystem>::Query as TQueryData>::Item<'a>;
+
+impl<S: TQuerySystem> TSystem for S {
+    fn get_queries(&self) -> Vec<FQueryDesc> {
+        vec![S::Query::desc()]
+    }
+
+    fn get_access(&self) -> FSystemAccess {
+        TQuerySystem::get_access(self)
+    }
+
+    fn begin_frame(&mut self, space: &HSpace) {
+        TQuerySystem::begin_frame(self, space);
+    }
+
+    fn before_first_tick(&mut self, space: &HSpace, delta_time: f64) {
+        TQuerySystem::before_first_tick(self, space, delta_time);
+    }
+
+    fn tick_entity(&mut self, mut context: FSystemTickContext) {
+        let mut components = std::mem::take(&mut context.components);
+        let item = fetch_or_report(self, &context, &mut components);
+        if let Some(item) = item {
+            self.tick(&context, item);
+        }
+    }
+
+    fn after_last_tick(&mut self, space: &HSpace) {
+        TQuerySystem::after_last_tick(self, space);
+    }
+
+    fn end_frame(&mut self, space: &HSpace) {
+        TQuerySystem::end_frame(self, space);
+    }
+
+    fn has_fixed_tick(&self) -> bool {
+        TQuerySystem::has_fixed_tick(self)
+    }
+
+    fn fixed_tick_entity(&mut self, mut context: FSystemTickContext) {
+        let mut components = std::mem::take(&mut context.components);
+        let item = fetch_or_report(self, &context, &mut components);
+        if let Some(item) = item {
+            self.fixed_tick(&context, item);
+        }
+    }
+}
+
+// 查询在注册时检查过，这里只可能是 component 的类型或者借用方式不对
+fn fetch_or_report<'a, S: TQuerySystem>(
+    system: &S,
+    context: &'a FSystemTickContext,
+    components: &'a mut [Option<FComponentRef<'_>>],
+) -> Option<QueryItem<'a, S>> {
+    match S::Query::fetch(components, &context.ticks, context.change_tick) {
+        Ok(components) => Some(components),
+        Err(err) => {
+            context.space.report_error(format!(
+                "{} skipped entity {}: {}",
+                TSystem::get_name(system),
+                context.entity_id,
+                err
+            ));
+            None
+        }
+    }
 }
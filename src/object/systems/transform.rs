@@ -0,0 +1,102 @@
+// 从根节点往下计算世界变换。position / rotation / scale 和上次计算时相同、父节点也没变的节点直接跳过
+// 根节点：没有父节点，或者父节点没有 HTransformComponent
+// tick 时查询借用着整列 HTransformComponent，所以 tick 只更新根节点，子节点在 after_last_tick 里逐个借用更新
+
+use std::ops::DerefMut;
+
+use nalgebra_glm as glm;
+
+use crate::object::{components::*, entity::FEntityId, space::HSpace};
+
+use super::{FSystemTickContext, QueryItem, TQuerySystem};
+
+pub struct HTransformSystem {
+    // 根节点的子节点、根节点的世界变换、根节点是否重新计算过
+    pending: Vec<(Vec<FEntityId>, glm::Mat4, bool)>,
+}
+
+impl HTransformSystem {
+    pub fn new() -> Self {
+        Self { pending: vec![] }
+    }
+
+    // 返回这个节点的世界变换是否重新计算过。不需要重新计算时只读，不会触发 Changed
+    fn update_world(
+        transform: &mut impl DerefMut<Target = HTransformComponent>,
+        parent_world: Option<&glm::Mat4>,
+        parent_changed: bool,
+    ) -> bool {
+        let local = (transform.position, transform.rotation, transform.scale);
+        if !parent_changed && transform.world.propagated_local == Some(local) {
+            return false;
+        }
+        let matrix = transform.get_matrix();
+        transform.world.matrix = match parent_world {
+            Some(parent_world) => parent_world * matrix,
+            None => matrix,
+        };
+        transform.world.propagated_local = Some(local);
+        true
+    }
+
+    fn propagate_to_children(
+        space: &HSpace,
+        children: &[FEntityId],
+        parent_world: &glm::Mat4,
+        parent_changed: bool,
+    ) {
+        for child in children.iter() {
+            // 没有 transform 的子节点把它的子节点当成根节点，由它们自己的 tick 处理
+            let (world, changed) = match space.get_component_mut::<HTransformComponent>(*child) {
+                Some(mut transform) => {
+                    let changed =
+                        Self::update_world(&mut transform, Some(parent_world), parent_changed);
+                    (transform.world.matrix, changed)
+                }
+                None => continue,
+            };
+            if changed {
+                space.mark_changed::<HTransformComponent>(*child);
+            }
+            Self::propagate_to_children(space, &space.get_children(*child), &world, changed);
+        }
+    }
+}
+
+impl TQuerySystem for HTransformSystem {
+    type Query = (
+        &'static mut HTransformComponent,
+        Option<&'static HParentComponent>,
+        Option<&'static HChildrenComponent>,
+    );
+
+    fn tick(
+        &mut self,
+        context: &FSystemTickContext,
+        (mut transform, parent, children): QueryItem<'_, Self>,
+    ) {
+        if let Some(parent) = parent {
+            if context
+                .space
+                .has_component::<HTransformComponent>(parent.get_parent())
+            {
+                return;
+            }
+        }
+
+        let changed = Self::update_world(&mut transform, None, false);
+        if let Some(children) = children {
+            self.pending.push((
+                children.get_children().to_vec(),
+                transform.world.matrix,
+                changed,
+            ));
+        }
+    }
+
+    fn after_last_tick(&mut self, space: &HSpace) {
+        for (children, parent_world, parent_changed) in std::mem::take(&mut self.pending) {
+            Self::propagate_to_children(space, &children, &parent_world, parent_changed);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+// 引擎时钟和固定步长。HContext 用 FClock 把真实时间换算成这一帧的 delta time，
+// 每个 space 用 FFixedTimestep 把 delta time 累积成若干个固定步长，结果放在 FTime 资源里
+
+use std::time::Instant;
+
+pub const DEFAULT_FIXED_DELTA_TIME: f64 = 1.0 / 60.0;
+
+// 断点、卡顿之后真实时间可能跳很多，超过的部分丢掉
+const MAX_REAL_DELTA_TIME: f64 = 0.25;
+
+// 一帧里固定步长最多执行的次数，追不上时剩下的时间丢掉
+const MAX_FIXED_STEPS: u32 = 8;
+
+// 一帧的时间，由 FClock 产生，交给 HSpace::tick_frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FFrameTime {
+    // 经过缩放、暂停后的时间，system 用它
+    pub delta_time: f64,
+    pub real_delta_time: f64,
+    pub time_scale: f64,
+    pub paused: bool,
+}
+
+impl FFrameTime {
+    // 不经过时钟，直接指定 delta time（脚本测试、benchmark）
+    pub fn new(delta_time: f64) -> Self {
+        Self {
+            delta_time,
+            real_delta_time: delta_time,
+            time_scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+pub struct FClock {
+    last_instant: Option<Instant>,
+    time_scale: f64,
+    paused: bool,
+    // 暂停时单步执行的次数，每次前进 step_delta_time
+    pending_steps: u32,
+    step_delta_time: f64,
+}
+
+impl FClock {
+    pub fn new() -> Self {
+        Self {
+            last_instant: None,
+            time_scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+            step_delta_time: DEFAULT_FIXED_DELTA_TIME,
+        }
+    }
+
+    pub fn get_time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) -> Result<(), String> {
+        if !(time_scale >= 0.0 && time_scale.is_finite()) {
+            return Err(format!("invalid time scale {}", time_scale));
+        }
+        self.time_scale = time_scale;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            self.pending_steps = 0;
+        }
+    }
+
+    // 暂停时下一帧前进一个步长，不受 time scale 影响。没有暂停时不起作用
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub fn set_step_delta_time(&mut self, step_delta_time: f64) {
+        self.step_delta_time = step_delta_time;
+    }
+
+    // 用距离上次调用的真实时间前进，第一次调用时是 0
+    pub fn advance_real_time(&mut self) -> FFrameTime {
+        let now = Instant::now();
+        let real_delta_time = self
+            .last_instant
+            .map(|last_instant| (now - last_instant).as_secs_f64())
+            .unwrap_or(0.0);
+        self.last_instant = Some(now);
+        self.advance(real_delta_time.min(MAX_REAL_DELTA_TIME))
+    }
+
+    pub fn advance(&mut self, real_delta_time: f64) -> FFrameTime {
+        let real_delta_time = real_delta_time.max(0.0);
+        let delta_time = if !self.paused {
+            real_delta_time * self.time_scale
+        } else if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            self.step_delta_time
+        } else {
+            0.0
+        };
+        FFrameTime {
+            delta_time,
+            real_delta_time,
+            time_scale: self.time_scale,
+            paused: self.paused,
+        }
+    }
+}
+
+pub struct FFixedTimestep {
+    fixed_delta_time: f64,
+    accumulator: f64,
+}
+
+impl FFixedTimestep {
+    pub fn new(fixed_delta_time: f64) -> Self {
+        Self {
+            fixed_delta_time,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn get_fixed_delta_time(&self) -> f64 {
+        self.fixed_delta_time
+    }
+
+    pub fn set_fixed_delta_time(&mut self, fixed_delta_time: f64) -> Result<(), String> {
+        if !(fixed_delta_time > 0.0 && fixed_delta_time.is_finite()) {
+            return Err(format!("invalid fixed delta time {}", fixed_delta_time));
+        }
+        self.fixed_delta_time = fixed_delta_time;
+        Ok(())
+    }
+
+    // 返回这一帧要执行的固定步长次数
+    pub fn advance(&mut self, delta_time: f64) -> u32 {
+        self.accumulator += delta_time;
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_delta_time {
+            self.accumulator -= self.fixed_delta_time;
+            steps += 1;
+            if steps == MAX_FIXED_STEPS {
+                self.accumulator = self.accumulator.min(self.fixed_delta_time);
+                break;
+            }
+        }
+        steps
+    }
+
+    // 剩下不足一个步长的时间占步长的比例，渲染时在上一步和这一步的状态之间插值
+    pub fn get_alpha(&self) -> f64 {
+        (self.accumulator / self.fixed_delta_time).min(1.0)
+    }
+}
+
+// space 的资源，每帧开始时更新
+#[derive(Clone, Debug, PartialEq)]
+pub struct FTime {
+    pub delta_time: f64,
+    pub real_delta_time: f64,
+    pub time_scale: f64,
+    pub paused: bool,
+    // 累计的 delta time
+    pub elapsed_time: f64,
+    pub frame_count: u64,
+    pub fixed_delta_time: f64,
+    // 这一帧 fixed_tick 执行的次数
+    pub fixed_steps: u32,
+    pub alpha: f64,
+}
+
+impl Default for FTime {
+    fn default() -> Self {
+        Self {
+            delta_time: 0.0,
+            real_delta_time: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            elapsed_time: 0.0,
+            frame_count: 0,
+            fixed_delta_time: DEFAULT_FIXED_DELTA_TIME,
+            fixed_steps: 0,
+            alpha: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn clock_scales_and_pauses() {
+        let mut clock = FClock::new();
+        assert_eq!(clock.advance(0.1), FFrameTime::new(0.1));
+
+        clock.set_time_scale(2.0).unwrap();
+        let frame_time = clock.advance(0.1);
+        assert_near(frame_time.delta_time, 0.2);
+        assert_eq!(frame_time.real_delta_time, 0.1);
+        assert_eq!(frame_time.time_scale, 2.0);
+        assert!(clock.set_time_scale(-1.0).is_err());
+        assert!(clock.set_time_scale(f64::NAN).is_err());
+        assert_eq!(clock.get_time_scale(), 2.0);
+        assert_eq!(clock.advance(-1.0).delta_time, 0.0);
+
+        clock.set_paused(true);
+        let frame_time = clock.advance(0.1);
+        assert_eq!(frame_time.delta_time, 0.0);
+        assert!(frame_time.paused);
+    }
+
+    #[test]
+    fn clock_steps_while_paused() {
+        let mut clock = FClock::new();
+        clock.set_time_scale(2.0).unwrap();
+        // 没有暂停时 step 不起作用
+        clock.step();
+        assert_near(clock.advance(0.1).delta_time, 0.2);
+
+        clock.set_paused(true);
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(0.1).delta_time, DEFAULT_FIXED_DELTA_TIME);
+        clock.set_step_delta_time(0.5);
+        assert_eq!(clock.advance(0.1).delta_time, 0.5);
+        assert_eq!(clock.advance(0.1).delta_time, 0.0);
+
+        // 取消暂停时丢掉还没执行的单步
+        clock.step();
+        clock.set_paused(false);
+        clock.set_paused(true);
+        assert_eq!(clock.advance(0.1).delta_time, 0.0);
+    }
+
+    #[test]
+    fn fixed_timestep_accumulates_and_caps_steps() {
+        let mut timestep = FFixedTimestep::new(0.1);
+        assert_eq!(timestep.advance(0.05), 0);
+        assert_near(timestep.get_alpha(), 0.5);
+        assert_eq!(timestep.advance(0.2), 2);
+        assert_near(timestep.get_alpha(), 0.5);
+
+        // 追不上时最多执行 MAX_FIXED_STEPS 次，只留下一个步长
+        assert_eq!(timestep.advance(10.0), MAX_FIXED_STEPS);
+        assert!(timestep.get_alpha() <= 1.0);
+        assert_eq!(timestep.advance(0.0), 1);
+        assert_eq!(timestep.advance(0.0), 0);
+
+        assert!(timestep.set_fixed_delta_time(0.0).is_err());
+        assert!(timestep.set_fixed_delta_time(f64::INFINITY).is_err());
+        assert_eq!(timestep.get_fixed_delta_time(), 0.1);
+        timestep.set_fixed_delta_time(0.5).unwrap();
+        assert_eq!(timestep.advance(1.0), 2);
+    }
+}
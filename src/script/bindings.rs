@@ -0,0 +1,85 @@
+// 引擎暴露给脚本的接口
+// 脚本函数都是无状态的自由函数，所以当前的 HContext 只能放在 thread local 里
+
+use std::cell::RefCell;
+
+use hoo_meta::*;
+use hoo_meta_macros::*;
+use hoo_object::RcObject;
+
+use crate::object::{
+    components::{
+        register_script_component, FScriptFieldDecls, HCameraComponent, HLightComponent,
+        HScriptComponent, HStaticModelComponent, HTransformComponent,
+    },
+    context::HContext,
+    objects::{HCamera, HLight, HMaterial, HStaticMesh, HStaticModel},
+    space::HSpace,
+};
+
+thread_local! {
+    static SCRIPT_CONTEXT: RefCell<Option<RcObject<HContext>>> = RefCell::new(None);
+}
+
+pub fn set_script_context(context: Option<RcObject<HContext>>) {
+    SCRIPT_CONTEXT.with(|c| *c.borrow_mut() = context);
+}
+
+pub fn script_context() -> RcObject<HContext> {
+    SCRIPT_CONTEXT.with(|c| {
+        c.borrow()
+            .as_ref()
+            .expect("script context is not set")
+            .clone()
+    })
+}
+
+#[js_function]
+fn log(message: String) {
+    println!("{}", message);
+}
+
+#[js_function]
+fn get_context() -> RcObject<HContext> {
+    script_context()
+}
+
+// 返回分配的 component id
+#[js_function]
+fn register_component(type_name: String, fields: FScriptFieldDecls) -> Result<u32, String> {
+    let component_id = register_script_component(&type_name, fields.0)?;
+    // 热重载时字段可能变了
+    if let Some(context) = SCRIPT_CONTEXT.with(|c| c.borrow().clone()) {
+        context.borrow().migrate_script_components(&type_name);
+    }
+    Ok(component_id)
+}
+
+#[js_function]
+fn tick(delta_time: f64) {
+    script_context().borrow_mut().tick(delta_time);
+}
+
+pub fn register_engine_bindings(context_builder: &mut HooMetaContextBuilder) {
+    context_builder.build_module("hoo", |module_builder| {
+        module_add_function!(module_builder, log);
+        module_add_function!(module_builder, get_context);
+        module_add_function!(module_builder, register_component);
+        module_add_function!(module_builder, tick);
+
+        module_add_class!(module_builder, HContext);
+        module_add_class!(module_builder, HSpace);
+
+        module_add_class!(module_builder, HTransformComponent);
+        module_add_class!(module_builder, HLightComponent);
+        module_add_class!(module_builder, HCameraComponent);
+        module_add_class!(module_builder, HStaticModelComponent);
+        module_add_class!(module_builder, HScriptComponent);
+
+        module_add_class!(module_builder, HCamera);
+        module_add_class!(module_builder, HLight);
+        module_add_class!(module_builder, HStaticModel);
+        module_add_class!(module_builder, HStaticMesh);
+        module_add_class!(module_builder, HMaterial);
+    });
+}
@@ -0,0 +1,82 @@
+// 给带窗口的引擎用的 inspector：HooEngine::next_frame 每帧 poll 一次。
+// 帧循环里没有现成的 scope，和 HScriptSystem 一样记下 isolate 重新进入
+
+use std::rc::Weak;
+
+use hoo_meta::{FInspectorOptions, HooInspector, HooMetaContext};
+
+use crate::object::systems::get_isolate_alive;
+
+pub struct FScriptInspector {
+    // isolate 销毁后不能再析构 V8Inspector，只能 leak，所以是 Option
+    inspector: Option<HooInspector>,
+    isolate: *mut v8::Isolate,
+    isolate_alive: Weak<()>,
+    context: v8::Global<v8::Context>,
+}
+
+impl FScriptInspector {
+    pub fn new(
+        meta_context: &mut HooMetaContext,
+        options: FInspectorOptions,
+    ) -> std::io::Result<Self> {
+        let context = meta_context.get_context();
+        let inspector = HooInspector::new(meta_context.scope_mut(), context, options)?;
+
+        let scope = meta_context.scope_mut();
+        let context = v8::Global::new(scope, context);
+        let isolate: &mut v8::Isolate = scope;
+        let isolate_alive = get_isolate_alive(isolate);
+
+        Ok(Self {
+            inspector: Some(inspector),
+            isolate: isolate as *mut v8::Isolate,
+            isolate_alive,
+            context,
+        })
+    }
+
+    pub fn get_inspector(&self) -> Option<&HooInspector> {
+        self.inspector.as_ref()
+    }
+
+    pub fn get_inspector_mut(&mut self) -> Option<&mut HooInspector> {
+        self.inspector.as_mut()
+    }
+
+    // isolate 已经销毁时返回 false，之后不用再 poll
+    pub fn poll(&mut self) -> bool {
+        if self.isolate_alive.upgrade().is_none() {
+            self.leak_inspector();
+            return false;
+        }
+        let inspector = match self.inspector.as_mut() {
+            Some(inspector) => inspector,
+            None => return false,
+        };
+
+        let isolate = unsafe { &mut *self.isolate };
+        let scope = &mut unsafe { v8::CallbackScope::new(isolate) };
+        let scope = &mut v8::HandleScope::new(scope);
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        inspector.poll(scope);
+        true
+    }
+
+    // 服务线程和 v8 无关，先停下来释放端口
+    fn leak_inspector(&mut self) {
+        if let Some(mut inspector) = self.inspector.take() {
+            inspector.stop_server();
+            std::mem::forget(inspector);
+        }
+    }
+}
+
+impl Drop for FScriptInspector {
+    fn drop(&mut self) {
+        if self.isolate_alive.upgrade().is_none() {
+            self.leak_inspector();
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod bindings;
+mod inspector;
+mod runner;
+
+pub use bindings::*;
+pub use inspector::*;
+pub use runner::*;
@@ -0,0 +1,242 @@
+// 不需要窗口和 GPU 的脚本运行环境，用于跑 gameplay 脚本和脚本单元测试
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use hoo_meta::{HooMetaContext, HooMetaSnapshot, JsRuntimeError};
+use hoo_object::RcObject;
+
+use crate::object::context::HContext;
+
+use super::{register_engine_bindings, set_script_context};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EScriptKind {
+    Script,
+    Module,
+}
+
+impl EScriptKind {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("mjs") => EScriptKind::Module,
+            _ => EScriptKind::Script,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FScriptTestReport {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl FScriptTestReport {
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+// 建立 isolate 和注册了引擎接口的 context，在其中执行 f
+// 脚本看到的 HContext 是 headless 的：没有 renderer，也没有 demo space
+pub fn with_headless_script_context<R, F>(f: F) -> R
+where
+    F: FnOnce(&mut HooMetaContext) -> R,
+{
+    with_headless_script_context_impl(None, f)
+}
+
+// 和 with_headless_script_context 一样，但 isolate 从 snapshot 启动
+pub fn with_headless_script_context_from_snapshot<R, F>(snapshot: &HooMetaSnapshot, f: F) -> R
+where
+    F: FnOnce(&mut HooMetaContext) -> R,
+{
+    with_headless_script_context_impl(Some(snapshot), f)
+}
+
+fn with_headless_script_context_impl<R, F>(snapshot: Option<&HooMetaSnapshot>, f: F) -> R
+where
+    F: FnOnce(&mut HooMetaContext) -> R,
+{
+    hoo_meta::initialize("");
+
+    set_script_context(Some(RcObject::new(HContext::new())));
+
+    let isolate = &mut match snapshot {
+        Some(snapshot) => snapshot.create_isolate(),
+        None => v8::Isolate::new(v8::CreateParams::default()),
+    };
+    let mut global_scope = v8::HandleScope::new(isolate);
+    let mut meta_context = match snapshot {
+        Some(_) => hoo_meta::build_context_from_snapshot(&mut global_scope),
+        None => hoo_meta::build_context(&mut global_scope, |context_builder| {
+            register_engine_bindings(context_builder);
+        }),
+    };
+    let result = f(&mut meta_context);
+
+    // HScriptSystem 持有 isolate 里的对象，HContext 必须先于 isolate 销毁
+    set_script_context(None);
+    result
+}
+
+// 引擎接口 + prelude 脚本。prelude 里不能创建引擎对象，见 HooMetaSnapshot
+pub fn create_engine_snapshot(
+    prelude_scripts: &[(&str, &str)],
+) -> Result<HooMetaSnapshot, JsRuntimeError> {
+    hoo_meta::initialize("");
+    HooMetaSnapshot::create(register_engine_bindings, prelude_scripts)
+}
+
+// 恢复 create_engine_snapshot 生成后保存下来的 blob。blob 必须由同一个版本的引擎生成
+pub fn load_engine_snapshot(blob: Vec<u8>) -> Result<HooMetaSnapshot, JsRuntimeError> {
+    hoo_meta::initialize("");
+    HooMetaSnapshot::from_blob(blob, register_engine_bindings)
+}
+
+pub struct FStartupTimings {
+    pub iterations: u32,
+    pub snapshot_creation: Duration,
+    // 以下都是平均每次启动的耗时
+    pub without_snapshot: Duration,
+    pub with_snapshot: Duration,
+}
+
+// 分别用两种方式启动 iterations 次，启动后跑一行脚本确保 context 可用
+pub fn measure_startup(iterations: u32) -> Result<FStartupTimings, JsRuntimeError> {
+    let iterations = iterations.max(1);
+
+    let begin = Instant::now();
+    let snapshot = create_engine_snapshot(&[])?;
+    let snapshot_creation = begin.elapsed();
+
+    let begin = Instant::now();
+    for _ in 0..iterations {
+        with_headless_script_context(|meta_context| {
+            meta_context.run_script("typeof hoo", "<startup>").map(|_| ())
+        })?;
+    }
+    let without_snapshot = begin.elapsed() / iterations;
+
+    let begin = Instant::now();
+    for _ in 0..iterations {
+        with_headless_script_context_from_snapshot(&snapshot, |meta_context| {
+            meta_context.run_script("typeof hoo", "<startup>").map(|_| ())
+        })?;
+    }
+    let with_snapshot = begin.elapsed() / iterations;
+
+    Ok(FStartupTimings {
+        iterations,
+        snapshot_creation,
+        without_snapshot,
+        with_snapshot,
+    })
+}
+
+pub fn run_script_file(
+    meta_context: &mut HooMetaContext,
+    path: &Path,
+    kind: EScriptKind,
+) -> Result<(), JsRuntimeError> {
+    let source = std::fs::read_to_string(path).map_err(|err| {
+        JsRuntimeError::new(&format!("cannot read {}: {}", path.display(), err))
+    })?;
+    let resource_name = hoo_meta::script_url_from_path(path);
+
+    match kind {
+        EScriptKind::Script => meta_context.run_script(&source, &resource_name).map(|_| ()),
+        EScriptKind::Module => meta_context.run_module(&source, &resource_name).map(|_| ()),
+    }
+}
+
+pub fn run_repl(meta_context: &mut HooMetaContext) {
+    let stdin = std::io::stdin();
+    let mut line_number = 0;
+
+    loop {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        line_number += 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match meta_context.run_script(&line, &format!("<repl:{}>", line_number)) {
+            Ok(value) => {
+                let scope = meta_context.scope_mut();
+                println!("{}", value.to_rust_string_lossy(scope));
+            }
+            Err(err) => eprintln!("Uncaught {}", err),
+        }
+    }
+}
+
+// 以 module 方式加载 path，依次调用所有名字以 test 开头的 export 函数
+pub fn run_script_tests(meta_context: &mut HooMetaContext, path: &Path) -> FScriptTestReport {
+    let mut report = FScriptTestReport::default();
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("cannot read {}: {}", path.display(), err);
+            report.failed += 1;
+            return report;
+        }
+    };
+
+    let resource_name = hoo_meta::script_url_from_path(path);
+    let namespace = match meta_context.run_module(&source, &resource_name) {
+        Ok(namespace) => namespace,
+        Err(err) => {
+            eprintln!("failed to load {}:\n{}", path.display(), err);
+            report.failed += 1;
+            return report;
+        }
+    };
+
+    let tests = {
+        let scope = meta_context.scope_mut();
+        let names = namespace
+            .get_own_property_names(scope, v8::GetPropertyNamesArgs::default())
+            .unwrap();
+
+        let mut tests = vec![];
+        for i in 0..names.length() {
+            let key = names.get_index(scope, i).unwrap();
+            let name = key.to_rust_string_lossy(scope);
+            if !name.starts_with("test") {
+                continue;
+            }
+            let value = namespace.get(scope, key).unwrap();
+            if let Ok(function) = v8::Local::<v8::Function>::try_from(value) {
+                tests.push((name, function));
+            }
+        }
+        tests
+    };
+
+    for (name, function) in tests {
+        match meta_context.call_function(function, &[]) {
+            Ok(_) => {
+                println!("test {}::{} ... ok", path.display(), name);
+                report.passed += 1;
+            }
+            Err(err) => {
+                println!("test {}::{} ... FAILED", path.display(), name);
+                eprintln!("{}", err);
+                report.failed += 1;
+            }
+        }
+    }
+
+    report
+}
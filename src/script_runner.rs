@@ -0,0 +1,259 @@
+use std::path::{Path, PathBuf};
+
+use hoo_engine::*;
+
+fn print_usage() {
+    eprintln!("usage:");
+    eprintln!("    hoo-script-runner                      start a REPL");
+    eprintln!("    hoo-script-runner [--module] <file>    run a script (.mjs files run as modules)");
+    eprintln!("    hoo-script-runner --test <file>...     run exported test* functions of modules");
+    eprintln!("    hoo-script-runner --measure-startup    compare startup time with and without snapshot");
+    eprintln!("options:");
+    eprintln!("    --snapshot[=file]                      boot the script context from a startup snapshot");
+    eprintln!("                                           cached in <file> (default: next to the executable)");
+    eprintln!("    --inspect[=port]                       accept DevTools connections (default port 9229)");
+    eprintln!("    --inspect-brk[=port]                   like --inspect, but wait for a debugger and break on start");
+}
+
+struct FInspectArgs {
+    options: hoo_meta::FInspectorOptions,
+    break_on_start: bool,
+}
+
+fn parse_inspect_arg(arg: &str) -> Option<FInspectArgs> {
+    let (flag, port) = match arg.split_once('=') {
+        Some((flag, port)) => (flag, Some(port.parse::<u16>().ok()?)),
+        None => (arg, None),
+    };
+    let break_on_start = match flag {
+        "--inspect" => false,
+        "--inspect-brk" => true,
+        _ => return None,
+    };
+
+    let mut options = hoo_meta::FInspectorOptions::default();
+    if let Some(port) = port {
+        options.port = port;
+    }
+    Some(FInspectArgs {
+        options,
+        break_on_start,
+    })
+}
+
+fn start_inspector(
+    meta_context: &mut hoo_meta::HooMetaContext,
+    inspect: &FInspectArgs,
+) -> Option<hoo_meta::HooInspector> {
+    let context = meta_context.get_context();
+    let mut inspector =
+        match hoo_meta::HooInspector::new(meta_context.scope_mut(), context, inspect.options.clone()) {
+            Ok(inspector) => inspector,
+            Err(err) => {
+                eprintln!("failed to start inspector: {}", err);
+                return None;
+            }
+        };
+
+    eprintln!("Debugger listening on {}", inspector.get_websocket_url());
+    eprintln!("Open {} in Chrome to debug", inspector.get_devtools_url());
+    if inspect.break_on_start {
+        eprintln!("Waiting for the debugger to attach...");
+        inspector.wait_for_debugger();
+    }
+    Some(inspector)
+}
+
+fn run_in_context(
+    meta_context: &mut hoo_meta::HooMetaContext,
+    test_mode: bool,
+    force_module: bool,
+    files: &[PathBuf],
+    inspect: Option<&FInspectArgs>,
+) -> i32 {
+    // 必须先于 meta_context 销毁
+    let mut inspector = match inspect {
+        Some(inspect) => match start_inspector(meta_context, inspect) {
+            Some(inspector) => Some(inspector),
+            None => return 1,
+        },
+        None => None,
+    };
+
+    if test_mode {
+        if files.is_empty() {
+            print_usage();
+            return 2;
+        }
+
+        let mut report = FScriptTestReport::default();
+        for file in files.iter() {
+            let file_report = run_script_tests(meta_context, file);
+            report.passed += file_report.passed;
+            report.failed += file_report.failed;
+
+            if let Some(inspector) = inspector.as_mut() {
+                inspector.poll(meta_context.scope_mut());
+            }
+        }
+
+        println!(
+            "\ntest result: {}. {} passed; {} failed",
+            if report.success() { "ok" } else { "FAILED" },
+            report.passed,
+            report.failed
+        );
+        return if report.success() { 0 } else { 1 };
+    }
+
+    if files.is_empty() {
+        run_repl(meta_context);
+        return 0;
+    }
+
+    for file in files.iter() {
+        let kind = if force_module {
+            EScriptKind::Module
+        } else {
+            EScriptKind::from_path(file)
+        };
+        if let Err(err) = run_script_file(meta_context, file, kind) {
+            eprintln!("Uncaught {}", err);
+            return 1;
+        }
+
+        if let Some(inspector) = inspector.as_mut() {
+            inspector.poll(meta_context.scope_mut());
+        }
+    }
+    0
+}
+
+// 写在缓存文件开头。可执行文件变了（重新编译、升级 v8）之后旧的 blob 不能用
+fn snapshot_stamp() -> String {
+    let exe_modified = std::env::current_exe()
+        .and_then(|exe| exe.metadata())
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    format!(
+        "hoo-snapshot {} {} {:?}\n",
+        env!("CARGO_PKG_VERSION"),
+        v8::V8::get_version(),
+        exe_modified
+    )
+}
+
+fn default_snapshot_path() -> PathBuf {
+    std::env::current_exe()
+        .map(|exe| exe.with_extension("snapshot"))
+        .unwrap_or_else(|_| std::env::temp_dir().join("hoo-script-runner.snapshot"))
+}
+
+// 缓存文件不存在或者过期时重新生成并写回
+fn load_or_create_snapshot(path: &Path) -> Result<hoo_meta::HooMetaSnapshot, String> {
+    let stamp = snapshot_stamp();
+    if let Ok(data) = std::fs::read(path) {
+        if let Some(blob) = data.strip_prefix(stamp.as_bytes()) {
+            return load_engine_snapshot(blob.to_vec()).map_err(|err| err.to_string());
+        }
+    }
+
+    let snapshot = create_engine_snapshot(&[]).map_err(|err| err.to_string())?;
+    let mut data = stamp.into_bytes();
+    data.extend_from_slice(snapshot.get_blob());
+    // 先写临时文件再改名，同时运行的另一个进程不会读到写了一半的文件
+    let temp_path = path.with_extension(format!("snapshot.{}", std::process::id()));
+    let saved = std::fs::write(&temp_path, data).and_then(|_| std::fs::rename(&temp_path, path));
+    if let Err(err) = saved {
+        let _ = std::fs::remove_file(&temp_path);
+        eprintln!("failed to save snapshot to {}: {}", path.display(), err);
+    }
+    Ok(snapshot)
+}
+
+fn print_startup_timings() -> i32 {
+    match measure_startup(20) {
+        Ok(timings) => {
+            println!("snapshot creation: {:?}", timings.snapshot_creation);
+            println!(
+                "startup without snapshot: {:?} (average of {})",
+                timings.without_snapshot, timings.iterations
+            );
+            println!(
+                "startup with snapshot: {:?} (average of {})",
+                timings.with_snapshot, timings.iterations
+            );
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+pub fn run() -> i32 {
+    env_logger::init();
+
+    let mut test_mode = false;
+    let mut force_module = false;
+    let mut snapshot_path: Option<PathBuf> = None;
+    let mut measure_startup_mode = false;
+    let mut inspect: Option<FInspectArgs> = None;
+    let mut files: Vec<PathBuf> = vec![];
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--test" => test_mode = true,
+            "--module" => force_module = true,
+            "--snapshot" => snapshot_path = Some(default_snapshot_path()),
+            "--measure-startup" => measure_startup_mode = true,
+            "--help" | "-h" => {
+                print_usage();
+                return 0;
+            }
+            _ if arg.starts_with("--snapshot=") => {
+                snapshot_path = Some(arg["--snapshot=".len()..].into())
+            }
+            _ if arg.starts_with("--inspect") => match parse_inspect_arg(&arg) {
+                Some(inspect_args) => inspect = Some(inspect_args),
+                None => {
+                    eprintln!("invalid option: {}", arg);
+                    print_usage();
+                    return 2;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option: {}", arg);
+                print_usage();
+                return 2;
+            }
+            _ => files.push(arg.into()),
+        }
+    }
+
+    if measure_startup_mode {
+        return print_startup_timings();
+    }
+
+    if let Some(snapshot_path) = snapshot_path {
+        let snapshot = match load_or_create_snapshot(&snapshot_path) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                eprintln!("failed to load snapshot: {}", err);
+                return 1;
+            }
+        };
+        with_headless_script_context_from_snapshot(&snapshot, |meta_context| {
+            run_in_context(meta_context, test_mode, force_module, &files, inspect.as_ref())
+        })
+    } else {
+        with_headless_script_context(|meta_context| {
+            run_in_context(meta_context, test_mode, force_module, &files, inspect.as_ref())
+        })
+    }
+}
+
+fn main() {
+    std::process::exit(run());
+}